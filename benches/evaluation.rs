@@ -0,0 +1,91 @@
+use alpha_encoding_ast::gene::{ColumnContext, Context, Expression, Program};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::collections::HashMap;
+
+const ROWS: usize = 10_000;
+const FORMULA: &str = "sqrt(close / open) - 2.5 * volume ^ 0.5 + (high - low) / (close + 1e-9)";
+
+struct Columns {
+    names: [&'static str; 5],
+    values: Vec<Vec<f64>>,
+}
+
+fn columns() -> Columns {
+    let names = ["open", "high", "low", "close", "volume"];
+    let values = names
+        .iter()
+        .enumerate()
+        .map(|(offset, _)| {
+            (0..ROWS)
+                .map(|i| 100.0 + ((i + offset) as f64 * 0.37).sin() * 5.0)
+                .collect()
+        })
+        .collect();
+    Columns { names, values }
+}
+
+fn evaluation(c: &mut Criterion) {
+    let expression: Expression = FORMULA.parse().unwrap();
+    let program = Program::compile(&expression).unwrap();
+    let data = columns();
+
+    let mut context = ColumnContext::new(ROWS);
+    for (name, values) in data.names.iter().zip(&data.values) {
+        context.push_column(name, values).unwrap();
+    }
+    let slot_columns: Vec<&[f64]> = program
+        .slots()
+        .iter()
+        .map(|slot| {
+            let index = data.names.iter().position(|name| name == slot).unwrap();
+            data.values[index].as_slice()
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("evaluate");
+
+    group.bench_function(BenchmarkId::new("tree_per_row_hashmap", ROWS), |b| {
+        b.iter(|| {
+            (0..ROWS)
+                .map(|row| {
+                    let variables: HashMap<String, f64> = data
+                        .names
+                        .iter()
+                        .zip(&data.values)
+                        .map(|(name, values)| (name.to_string(), values[row]))
+                        .collect();
+                    expression
+                        .evaluate(&Context::with_variables(variables))
+                        .unwrap()
+                })
+                .collect::<Vec<f64>>()
+        })
+    });
+
+    group.bench_function(BenchmarkId::new("tree_columns", ROWS), |b| {
+        b.iter(|| expression.evaluate_columns(black_box(&context)).unwrap())
+    });
+
+    group.bench_function(BenchmarkId::new("bytecode_per_row", ROWS), |b| {
+        let mut row_values = vec![0.0; slot_columns.len()];
+        b.iter(|| {
+            (0..ROWS)
+                .map(|row| {
+                    for (value, column) in row_values.iter_mut().zip(&slot_columns) {
+                        *value = column[row];
+                    }
+                    program.run(black_box(&row_values)).unwrap()
+                })
+                .collect::<Vec<f64>>()
+        })
+    });
+
+    group.bench_function(BenchmarkId::new("bytecode_columns", ROWS), |b| {
+        b.iter(|| program.run_slices(black_box(&slot_columns), ROWS).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, evaluation);
+criterion_main!(benches);
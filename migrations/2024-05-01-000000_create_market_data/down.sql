@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS ticks;
+DROP TABLE IF EXISTS book_snapshots;
+DROP TABLE IF EXISTS bars;
@@ -0,0 +1,32 @@
+-- Timestamps are stored as the BIGINT the datasets key on. IF NOT EXISTS keeps the migration safe
+-- to re-run through data::db::run_migrations.
+CREATE TABLE IF NOT EXISTS bars (
+    granularity TEXT NOT NULL,
+    ts BIGINT NOT NULL,
+    open DOUBLE PRECISION NOT NULL,
+    high DOUBLE PRECISION NOT NULL,
+    low DOUBLE PRECISION NOT NULL,
+    close DOUBLE PRECISION NOT NULL,
+    volume DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (granularity, ts)
+);
+
+CREATE TABLE IF NOT EXISTS book_snapshots (
+    symbol TEXT NOT NULL,
+    ts BIGINT NOT NULL,
+    depth INTEGER NOT NULL,
+    bids JSONB NOT NULL,
+    asks JSONB NOT NULL,
+    PRIMARY KEY (symbol, ts)
+);
+
+CREATE TABLE IF NOT EXISTS ticks (
+    symbol TEXT NOT NULL,
+    side TEXT NOT NULL CHECK (side IN ('buy', 'sell')),
+    px DOUBLE PRECISION NOT NULL,
+    qty DOUBLE PRECISION NOT NULL,
+    local_ids BIGINT NOT NULL,
+    server_id BIGINT NOT NULL,
+    tx_ts BIGINT NOT NULL,
+    PRIMARY KEY (symbol, tx_ts, server_id)
+);
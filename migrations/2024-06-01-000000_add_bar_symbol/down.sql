@@ -0,0 +1,3 @@
+ALTER TABLE bars DROP CONSTRAINT IF EXISTS bars_pkey;
+ALTER TABLE bars DROP COLUMN IF EXISTS symbol;
+ALTER TABLE bars ADD PRIMARY KEY (granularity, ts);
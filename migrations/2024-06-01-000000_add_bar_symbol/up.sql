@@ -0,0 +1,14 @@
+-- Bars of different symbols share a granularity and timestamps, so the symbol is part of the key.
+-- Rows stored before this migration get an empty symbol. Only runs once, re-running is a no-op.
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = 'bars' AND column_name = 'symbol'
+    ) THEN
+        ALTER TABLE bars ADD COLUMN symbol TEXT NOT NULL DEFAULT '';
+        ALTER TABLE bars ALTER COLUMN symbol DROP DEFAULT;
+        ALTER TABLE bars DROP CONSTRAINT bars_pkey;
+        ALTER TABLE bars ADD PRIMARY KEY (symbol, granularity, ts);
+    END IF;
+END $$;
@@ -0,0 +1,404 @@
+use crate::data::{
+    stream::io_error,
+    types::{NormalizedBook, Quotes},
+};
+use csv::{Reader, StringRecord, StringRecordsIntoIter, Writer};
+use std::{fmt::Display, io, str::FromStr};
+
+//NOTE: Flat CSV layouts for NormalizedBook, serde can't read the nested bids/asks from a csv row.
+// Levels are numbered per side from 0 (best price). Columns are matched by header name, their
+// order is free.
+//
+// Wide, one row per snapshot:
+//   symbol,ts,depth,bid_px_0,bid_qty_0,bid_count_0,...,ask_px_0,ask_qty_0,ask_count_0,...
+// The header fixes how many levels per side the file holds. The first `depth` levels of a row are
+// read, a thinner book leaves px/qty/count of its missing levels empty. depth may not exceed the
+// levels of either side.
+//
+// Long, one row per level:
+//   symbol,ts,depth,side,level,px,qty,count
+// side is `bid` or `ask`. The rows of a snapshot are consecutive and the levels of a side are
+// listed in order from 0, levels at or past depth are skipped. A snapshot without quotes has no
+// rows, so it is not written.
+//
+// In both layouts depth is optional (column or cell) and defaults to the levels of the deeper
+// side, count columns are optional and an empty count reads as None. Writers leave out quotes past
+// a book's depth and only add count columns when some quote has a count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BookCsvFormat {
+    #[default]
+    Wide,
+    Long,
+}
+
+const SIDES: [&str; 2] = ["bid", "ask"];
+
+// Column indices of one level in the wide layout
+struct LevelColumns {
+    px: usize,
+    qty: usize,
+    count: Option<usize>,
+}
+
+pub(crate) struct WideRecords<R> {
+    records: StringRecordsIntoIter<R>,
+    symbol: usize,
+    ts: usize,
+    depth: Option<usize>,
+    bids: Vec<LevelColumns>,
+    asks: Vec<LevelColumns>,
+}
+
+impl<R: io::Read> WideRecords<R> {
+    pub(crate) fn new(mut reader: Reader<R>) -> io::Result<Self> {
+        let headers = reader.headers().map_err(io_error)?.clone();
+        Ok(WideRecords {
+            symbol: required(&headers, "symbol")?,
+            ts: required(&headers, "ts")?,
+            depth: column(&headers, "depth"),
+            bids: level_columns(&headers, "bid")?,
+            asks: level_columns(&headers, "ask")?,
+            records: reader.into_records(),
+        })
+    }
+
+    fn book(&self, record: &StringRecord) -> io::Result<NormalizedBook> {
+        let depth = match optional(record, self.depth, "depth")? {
+            Some(depth) => depth,
+            None => book_depth(self.bids.len().max(self.asks.len()))?,
+        };
+        Ok(NormalizedBook {
+            symbol: cell(record, self.symbol).to_string(),
+            depth,
+            bids: wide_quotes(record, &self.bids, depth)?,
+            asks: wide_quotes(record, &self.asks, depth)?,
+            ts: parse(record, self.ts, "ts")?,
+        })
+    }
+}
+
+impl<R: io::Read> Iterator for WideRecords<R> {
+    type Item = io::Result<NormalizedBook>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.records.next()?;
+        Some(
+            record
+                .map_err(io_error)
+                .and_then(|record| self.book(&record)),
+        )
+    }
+}
+
+fn level_columns(headers: &StringRecord, side: &str) -> io::Result<Vec<LevelColumns>> {
+    let mut levels = Vec::new();
+    while let Some(px) = column(headers, &format!("{}_px_{}", side, levels.len())) {
+        let level = levels.len();
+        levels.push(LevelColumns {
+            px,
+            qty: required(headers, &format!("{}_qty_{}", side, level))?,
+            count: column(headers, &format!("{}_count_{}", side, level)),
+        });
+    }
+    Ok(levels)
+}
+
+fn wide_quotes(
+    record: &StringRecord,
+    columns: &[LevelColumns],
+    depth: u16,
+) -> io::Result<Vec<Quotes>> {
+    let Some(levels) = columns.get(..usize::from(depth)) else {
+        return Err(invalid(format!(
+            "depth {} on line {} exceeds the {} levels of the file",
+            depth,
+            line(record),
+            columns.len()
+        )));
+    };
+    let mut quotes = Vec::new();
+    for level in levels {
+        if cell(record, level.px).is_empty() {
+            break;
+        }
+        quotes.push(Quotes {
+            level: parse(record, level.px, "px")?,
+            qty: parse(record, level.qty, "qty")?,
+            count: optional(record, level.count, "count")?,
+        });
+    }
+    Ok(quotes)
+}
+
+pub(crate) struct LongRecords<R> {
+    records: StringRecordsIntoIter<R>,
+    symbol: usize,
+    ts: usize,
+    depth: Option<usize>,
+    side: usize,
+    level: usize,
+    px: usize,
+    qty: usize,
+    count: Option<usize>,
+    // First row of the next snapshot, read while looking for the end of the current one
+    pending: Option<StringRecord>,
+}
+
+impl<R: io::Read> LongRecords<R> {
+    pub(crate) fn new(mut reader: Reader<R>) -> io::Result<Self> {
+        let headers = reader.headers().map_err(io_error)?.clone();
+        Ok(LongRecords {
+            symbol: required(&headers, "symbol")?,
+            ts: required(&headers, "ts")?,
+            depth: column(&headers, "depth"),
+            side: required(&headers, "side")?,
+            level: required(&headers, "level")?,
+            px: required(&headers, "px")?,
+            qty: required(&headers, "qty")?,
+            count: column(&headers, "count"),
+            records: reader.into_records(),
+            pending: None,
+        })
+    }
+
+    fn snapshot(&mut self, first: StringRecord) -> io::Result<NormalizedBook> {
+        let depth: Option<u16> = optional(&first, self.depth, "depth")?;
+        let mut book = NormalizedBook {
+            symbol: cell(&first, self.symbol).to_string(),
+            depth: depth.unwrap_or(0),
+            bids: Vec::new(),
+            asks: Vec::new(),
+            ts: parse(&first, self.ts, "ts")?,
+        };
+        // Levels listed so far per side, including the skipped ones
+        let mut listed = [0; 2];
+        let mut record = first;
+        loop {
+            if optional(&record, self.depth, "depth")? != depth {
+                return Err(invalid(format!(
+                    "depth changes within the snapshot on line {}",
+                    line(&record)
+                )));
+            }
+            self.push_level(&record, depth, &mut book, &mut listed)?;
+            record = match self.records.next() {
+                None => break,
+                Some(next) => next.map_err(io_error)?,
+            };
+            let ts: i64 = parse(&record, self.ts, "ts")?;
+            if cell(&record, self.symbol) != book.symbol || ts != book.ts {
+                self.pending = Some(record);
+                break;
+            }
+        }
+        if depth.is_none() {
+            book.depth = book_depth(book.bids.len().max(book.asks.len()))?;
+        }
+        Ok(book)
+    }
+
+    fn push_level(
+        &self,
+        record: &StringRecord,
+        depth: Option<u16>,
+        book: &mut NormalizedBook,
+        listed: &mut [usize; 2],
+    ) -> io::Result<()> {
+        let side = cell(record, self.side);
+        let Some(side_index) = SIDES.iter().position(|name| *name == side) else {
+            return Err(invalid(format!(
+                "invalid side `{}` on line {}",
+                side,
+                line(record)
+            )));
+        };
+        let level: usize = parse(record, self.level, "level")?;
+        if level != listed[side_index] {
+            return Err(invalid(format!(
+                "expected {} level {} on line {}, found {}",
+                side,
+                listed[side_index],
+                line(record),
+                level
+            )));
+        }
+        listed[side_index] += 1;
+        if depth.is_some_and(|depth| level >= usize::from(depth)) {
+            return Ok(());
+        }
+
+        let quotes = if side_index == 0 {
+            &mut book.bids
+        } else {
+            &mut book.asks
+        };
+        quotes.push(Quotes {
+            level: parse(record, self.px, "px")?,
+            qty: parse(record, self.qty, "qty")?,
+            count: optional(record, self.count, "count")?,
+        });
+        Ok(())
+    }
+}
+
+impl<R: io::Read> Iterator for LongRecords<R> {
+    type Item = io::Result<NormalizedBook>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = match self.pending.take() {
+            Some(first) => first,
+            None => match self.records.next()? {
+                Ok(first) => first,
+                Err(e) => return Some(Err(io_error(e))),
+            },
+        };
+        Some(self.snapshot(first))
+    }
+}
+
+pub(crate) fn write_wide<W: io::Write>(writer: W, books: &[NormalizedBook]) -> io::Result<()> {
+    let levels = books
+        .iter()
+        .map(|book| usize::from(book.depth))
+        .max()
+        .unwrap_or(0);
+    let counts = has_counts(books);
+    let mut writer = Writer::from_writer(writer);
+
+    let mut header = vec!["symbol".to_string(), "ts".to_string(), "depth".to_string()];
+    for side in SIDES {
+        for level in 0..levels {
+            header.push(format!("{}_px_{}", side, level));
+            header.push(format!("{}_qty_{}", side, level));
+            if counts {
+                header.push(format!("{}_count_{}", side, level));
+            }
+        }
+    }
+    writer.write_record(&header).map_err(io_error)?;
+
+    for book in books {
+        let mut row = vec![
+            book.symbol.clone(),
+            book.ts.to_string(),
+            book.depth.to_string(),
+        ];
+        for quotes in [&book.bids, &book.asks] {
+            for level in 0..levels {
+                match quotes
+                    .get(level)
+                    .filter(|_| level < usize::from(book.depth))
+                {
+                    Some(quote) => {
+                        row.push(quote.level.to_string());
+                        row.push(quote.qty.to_string());
+                        if counts {
+                            row.push(count_cell(quote));
+                        }
+                    }
+                    None => row.resize(row.len() + if counts { 3 } else { 2 }, String::new()),
+                }
+            }
+        }
+        writer.write_record(&row).map_err(io_error)?;
+    }
+    writer.flush()
+}
+
+pub(crate) fn write_long<W: io::Write>(writer: W, books: &[NormalizedBook]) -> io::Result<()> {
+    let counts = has_counts(books);
+    let mut writer = Writer::from_writer(writer);
+
+    let mut header = vec!["symbol", "ts", "depth", "side", "level", "px", "qty"];
+    if counts {
+        header.push("count");
+    }
+    writer.write_record(&header).map_err(io_error)?;
+
+    for book in books {
+        for (side, quotes) in SIDES.into_iter().zip([&book.bids, &book.asks]) {
+            for (level, quote) in quotes.iter().take(usize::from(book.depth)).enumerate() {
+                let mut row = vec![
+                    book.symbol.clone(),
+                    book.ts.to_string(),
+                    book.depth.to_string(),
+                    side.to_string(),
+                    level.to_string(),
+                    quote.level.to_string(),
+                    quote.qty.to_string(),
+                ];
+                if counts {
+                    row.push(count_cell(quote));
+                }
+                writer.write_record(&row).map_err(io_error)?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+fn book_depth(levels: usize) -> io::Result<u16> {
+    u16::try_from(levels).map_err(|_| invalid(format!("{} levels exceed a book depth", levels)))
+}
+
+fn has_counts(books: &[NormalizedBook]) -> bool {
+    books
+        .iter()
+        .flat_map(|book| book.bids.iter().chain(&book.asks))
+        .any(|quote| quote.count.is_some())
+}
+
+fn count_cell(quote: &Quotes) -> String {
+    quote
+        .count
+        .map_or_else(String::new, |count| count.to_string())
+}
+
+fn column(headers: &StringRecord, name: &str) -> Option<usize> {
+    headers.iter().position(|header| header.trim() == name)
+}
+
+fn required(headers: &StringRecord, name: &str) -> io::Result<usize> {
+    column(headers, name).ok_or_else(|| invalid(format!("missing column `{}`", name)))
+}
+
+fn cell(record: &StringRecord, index: usize) -> &str {
+    record.get(index).unwrap_or("").trim()
+}
+
+fn parse<T>(record: &StringRecord, index: usize, name: &str) -> io::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = cell(record, index);
+    value.parse().map_err(|e| {
+        invalid(format!(
+            "invalid {} `{}` on line {}: {}",
+            name,
+            value,
+            line(record),
+            e
+        ))
+    })
+}
+
+// An absent column or an empty cell is None
+fn optional<T>(record: &StringRecord, index: Option<usize>, name: &str) -> io::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match index {
+        Some(index) if !cell(record, index).is_empty() => parse(record, index, name).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn line(record: &StringRecord) -> u64 {
+    record.position().map_or(0, |position| position.line())
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
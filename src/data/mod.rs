@@ -1 +1,5 @@
+pub mod book_csv;
+pub mod db;
+pub mod schema;
+pub mod stream;
 pub mod types;
@@ -0,0 +1,152 @@
+use crate::traits::Timestamped;
+use csv::{Reader, ReaderBuilder};
+use futures_core::Stream;
+use serde::de::DeserializeOwned;
+use std::{
+    fs::File,
+    io,
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::sync::mpsc;
+
+// Records read ahead of the consumer, bounds the memory a stream holds whatever the file size
+const CHANNEL_RECORDS: usize = 1024;
+
+//NOTE: Records of a CSV file with a header row, read on tokio's blocking pool and handed over
+// through a bounded channel. Records before `first_ts` are skipped and reading stops at the first
+// record past `last_ts`, rows after it are never parsed. The first error (including a missing file
+// or a record older than the one before it) is yielded and ends the stream. Dropping the stream
+// stops the reader. Must be created within a tokio runtime.
+pub struct CsvStream<T> {
+    receiver: mpsc::Receiver<io::Result<T>>,
+}
+
+impl<T> CsvStream<T>
+where
+    T: DeserializeOwned + Timestamped + Send + 'static,
+{
+    // Files have to be in ascending timestamp order (equal timestamps are fine), unsorted files
+    // end in an InvalidData error rather than being truncated
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        CsvStream::window(path, i64::MIN, i64::MAX)
+    }
+
+    // Same ordering requirement as `open`, both bounds are inclusive
+    pub fn window(path: impl Into<PathBuf>, first_ts: i64, last_ts: i64) -> Self {
+        CsvStream::with_records(path, first_ts, last_ts, |reader| {
+            Ok(reader
+                .into_deserialize::<T>()
+                .map(|record| record.map_err(io_error)))
+        })
+    }
+}
+
+impl<T> CsvStream<T>
+where
+    T: Timestamped + Send + 'static,
+{
+    // For layouts serde can't map a row onto, `records` turns the opened reader into the records
+    pub(crate) fn with_records<F, I>(
+        path: impl Into<PathBuf>,
+        first_ts: i64,
+        last_ts: i64,
+        records: F,
+    ) -> Self
+    where
+        F: FnOnce(Reader<File>) -> io::Result<I> + Send + 'static,
+        I: Iterator<Item = io::Result<T>>,
+    {
+        let path = path.into();
+        let (sender, receiver) = mpsc::channel(CHANNEL_RECORDS);
+
+        tokio::task::spawn_blocking(move || {
+            let records = ReaderBuilder::new()
+                .has_headers(true)
+                .from_path(&path)
+                .map_err(io_error)
+                .and_then(records);
+            let records = match records {
+                Ok(records) => records,
+                Err(e) => {
+                    let _ = sender.blocking_send(Err(e));
+                    return;
+                }
+            };
+            let mut previous_ts = i64::MIN;
+            for record in records {
+                let record = match record {
+                    Ok(record) if record.timestamp() < previous_ts => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "record at ts {} follows ts {}, files have to be in ascending timestamp order",
+                            record.timestamp(),
+                            previous_ts
+                        ),
+                    )),
+                    Ok(record) => {
+                        previous_ts = record.timestamp();
+                        if previous_ts < first_ts {
+                            continue;
+                        }
+                        if previous_ts > last_ts {
+                            return;
+                        }
+                        Ok(record)
+                    }
+                    Err(e) => Err(e),
+                };
+                let failed = record.is_err();
+                // The receiver is gone or the stream ends with this error
+                if sender.blocking_send(record).is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        CsvStream { receiver }
+    }
+}
+
+impl<T> CsvStream<T> {
+    pub async fn next(&mut self) -> Option<io::Result<T>> {
+        self.receiver.recv().await
+    }
+
+    // Reads the remaining records, failing on the first error
+    pub async fn try_collect(self) -> io::Result<Vec<T>> {
+        self.try_collect_where(|_| true).await
+    }
+
+    // As `try_collect`, dropping the records `keep` rejects as they arrive
+    pub async fn try_collect_where(mut self, keep: impl Fn(&T) -> bool) -> io::Result<Vec<T>> {
+        let mut records = Vec::new();
+        while let Some(record) = self.next().await {
+            let record = record?;
+            if keep(&record) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+impl<T> Stream for CsvStream<T> {
+    type Item = io::Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+// Keeps the kind of I/O errors (e.g. NotFound), anything the csv crate raises itself is InvalidData
+pub(crate) fn io_error(e: csv::Error) -> io::Error {
+    if !e.is_io_error() {
+        return io::Error::new(io::ErrorKind::InvalidData, e);
+    }
+    match e.into_kind() {
+        csv::ErrorKind::Io(e) => e,
+        kind => io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", kind)),
+    }
+}
@@ -1,18 +1,25 @@
-use crate::traits::{DataUpdate, IODataMethods, LocalDataMethods};
+use crate::data::{
+    book_csv::{write_long, write_wide, BookCsvFormat, LongRecords, WideRecords},
+    db::{
+        inserted, upsert_batches, with_connection, BarRow, BookRow, PgPool, TickRow, WriteReport,
+    },
+    schema::{bars, book_snapshots, ticks},
+    stream::CsvStream,
+};
+use crate::gene::{ColumnContext, PanelContext};
+use crate::traits::{DataUpdate, DbWriteMethods, IODataMethods, LocalDataMethods, Timestamped};
 use async_trait::async_trait;
-use csv::ReaderBuilder;
-use diesel::sql_types::Timestamp;
-use diesel::PgConnection;
-use eyre::Result;
+use diesel::{prelude::*, query_dsl::methods, upsert::excluded};
+use eyre::{eyre, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::de::from_reader;
 use std::{
-    collections::{BTreeMap, VecDeque},
+    collections::{BTreeMap, BTreeSet, VecDeque},
     error::Error,
-    io::{BufReader, ErrorKind},
+    fs::File,
+    io::{BufReader, BufWriter},
     ops::Bound::{Included, Unbounded},
 };
-use tokio::{fs::File, io::AsyncReadExt};
 
 // Timestamp
 type TS = i64;
@@ -28,15 +35,55 @@ pub struct Bar {
     ts: TS,
 }
 
+impl Bar {
+    pub fn new(ts: TS, open: f64, high: f64, low: f64, close: f64, volume: f64) -> Self {
+        Bar {
+            o: open,
+            h: high,
+            l: low,
+            c: close,
+            v: volume,
+            ts,
+        }
+    }
+    pub fn ts(&self) -> TS {
+        self.ts
+    }
+    pub fn open(&self) -> f64 {
+        self.o
+    }
+    pub fn high(&self) -> f64 {
+        self.h
+    }
+    pub fn low(&self) -> f64 {
+        self.l
+    }
+    pub fn close(&self) -> f64 {
+        self.c
+    }
+    pub fn volume(&self) -> f64 {
+        self.v
+    }
+}
+
+impl Timestamped for Bar {
+    fn timestamp(&self) -> TS {
+        self.ts
+    }
+}
+
+// Bars of one symbol
 #[derive(Debug, Clone)]
 pub struct BarDataSet {
+    pub symbol: String,
     pub granularity: BarGranularity,
     pub data: BTreeMap<TS, Bar>,
 }
 
 impl BarDataSet {
-    fn new(granularity: BarGranularity) -> Self {
+    pub fn new(symbol: String, granularity: BarGranularity) -> Self {
         BarDataSet {
+            symbol,
             granularity,
             data: BTreeMap::new(),
         }
@@ -51,14 +98,143 @@ impl BarDataSet {
             .map(|(k, v)| v)
             .collect())
     }
+    pub fn columns(&self) -> BarColumns {
+        let mut columns = BarColumns::with_capacity(self.data.len());
+        for bar in self.data.values() {
+            columns.push_bar(bar);
+        }
+        columns
+    }
+}
+
+//NOTE: Column-major copy of a BarDataSet in timestamp order, for vectorized expression evaluation
+#[derive(Debug, Clone, Default)]
+pub struct BarColumns {
+    pub ts: Vec<TS>,
+    pub open: Vec<f64>,
+    pub high: Vec<f64>,
+    pub low: Vec<f64>,
+    pub close: Vec<f64>,
+    pub volume: Vec<f64>,
+}
+
+impl BarColumns {
+    pub fn with_capacity(capacity: usize) -> Self {
+        BarColumns {
+            ts: Vec::with_capacity(capacity),
+            open: Vec::with_capacity(capacity),
+            high: Vec::with_capacity(capacity),
+            low: Vec::with_capacity(capacity),
+            close: Vec::with_capacity(capacity),
+            volume: Vec::with_capacity(capacity),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.ts.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ts.is_empty()
+    }
+    // Exposes the columns as open, high, low, close and volume
+    pub fn context(&self) -> ColumnContext<'_> {
+        ColumnContext::from(self)
+    }
+    fn push_bar(&mut self, bar: &Bar) {
+        self.ts.push(bar.ts);
+        self.open.push(bar.o);
+        self.high.push(bar.h);
+        self.low.push(bar.l);
+        self.close.push(bar.c);
+        self.volume.push(bar.v);
+    }
+    fn push_missing(&mut self, ts: TS) {
+        self.ts.push(ts);
+        for column in [
+            &mut self.open,
+            &mut self.high,
+            &mut self.low,
+            &mut self.close,
+            &mut self.volume,
+        ] {
+            column.push(f64::NAN);
+        }
+    }
+}
+
+//NOTE: Several BarDataSets of the same granularity aligned on the union of their timestamps, a
+// symbol without a bar at some timestamp gets a row of NaN there
+#[derive(Debug, Clone, Default)]
+pub struct BarPanel {
+    pub ts: Vec<TS>,
+    pub symbols: Vec<String>,
+    pub columns: Vec<BarColumns>,
+}
+
+impl BarPanel {
+    pub fn new(datasets: &[(&str, &BarDataSet)]) -> Result<Self> {
+        if let Some((_, first)) = datasets.first() {
+            if let Some((symbol, _)) = datasets
+                .iter()
+                .find(|(_, set)| set.granularity != first.granularity)
+            {
+                return Err(eyre!(
+                    "{} has a different granularity than {:?}",
+                    symbol,
+                    first.granularity
+                ));
+            }
+        }
+
+        let ts: Vec<TS> = datasets
+            .iter()
+            .flat_map(|(_, set)| set.data.keys().copied())
+            .collect::<BTreeSet<TS>>()
+            .into_iter()
+            .collect();
+
+        let columns = datasets
+            .iter()
+            .map(|(_, set)| {
+                let mut columns = BarColumns::with_capacity(ts.len());
+                for timestamp in &ts {
+                    match set.data.get(timestamp) {
+                        Some(bar) => columns.push_bar(bar),
+                        None => columns.push_missing(*timestamp),
+                    }
+                }
+                columns
+            })
+            .collect();
+
+        Ok(BarPanel {
+            symbols: datasets
+                .iter()
+                .map(|(symbol, _)| symbol.to_string())
+                .collect(),
+            ts,
+            columns,
+        })
+    }
+    pub fn len(&self) -> usize {
+        self.ts.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ts.is_empty()
+    }
+    // One ColumnContext per symbol, groups can be added with PanelContext::push_group
+    pub fn context(&self) -> PanelContext<'_> {
+        PanelContext::from(self)
+    }
 }
 
 impl DataUpdate for BarDataSet {
     type NewData = Vec<Bar>;
 
+    // A new entry replaces the one stored under the same timestamp
     fn update(&mut self, data: Self::NewData) {
-        data.iter()
-            .map(|entry| self.data.insert(entry.ts, entry.clone()));
+        for entry in data {
+            self.data.insert(entry.ts, entry);
+        }
     }
 }
 
@@ -78,6 +254,10 @@ impl LocalDataMethods for BarDataSet {
         first_ts: TS,
         last_ts: TS,
     ) -> Result<Self::Output, Box<dyn Error>> {
+        // BTreeMap::range panics on an inverted range
+        if first_ts > last_ts {
+            return Ok(Vec::new());
+        }
         Ok(self
             .data
             .range(first_ts..=last_ts)
@@ -91,44 +271,17 @@ impl IODataMethods for BarDataSet {
     type Item = Bar;
 
     async fn from_file_full_dataset(&self, path: &str) -> Result<Vec<Self::Item>, std::io::Error> {
-        let mut file = File::open(path).await?;
-        let mut contents = String::new();
-
-        file.read_to_string(&mut contents).await?;
-
-        let mut csv_reader = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(contents.as_bytes());
-
-        let mut bars = Vec::new();
-        for result in csv_reader.deserialize() {
-            let record: Bar = result.map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
-        }
-
-        Ok(bars)
+        CsvStream::open(path).try_collect().await
     }
+    // Records at or after `last_ts`
     async fn from_file_by_ts_lookback(
         &self,
         path: &str,
         last_ts: TS,
     ) -> Result<Vec<Self::Item>, std::io::Error> {
-        let mut file = File::open(path).await?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).await?;
-
-        let mut csv_reader = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(contents.as_bytes());
-
-        let mut bars = Vec::new();
-
-        for result in csv_reader.deserialize() {
-            let record: Bar = result.map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
-            if record.ts >= last_ts {
-                bars.push(record);
-            }
-        }
-        Ok(bars)
+        CsvStream::window(path, last_ts, TS::MAX)
+            .try_collect()
+            .await
     }
     async fn from_file_by_ts_window(
         &self,
@@ -136,39 +289,77 @@ impl IODataMethods for BarDataSet {
         first_ts: TS,
         last_ts: TS,
     ) -> Result<Vec<Self::Item>, std::io::Error> {
-        let mut file = File::open(path).await?;
-        let mut contents = String::new();
-
-        file.read_to_string(&mut contents).await?;
-
-        let mut csv_reader = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(contents.as_bytes());
-
-        let mut bars = Vec::new();
-
-        for result in csv_reader.deserialize() {
-            let record: Bar = result.map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
-            if record.ts >= first_ts && record.ts <= last_ts {
-                bars.push(record);
-            }
-        }
-
-        Ok(bars)
+        CsvStream::window(path, first_ts, last_ts)
+            .try_collect()
+            .await
     }
-    async fn from_db_all_entries(
-        &self,
-        conn: &PgConnection,
-    ) -> Result<Vec<Self::Item>, diesel::result::Error> {
-        todo!();
+    // Bars of this dataset's symbol and granularity
+    async fn from_db_all_entries(&self, pool: &PgPool) -> Result<Vec<Self::Item>> {
+        self.db_ts_window(pool, TS::MIN, TS::MAX).await
     }
     async fn db_ts_window(
         &self,
-        conn: &PgConnection,
+        pool: &PgPool,
         first_ts: TS,
-        last_entry: TS,
-    ) -> Result<Vec<Self::Item>, diesel::result::Error> {
-        todo!();
+        last_ts: TS,
+    ) -> Result<Vec<Self::Item>> {
+        let symbol = self.symbol.clone();
+        let granularity = self.granularity.as_ref().to_string();
+        with_connection(pool, move |conn| {
+            let rows = bars::table
+                .filter(bars::symbol.eq(symbol))
+                .filter(bars::granularity.eq(granularity))
+                .filter(bars::ts.between(first_ts, last_ts))
+                .order(bars::ts.asc())
+                .select(BarRow::as_select())
+                .load(conn)?;
+            Ok(rows.into_iter().map(Bar::from).collect())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl DbWriteMethods for BarDataSet {
+    // Bars are stored under this dataset's symbol and granularity
+    async fn db_write(&self, pool: &PgPool, items: Vec<Self::Item>) -> Result<WriteReport> {
+        let rows: Vec<BarRow> = items
+            .iter()
+            .map(|bar| BarRow::new(&self.symbol, &self.granularity, bar))
+            .collect();
+        with_connection(pool, move |conn| {
+            let key = |row: &BarRow| (row.symbol.clone(), row.granularity.clone(), row.ts);
+            upsert_batches(conn, rows, key, |conn, batch| {
+                let upsert = diesel::insert_into(bars::table)
+                    .values(batch)
+                    .on_conflict((bars::symbol, bars::granularity, bars::ts))
+                    .do_update()
+                    .set((
+                        bars::open.eq(excluded(bars::open)),
+                        bars::high.eq(excluded(bars::high)),
+                        bars::low.eq(excluded(bars::low)),
+                        bars::close.eq(excluded(bars::close)),
+                        bars::volume.eq(excluded(bars::volume)),
+                    ));
+                // Unchanged rows are neither written nor returned
+                methods::FilterDsl::filter(
+                    upsert,
+                    bars::open
+                        .ne(excluded(bars::open))
+                        .or(bars::high.ne(excluded(bars::high)))
+                        .or(bars::low.ne(excluded(bars::low)))
+                        .or(bars::close.ne(excluded(bars::close)))
+                        .or(bars::volume.ne(excluded(bars::volume))),
+                )
+                .returning(inserted())
+                .get_results(conn)
+            })
+        })
+        .await
+    }
+    async fn db_write_all(&self, pool: &PgPool) -> Result<WriteReport> {
+        self.db_write(pool, self.data.values().cloned().collect())
+            .await
     }
 }
 
@@ -190,16 +381,78 @@ pub struct NormalizedBook {
     pub ts: TS,
 }
 
+impl Timestamped for NormalizedBook {
+    fn timestamp(&self) -> TS {
+        self.ts
+    }
+}
+
+// Snapshots of one symbol, files are read and written in `format`, see data::book_csv for the layouts
 #[derive(Debug, Clone)]
 pub struct BookDataSet {
+    symbol: String,
     data: BTreeMap<TS, NormalizedBook>,
+    format: BookCsvFormat,
 }
 impl BookDataSet {
-    fn new(granularity: BarGranularity) -> Self {
+    pub fn new(symbol: String) -> Self {
         BookDataSet {
+            symbol,
             data: BTreeMap::new(),
+            format: BookCsvFormat::default(),
         }
     }
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+    pub fn with_format(mut self, format: BookCsvFormat) -> Self {
+        self.format = format;
+        self
+    }
+    pub fn format(&self) -> BookCsvFormat {
+        self.format
+    }
+    // Snapshots of the dataset's symbol within the window
+    async fn file_snapshots(
+        &self,
+        path: &str,
+        first_ts: TS,
+        last_ts: TS,
+    ) -> Result<Vec<NormalizedBook>, std::io::Error> {
+        let stream = match self.format {
+            BookCsvFormat::Wide => {
+                CsvStream::with_records(path, first_ts, last_ts, WideRecords::new)
+            }
+            BookCsvFormat::Long => {
+                CsvStream::with_records(path, first_ts, last_ts, LongRecords::new)
+            }
+        };
+        stream
+            .try_collect_where(|book| book.symbol == self.symbol)
+            .await
+    }
+    // Writes `books` to `path` in the dataset's format, an existing file is replaced
+    pub async fn file_write(
+        &self,
+        path: &str,
+        books: Vec<NormalizedBook>,
+    ) -> Result<(), std::io::Error> {
+        let path = path.to_string();
+        let format = self.format;
+        tokio::task::spawn_blocking(move || {
+            let file = BufWriter::new(File::create(path)?);
+            match format {
+                BookCsvFormat::Wide => write_wide(file, &books),
+                BookCsvFormat::Long => write_long(file, &books),
+            }
+        })
+        .await?
+    }
+    // Writes every snapshot held by the dataset
+    pub async fn file_write_all(&self, path: &str) -> Result<(), std::io::Error> {
+        self.file_write(path, self.data.values().cloned().collect())
+            .await
+    }
     fn single_insert(&mut self, timestamp: TS, book: NormalizedBook) {
         self.data.insert(timestamp, book);
     }
@@ -208,9 +461,13 @@ impl BookDataSet {
 impl DataUpdate for BookDataSet {
     type NewData = Vec<NormalizedBook>;
 
+    // A new entry replaces the one stored under the same timestamp, other symbols are dropped
     fn update(&mut self, data: Self::NewData) {
-        data.iter()
-            .map(|entry| self.data.insert(entry.ts, entry.clone()));
+        for entry in data {
+            if entry.symbol == self.symbol {
+                self.data.insert(entry.ts, entry);
+            }
+        }
     }
 }
 
@@ -229,6 +486,10 @@ impl LocalDataMethods for BookDataSet {
         first_ts: TS,
         last_ts: TS,
     ) -> Result<Self::Output, Box<dyn Error>> {
+        // BTreeMap::range panics on an inverted range
+        if first_ts > last_ts {
+            return Ok(Vec::new());
+        }
         Ok(self
             .data
             .range(first_ts..=last_ts)
@@ -241,46 +502,17 @@ impl LocalDataMethods for BookDataSet {
 impl IODataMethods for BookDataSet {
     type Item = NormalizedBook;
 
+    // Like the db methods only snapshots of the dataset's symbol are kept
     async fn from_file_full_dataset(&self, path: &str) -> Result<Vec<Self::Item>, std::io::Error> {
-        let mut file = File::open(path).await?;
-        let mut contents = String::new();
-
-        file.read_to_string(&mut contents).await?;
-
-        let mut csv_reader = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(contents.as_bytes());
-
-        let mut book = Vec::new();
-        for result in csv_reader.deserialize() {
-            let record: NormalizedBook =
-                result.map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
-        }
-
-        Ok(book)
+        self.file_snapshots(path, TS::MIN, TS::MAX).await
     }
+    // Snapshots at or after `last_ts`
     async fn from_file_by_ts_lookback(
         &self,
         path: &str,
         last_ts: TS,
     ) -> Result<Vec<Self::Item>, std::io::Error> {
-        let mut file = File::open(path).await?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).await?;
-
-        let mut reader = ReaderBuilder::new().from_reader(contents.as_bytes());
-
-        let mut book = Vec::new();
-        for result in reader.deserialize() {
-            let record: NormalizedBook =
-                result.map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
-
-            if record.ts >= last_ts {
-                book.push(record);
-            }
-        }
-
-        Ok(book)
+        self.file_snapshots(path, last_ts, TS::MAX).await
     }
     async fn from_file_by_ts_window(
         &self,
@@ -288,40 +520,70 @@ impl IODataMethods for BookDataSet {
         first_ts: TS,
         last_ts: TS,
     ) -> Result<Vec<Self::Item>, std::io::Error> {
-        let mut file = File::open(path).await?;
-        let mut contents = String::new();
-
-        file.read_to_string(&mut contents).await?;
-
-        let mut csv_reader = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(contents.as_bytes());
-
-        let mut book = Vec::new();
-
-        for result in csv_reader.deserialize() {
-            let record: NormalizedBook =
-                result.map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
-            if record.ts >= first_ts && record.ts <= last_ts {
-                book.push(record);
-            }
-        }
-
-        Ok(book)
+        self.file_snapshots(path, first_ts, last_ts).await
     }
-    async fn from_db_all_entries(
-        &self,
-        conn: &PgConnection,
-    ) -> Result<Vec<Self::Item>, diesel::result::Error> {
-        unimplemented!();
+    // Snapshots whose symbol is this dataset's symbol, ordered by timestamp
+    async fn from_db_all_entries(&self, pool: &PgPool) -> Result<Vec<Self::Item>> {
+        self.db_ts_window(pool, TS::MIN, TS::MAX).await
     }
     async fn db_ts_window(
         &self,
-        conn: &PgConnection,
+        pool: &PgPool,
         first_ts: TS,
-        last_entry: TS,
-    ) -> Result<Vec<Self::Item>, diesel::result::Error> {
-        unimplemented!();
+        last_ts: TS,
+    ) -> Result<Vec<Self::Item>> {
+        let symbol = self.symbol.clone();
+        with_connection(pool, move |conn| {
+            book_snapshots::table
+                .filter(book_snapshots::symbol.eq(symbol))
+                .filter(book_snapshots::ts.between(first_ts, last_ts))
+                .order(book_snapshots::ts.asc())
+                .select(BookRow::as_select())
+                .load(conn)?
+                .into_iter()
+                .map(NormalizedBook::try_from)
+                .collect()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl DbWriteMethods for BookDataSet {
+    async fn db_write(&self, pool: &PgPool, items: Vec<Self::Item>) -> Result<WriteReport> {
+        let rows = items
+            .iter()
+            .map(BookRow::try_from)
+            .collect::<Result<Vec<BookRow>>>()?;
+        with_connection(pool, move |conn| {
+            let key = |row: &BookRow| (row.symbol.clone(), row.ts);
+            upsert_batches(conn, rows, key, |conn, batch| {
+                let upsert = diesel::insert_into(book_snapshots::table)
+                    .values(batch)
+                    .on_conflict((book_snapshots::symbol, book_snapshots::ts))
+                    .do_update()
+                    .set((
+                        book_snapshots::depth.eq(excluded(book_snapshots::depth)),
+                        book_snapshots::bids.eq(excluded(book_snapshots::bids)),
+                        book_snapshots::asks.eq(excluded(book_snapshots::asks)),
+                    ));
+                // Unchanged rows are neither written nor returned
+                methods::FilterDsl::filter(
+                    upsert,
+                    book_snapshots::depth
+                        .ne(excluded(book_snapshots::depth))
+                        .or(book_snapshots::bids.ne(excluded(book_snapshots::bids)))
+                        .or(book_snapshots::asks.ne(excluded(book_snapshots::asks))),
+                )
+                .returning(inserted())
+                .get_results(conn)
+            })
+        })
+        .await
+    }
+    async fn db_write_all(&self, pool: &PgPool) -> Result<WriteReport> {
+        self.db_write(pool, self.data.values().cloned().collect())
+            .await
     }
 }
 
@@ -412,16 +674,13 @@ impl TickDataSet {
         }
     }
 
+    // Index of the first tick at or after `target_timestamp`, len() if there is none
     pub fn find_nearest_ts_index(&mut self, target_timestamp: TS) -> usize {
         self.sort_by_timestamp();
 
-        match self
-            .data
-            .binary_search_by_key(&target_timestamp, |entry| entry.tx_ts)
-        {
-            Ok(index) => index,
-            Err(index) => index,
-        }
+        //NOTE: binary_search may land anywhere in a run of equal timestamps
+        self.data
+            .partition_point(|entry| entry.tx_ts < target_timestamp)
     }
 
     pub fn get_data_by_timestamp_lookback(&mut self, first_ts: TS) -> Result<Vec<NormalizedTicks>> {
@@ -443,45 +702,119 @@ impl TickDataSet {
             .cloned()
             .collect())
     }
+}
 
-    async fn from_file_full_dataset(
-        &self,
-        path: &str,
-    ) -> Result<VecDeque<TickDataSet>, std::io::Error> {
-        todo!();
+impl DataUpdate for TickDataSet {
+    type NewData = Vec<NormalizedTicks>;
+
+    // Appends and keeps the ticks sorted, ticks sharing a timestamp stay in arrival order. Ticks
+    // of other symbols are dropped
+    fn update(&mut self, data: Self::NewData) {
+        let identifier = &self.identifier;
+        self.data
+            .extend(data.into_iter().filter(|tick| &tick.symbol == identifier));
+        self.sort_by_timestamp();
+    }
+}
+
+#[async_trait]
+impl IODataMethods for TickDataSet {
+    type Item = NormalizedTicks;
+
+    // Like the db methods only ticks whose symbol is the dataset's identifier are kept
+    async fn from_file_full_dataset(&self, path: &str) -> Result<Vec<Self::Item>, std::io::Error> {
+        self.from_file_by_ts_window(path, TS::MIN, TS::MAX).await
     }
+    // Ticks at or after `last_ts`
     async fn from_file_by_ts_lookback(
         &self,
         path: &str,
         last_ts: TS,
-    ) -> Result<VecDeque<TickDataSet>, std::io::Error> {
-        todo!();
+    ) -> Result<Vec<Self::Item>, std::io::Error> {
+        self.from_file_by_ts_window(path, last_ts, TS::MAX).await
     }
     async fn from_file_by_ts_window(
         &self,
         path: &str,
         first_ts: TS,
         last_ts: TS,
-    ) -> Result<VecDeque<TickDataSet>, std::io::Error> {
-        todo!();
+    ) -> Result<Vec<Self::Item>, std::io::Error> {
+        CsvStream::window(path, first_ts, last_ts)
+            .try_collect_where(|tick: &NormalizedTicks| tick.symbol == self.identifier)
+            .await
     }
-    async fn from_db_all_entries(
-        &self,
-        conn: &PgConnection,
-    ) -> Result<VecDeque<TickDataSet>, diesel::result::Error> {
-        unimplemented!();
+    // Ticks whose symbol is this dataset's identifier, ordered by tx_ts then server_id
+    async fn from_db_all_entries(&self, pool: &PgPool) -> Result<Vec<Self::Item>> {
+        self.db_ts_window(pool, TS::MIN, TS::MAX).await
     }
     async fn db_ts_window(
         &self,
-        conn: &PgConnection,
+        pool: &PgPool,
         first_ts: TS,
-        last_entry: TS,
-    ) -> Result<VecDeque<TickDataSet>, diesel::result::Error> {
-        unimplemented!();
+        last_ts: TS,
+    ) -> Result<Vec<Self::Item>> {
+        let symbol = self.identifier.clone();
+        with_connection(pool, move |conn| {
+            ticks::table
+                .filter(ticks::symbol.eq(symbol))
+                .filter(ticks::tx_ts.between(first_ts, last_ts))
+                .order((ticks::tx_ts.asc(), ticks::server_id.asc()))
+                .select(TickRow::as_select())
+                .load(conn)?
+                .into_iter()
+                .map(NormalizedTicks::try_from)
+                .collect()
+        })
+        .await
     }
 }
 
-#[derive(Debug, Clone)]
+impl Timestamped for NormalizedTicks {
+    fn timestamp(&self) -> TS {
+        self.tx_ts
+    }
+}
+
+#[async_trait]
+impl DbWriteMethods for TickDataSet {
+    // Ticks are stored under their own symbol, which need not be this dataset's identifier
+    async fn db_write(&self, pool: &PgPool, items: Vec<Self::Item>) -> Result<WriteReport> {
+        let rows: Vec<TickRow> = items.iter().map(TickRow::from).collect();
+        with_connection(pool, move |conn| {
+            let key = |row: &TickRow| (row.symbol.clone(), row.tx_ts, row.server_id);
+            upsert_batches(conn, rows, key, |conn, batch| {
+                let upsert = diesel::insert_into(ticks::table)
+                    .values(batch)
+                    .on_conflict((ticks::symbol, ticks::tx_ts, ticks::server_id))
+                    .do_update()
+                    .set((
+                        ticks::side.eq(excluded(ticks::side)),
+                        ticks::px.eq(excluded(ticks::px)),
+                        ticks::qty.eq(excluded(ticks::qty)),
+                        ticks::local_ids.eq(excluded(ticks::local_ids)),
+                    ));
+                // Unchanged rows are neither written nor returned
+                methods::FilterDsl::filter(
+                    upsert,
+                    ticks::side
+                        .ne(excluded(ticks::side))
+                        .or(ticks::px.ne(excluded(ticks::px)))
+                        .or(ticks::qty.ne(excluded(ticks::qty)))
+                        .or(ticks::local_ids.ne(excluded(ticks::local_ids))),
+                )
+                .returning(inserted())
+                .get_results(conn)
+            })
+        })
+        .await
+    }
+    async fn db_write_all(&self, pool: &PgPool) -> Result<WriteReport> {
+        self.db_write(pool, self.data.iter().cloned().collect())
+            .await
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NormalizedTicks {
     pub symbol: String,
     pub side: Side,
@@ -506,7 +839,7 @@ pub enum Granularity {
     OB(OBGranularity),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BarGranularity {
     OneMinute,
     FiveMinute,
@@ -532,6 +865,20 @@ pub enum Side {
     Buy,
     Sell,
 }
+impl AsRef<str> for BarGranularity {
+    fn as_ref(&self) -> &str {
+        match self {
+            BarGranularity::OneMinute => "1m",
+            BarGranularity::FiveMinute => "5m",
+            BarGranularity::FifteenMinute => "15m",
+            BarGranularity::ThirtyMinute => "30m",
+            BarGranularity::OneHour => "1h",
+            BarGranularity::FourHour => "4h",
+            BarGranularity::Daily => "1d",
+        }
+    }
+}
+
 impl AsRef<str> for Side {
     fn as_ref(&self) -> &str {
         match self {
@@ -540,5 +887,3 @@ impl AsRef<str> for Side {
         }
     }
 }
-
-//TODO: Throughouly test all search/get methods for expected behavior, especially TickDataSet
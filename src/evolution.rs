@@ -0,0 +1,769 @@
+use crate::{
+    features::DataContext,
+    gene::{
+        hash::{canonical, identical},
+        operators::GeneticOperators,
+        ConstantOptimizer, EvalPolicy, Expression, FitData,
+    },
+    traits::{FitnessFunction, GeneticTree},
+};
+use eyre::{eyre, Result};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+};
+
+//NOTE: Higher is better. `cases` holds one score per fitness case (e.g. per bar or per symbol)
+// and is only needed for lexicase selection, a non-finite score ranks below every finite one.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Fitness {
+    pub score: f64,
+    pub cases: Vec<f64>,
+}
+
+impl Fitness {
+    pub fn new(score: f64, cases: Vec<f64>) -> Self {
+        Fitness { score, cases }
+    }
+    pub fn scalar(score: f64) -> Self {
+        Fitness {
+            score,
+            cases: Vec::new(),
+        }
+    }
+    pub fn is_valid(&self) -> bool {
+        self.score.is_finite()
+    }
+
+    fn rank_value(value: f64) -> f64 {
+        if value.is_finite() {
+            value
+        } else {
+            f64::NEG_INFINITY
+        }
+    }
+    fn compare(&self, other: &Fitness) -> Ordering {
+        Fitness::rank_value(self.score).total_cmp(&Fitness::rank_value(other.score))
+    }
+}
+
+impl<F> FitnessFunction for F
+where
+    F: Fn(&Expression, EvalPolicy, &DataContext) -> f64,
+{
+    fn evaluate(&self, expression: &Expression, policy: EvalPolicy, data: &DataContext) -> Fitness {
+        Fitness::scalar(self(expression, policy, data))
+    }
+}
+
+// `policy` is the EvalPolicy the fitness was computed under, re-evaluate the alpha with it
+#[derive(Debug, Clone)]
+pub struct Individual {
+    pub expression: Expression,
+    pub fitness: Fitness,
+    pub policy: EvalPolicy,
+}
+
+#[derive(Debug, Clone)]
+pub struct Population {
+    individuals: Vec<Individual>,
+    generation: usize,
+}
+
+impl Population {
+    pub fn new(individuals: Vec<Individual>, generation: usize) -> Self {
+        Population {
+            individuals,
+            generation,
+        }
+    }
+    pub fn individuals(&self) -> &[Individual] {
+        &self.individuals
+    }
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+    pub fn len(&self) -> usize {
+        self.individuals.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.individuals.is_empty()
+    }
+    // Share of structurally distinct trees, see GeneticTree::structural_hash
+    pub fn unique_ratio(&self) -> f64 {
+        if self.individuals.is_empty() {
+            return 0.0;
+        }
+        let unique: HashSet<u64> = self
+            .individuals
+            .iter()
+            .map(|individual| individual.expression.structural_hash())
+            .collect();
+        unique.len() as f64 / self.individuals.len() as f64
+    }
+
+    pub fn best(&self) -> Option<&Individual> {
+        self.individuals
+            .iter()
+            .max_by(|a, b| a.fitness.compare(&b.fitness))
+    }
+
+    // Best first
+    fn ranked(&self) -> Vec<&Individual> {
+        let mut ranked: Vec<&Individual> = self.individuals.iter().collect();
+        ranked.sort_by(|a, b| b.fitness.compare(&a.fitness));
+        ranked
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    Tournament { size: usize },
+    Lexicase,
+    Roulette,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MutationWeights {
+    pub point: f64,
+    pub subtree: f64,
+    pub hoist: f64,
+    pub shrink: f64,
+    pub constant: f64,
+}
+
+impl Default for MutationWeights {
+    fn default() -> Self {
+        MutationWeights {
+            point: 1.0,
+            subtree: 1.0,
+            hoist: 1.0,
+            shrink: 1.0,
+            constant: 1.0,
+        }
+    }
+}
+
+//NOTE: Each offspring is produced by crossover with probability crossover_rate, by one of the
+// mutations (picked by MutationWeights) with probability mutation_rate, and copied unchanged
+// otherwise. The `elitism` best individuals pass into the next generation untouched.
+#[derive(Debug, Clone)]
+pub struct EvolutionConfig {
+    pub population_size: usize,
+    pub elitism: usize,
+    pub crossover_rate: f64,
+    pub mutation_rate: f64,
+    pub mutation_weights: MutationWeights,
+    pub selection: Selection,
+    pub max_generations: usize,
+    // Stop once the best score reaches this value
+    pub target_fitness: Option<f64>,
+    // Stop after this many generations without an improvement of the best score
+    pub stagnation_limit: Option<usize>,
+    pub seed: u64,
+    // Passed to the fitness function and recorded on every Individual
+    pub eval_policy: EvalPolicy,
+    // Needs FitData, see Evolver::with_fit_data
+    pub constant_optimization: Option<ConstantSchedule>,
+    // Reuse the fitness of structurally equal trees, see FitnessCache
+    pub cache_fitness: bool,
+}
+
+//NOTE: Every `every` generations the constants of the `elites` best individuals are fitted to the
+// FitData target. A refitted tree is re-evaluated and replaces the individual unless its fitness
+// got worse.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConstantSchedule {
+    pub every: usize,
+    pub elites: usize,
+    pub optimizer: ConstantOptimizer,
+}
+
+impl Default for EvolutionConfig {
+    fn default() -> Self {
+        EvolutionConfig {
+            population_size: 500,
+            elitism: 2,
+            crossover_rate: 0.8,
+            mutation_rate: 0.15,
+            mutation_weights: MutationWeights::default(),
+            selection: Selection::Tournament { size: 7 },
+            max_generations: 50,
+            target_fitness: None,
+            stagnation_limit: None,
+            seed: 0,
+            eval_policy: EvalPolicy::default(),
+            constant_optimization: None,
+            cache_fitness: true,
+        }
+    }
+}
+
+impl EvolutionConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.population_size == 0 {
+            return Err(eyre!("population_size must be positive"));
+        }
+        if self.elitism > self.population_size {
+            return Err(eyre!(
+                "elitism {} exceeds population_size {}",
+                self.elitism,
+                self.population_size
+            ));
+        }
+        let rates = [self.crossover_rate, self.mutation_rate];
+        if rates.iter().any(|rate| !(0.0..=1.0).contains(rate)) || rates.iter().sum::<f64>() > 1.0 {
+            return Err(eyre!(
+                "crossover_rate and mutation_rate must lie in [0, 1] and sum to at most 1"
+            ));
+        }
+        let weights = self.mutation_weights;
+        let weights = [
+            weights.point,
+            weights.subtree,
+            weights.hoist,
+            weights.shrink,
+            weights.constant,
+        ];
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0)
+            || (self.mutation_rate > 0.0 && weights.iter().sum::<f64>() <= 0.0)
+        {
+            return Err(eyre!(
+                "mutation weights must be non-negative and not all zero"
+            ));
+        }
+        if let Selection::Tournament { size: 0 } = self.selection {
+            return Err(eyre!("tournament size must be positive"));
+        }
+        if let Some(schedule) = self.constant_optimization {
+            if schedule.every == 0 {
+                return Err(eyre!("constant optimization interval must be positive"));
+            }
+            if schedule.elites > self.population_size {
+                return Err(eyre!(
+                    "constant optimization elites {} exceed population_size {}",
+                    schedule.elites,
+                    self.population_size
+                ));
+            }
+            let optimizer = schedule.optimizer;
+            if !(optimizer.tolerance() >= 0.0) {
+                return Err(eyre!(
+                    "constant optimization tolerance must be non-negative"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeDistribution {
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+    pub median: f64,
+    // node count -> number of individuals
+    pub histogram: BTreeMap<usize, usize>,
+}
+
+//NOTE: mean/median are taken over valid (finite) scores only, `invalid` counts the rest
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationStats {
+    pub generation: usize,
+    pub best_fitness: f64,
+    pub mean_fitness: f64,
+    pub median_fitness: f64,
+    pub invalid: usize,
+    // See Population::unique_ratio
+    pub unique_ratio: f64,
+    pub size: SizeDistribution,
+}
+
+impl GenerationStats {
+    pub fn from_population(population: &Population) -> Self {
+        let mut scores: Vec<f64> = population
+            .individuals
+            .iter()
+            .map(|individual| individual.fitness.score)
+            .filter(|score| score.is_finite())
+            .collect();
+        scores.sort_by(f64::total_cmp);
+
+        let mut sizes: Vec<usize> = population
+            .individuals
+            .iter()
+            .map(|individual| individual.expression.node_count())
+            .collect();
+        sizes.sort_unstable();
+
+        let mut histogram = BTreeMap::new();
+        for size in &sizes {
+            *histogram.entry(*size).or_insert(0) += 1;
+        }
+
+        GenerationStats {
+            generation: population.generation,
+            best_fitness: population
+                .best()
+                .map_or(f64::NAN, |best| best.fitness.score),
+            mean_fitness: mean(&scores),
+            median_fitness: median(&scores),
+            invalid: population.len() - scores.len(),
+            unique_ratio: population.unique_ratio(),
+            size: SizeDistribution {
+                min: sizes.first().copied().unwrap_or(0),
+                max: sizes.last().copied().unwrap_or(0),
+                mean: mean(&sizes.iter().map(|s| *s as f64).collect::<Vec<_>>()),
+                median: median(&sizes.iter().map(|s| *s as f64).collect::<Vec<_>>()),
+                histogram,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    MaxGenerations,
+    TargetFitness,
+    Stagnation,
+}
+
+#[derive(Debug, Clone)]
+pub struct EvolutionResult {
+    pub best: Individual,
+    pub population: Population,
+    pub history: Vec<GenerationStats>,
+    pub stop_reason: StopReason,
+}
+
+//NOTE: Fitness of the trees already scored, a tree that only differs from one of them in the
+// operand order of a binary Add/Multiply is not evaluated again. Entries are found by structural
+// hash and hold the canonical tree (hash::canonical), which has to match on a hit, so hash
+// collisions and reordered chains of three or more operands (which can round differently) are
+// scored on their own. Entries only hold for one DataContext and EvalPolicy, the Evolver clears its
+// cache at the start of every run, call Evolver::clear_cache before evaluating on other data.
+#[derive(Debug, Clone, Default)]
+pub struct FitnessCache {
+    entries: HashMap<u64, Vec<(Expression, Fitness)>>,
+    len: usize,
+    hits: usize,
+    misses: usize,
+}
+
+impl FitnessCache {
+    pub fn new() -> Self {
+        FitnessCache::default()
+    }
+
+    pub fn get(&mut self, expression: &Expression) -> Option<&Fitness> {
+        let canonical = canonical(expression);
+        let found = self
+            .entries
+            .get(&canonical.structural_hash())
+            .and_then(|bucket| bucket.iter().find(|(tree, _)| identical(tree, &canonical)));
+        match found {
+            Some((_, fitness)) => {
+                self.hits += 1;
+                Some(fitness)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, expression: &Expression, fitness: Fitness) {
+        let canonical = canonical(expression);
+        let bucket = self.entries.entry(canonical.structural_hash()).or_default();
+        match bucket
+            .iter_mut()
+            .find(|(tree, _)| identical(tree, &canonical))
+        {
+            Some(entry) => entry.1 = fitness,
+            None => {
+                bucket.push((canonical, fitness));
+                self.len += 1;
+            }
+        }
+    }
+
+    // Drops the entries and resets the counts
+    pub fn clear(&mut self) {
+        *self = FitnessCache::default();
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
+
+pub struct Evolver<F: FitnessFunction> {
+    config: EvolutionConfig,
+    operators: GeneticOperators,
+    fitness: F,
+    rng: StdRng,
+    fit_data: Option<FitData>,
+    cache: FitnessCache,
+}
+
+impl<F: FitnessFunction> Evolver<F> {
+    pub fn new(config: EvolutionConfig, operators: GeneticOperators, fitness: F) -> Result<Self> {
+        config.validate()?;
+        Ok(Evolver {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            operators,
+            fitness,
+            fit_data: None,
+            cache: FitnessCache::new(),
+        })
+    }
+
+    // Columns and target the ConstantSchedule fits constants against, evaluated under eval_policy
+    pub fn with_fit_data(mut self, fit_data: FitData) -> Self {
+        self.fit_data = Some(fit_data);
+        self
+    }
+
+    pub fn config(&self) -> &EvolutionConfig {
+        &self.config
+    }
+
+    pub fn cache(&self) -> &FitnessCache {
+        &self.cache
+    }
+
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn evaluate(&mut self, expression: Expression, data: &DataContext) -> Individual {
+        let policy = self.config.eval_policy;
+        if !self.config.cache_fitness {
+            let fitness = self.fitness.evaluate(&expression, policy, data);
+            return Individual {
+                expression,
+                fitness,
+                policy,
+            };
+        }
+
+        let fitness = match self.cache.get(&expression) {
+            Some(fitness) => fitness.clone(),
+            None => {
+                let fitness = self.fitness.evaluate(&expression, policy, data);
+                self.cache.insert(&expression, fitness.clone());
+                fitness
+            }
+        };
+        Individual {
+            expression,
+            fitness,
+            policy,
+        }
+    }
+
+    pub fn initialize(&mut self, data: &DataContext) -> Result<Population> {
+        let expressions: Vec<Expression> = self.operators.generator().ramped_half_and_half(
+            self.config.population_size,
+            &(),
+            &mut self.rng,
+        )?;
+        let individuals = expressions
+            .into_iter()
+            .map(|expression| self.evaluate(expression, data))
+            .collect();
+
+        Ok(Population::new(individuals, 0))
+    }
+
+    pub fn step(&mut self, population: &Population, data: &DataContext) -> Population {
+        let mut next: Vec<Individual> = population
+            .ranked()
+            .into_iter()
+            .take(self.config.elitism)
+            .cloned()
+            .collect();
+
+        while next.len() < self.config.population_size {
+            let offspring = self.breed(population);
+            next.push(self.evaluate(offspring, data));
+        }
+
+        Population::new(next, population.generation + 1)
+    }
+
+    // Runs the ConstantSchedule optimizer on the elites, regardless of the generation
+    pub fn optimize_constants(&mut self, population: &mut Population, data: &DataContext) {
+        // Taken out for the duration so the elites can be re-evaluated through self
+        let Some(schedule) = self.config.constant_optimization else {
+            return;
+        };
+        let Some(fit_data) = self.fit_data.take() else {
+            return;
+        };
+        let context = fit_data.context().with_eval_policy(self.config.eval_policy);
+
+        let mut ranked: Vec<usize> = (0..population.len()).collect();
+        ranked.sort_by(|a, b| {
+            population.individuals[*b]
+                .fitness
+                .compare(&population.individuals[*a].fitness)
+        });
+        for index in ranked.into_iter().take(schedule.elites) {
+            let current = &population.individuals[index];
+            let Ok(optimized) =
+                schedule
+                    .optimizer
+                    .optimize(&current.expression, &context, fit_data.target())
+            else {
+                continue;
+            };
+            if !(optimized.loss < optimized.initial_loss) {
+                continue;
+            }
+            let candidate = self.evaluate(optimized.expression, data);
+            if candidate.fitness.compare(&current.fitness) != Ordering::Less {
+                population.individuals[index] = candidate;
+            }
+        }
+        drop(context);
+        self.fit_data = Some(fit_data);
+    }
+
+    // Every run starts with an empty FitnessCache
+    pub fn run(&mut self, data: &DataContext) -> Result<EvolutionResult> {
+        self.clear_cache();
+        let population = self.initialize(data)?;
+        self.evolve(population, data)
+    }
+
+    pub fn run_from(
+        &mut self,
+        population: Population,
+        data: &DataContext,
+    ) -> Result<EvolutionResult> {
+        self.clear_cache();
+        self.evolve(population, data)
+    }
+
+    fn evolve(
+        &mut self,
+        mut population: Population,
+        data: &DataContext,
+    ) -> Result<EvolutionResult> {
+        if population.is_empty() {
+            return Err(eyre!("cannot evolve an empty population"));
+        }
+        if self.config.constant_optimization.is_some() && self.fit_data.is_none() {
+            return Err(eyre!(
+                "constant optimization needs FitData, see Evolver::with_fit_data"
+            ));
+        }
+
+        let mut history = vec![GenerationStats::from_population(&population)];
+        let mut best_score = history[0].best_fitness;
+        let mut stagnant = 0;
+
+        let stop_reason = loop {
+            if let Some(target) = self.config.target_fitness {
+                if Fitness::rank_value(best_score) >= target {
+                    break StopReason::TargetFitness;
+                }
+            }
+            if let Some(limit) = self.config.stagnation_limit {
+                if stagnant >= limit {
+                    break StopReason::Stagnation;
+                }
+            }
+            if population.generation >= self.config.max_generations {
+                break StopReason::MaxGenerations;
+            }
+
+            population = self.step(&population, data);
+            if let Some(schedule) = self.config.constant_optimization {
+                if population.generation % schedule.every == 0 {
+                    self.optimize_constants(&mut population, data);
+                }
+            }
+            let stats = GenerationStats::from_population(&population);
+            if Fitness::rank_value(stats.best_fitness) > Fitness::rank_value(best_score) {
+                best_score = stats.best_fitness;
+                stagnant = 0;
+            } else {
+                stagnant += 1;
+            }
+            history.push(stats);
+        };
+
+        let best = population
+            .best()
+            .cloned()
+            .ok_or_else(|| eyre!("population is empty"))?;
+
+        Ok(EvolutionResult {
+            best,
+            population,
+            history,
+            stop_reason,
+        })
+    }
+
+    fn breed(&mut self, population: &Population) -> Expression {
+        let roll: f64 = self.rng.gen();
+        let parent = self.select(population).expression.clone();
+
+        if roll < self.config.crossover_rate {
+            let other = self.select(population).expression.clone();
+            self.operators
+                .subtree_crossover(&parent, &other, &mut self.rng)
+                .0
+        } else if roll < self.config.crossover_rate + self.config.mutation_rate {
+            self.mutate(&parent)
+        } else {
+            parent
+        }
+    }
+
+    fn mutate(&mut self, parent: &Expression) -> Expression {
+        let weights = self.config.mutation_weights;
+        let weights = [
+            weights.point,
+            weights.subtree,
+            weights.hoist,
+            weights.shrink,
+            weights.constant,
+        ];
+        let mut roll = self.rng.gen::<f64>() * weights.iter().sum::<f64>();
+        let mut choice = weights.len() - 1;
+        for (index, weight) in weights.iter().enumerate() {
+            if roll < *weight {
+                choice = index;
+                break;
+            }
+            roll -= weight;
+        }
+
+        let operators = &self.operators;
+        let rng = &mut self.rng;
+        match choice {
+            0 => operators.point_mutation(parent, rng),
+            1 => operators.subtree_mutation(parent, rng),
+            2 => operators.hoist_mutation(parent, rng),
+            3 => operators.shrink_mutation(parent, rng),
+            _ => operators.constant_perturbation(parent, rng),
+        }
+    }
+
+    fn select<'a>(&mut self, population: &'a Population) -> &'a Individual {
+        let individuals = &population.individuals;
+
+        match self.config.selection {
+            Selection::Tournament { size } => (0..size)
+                .map(|_| &individuals[self.rng.gen_range(0..individuals.len())])
+                .max_by(|a, b| a.fitness.compare(&b.fitness))
+                .unwrap_or(&individuals[0]),
+            Selection::Lexicase => self.lexicase(individuals),
+            Selection::Roulette => self.roulette(individuals),
+        }
+    }
+
+    // Filters the pool case by case in random order, keeping only the best on each case. Without
+    // per-case scores it degrades to picking (one of) the best overall.
+    fn lexicase<'a>(&mut self, individuals: &'a [Individual]) -> &'a Individual {
+        let case_count = individuals
+            .iter()
+            .map(|individual| individual.fitness.cases.len())
+            .min()
+            .unwrap_or(0);
+
+        let mut pool: Vec<&Individual> = individuals.iter().collect();
+        let mut cases: Vec<usize> = (0..case_count).collect();
+        cases.shuffle(&mut self.rng);
+
+        if cases.is_empty() {
+            let best = pool
+                .iter()
+                .map(|individual| Fitness::rank_value(individual.fitness.score))
+                .fold(f64::NEG_INFINITY, f64::max);
+            pool.retain(|individual| Fitness::rank_value(individual.fitness.score) == best);
+        }
+        for case in cases {
+            if pool.len() == 1 {
+                break;
+            }
+            let best = pool
+                .iter()
+                .map(|individual| Fitness::rank_value(individual.fitness.cases[case]))
+                .fold(f64::NEG_INFINITY, f64::max);
+            pool.retain(|individual| Fitness::rank_value(individual.fitness.cases[case]) == best);
+        }
+
+        pool[self.rng.gen_range(0..pool.len())]
+    }
+
+    // Fitness proportionate on scores shifted to be non-negative, invalid individuals are never
+    // picked unless the whole population is invalid
+    fn roulette<'a>(&mut self, individuals: &'a [Individual]) -> &'a Individual {
+        let valid: Vec<&Individual> = individuals
+            .iter()
+            .filter(|individual| individual.fitness.is_valid())
+            .collect();
+        if valid.is_empty() {
+            return &individuals[self.rng.gen_range(0..individuals.len())];
+        }
+
+        let scores = valid.iter().map(|individual| individual.fitness.score);
+        let floor = scores.clone().fold(f64::INFINITY, f64::min);
+        let spread = scores.fold(f64::NEG_INFINITY, f64::max) - floor;
+        // The lowest valid score keeps a share of the spread, equal scores fall back to a uniform draw
+        let weights: Vec<f64> = valid
+            .iter()
+            .map(|individual| individual.fitness.score - floor + ROULETTE_FLOOR * spread)
+            .collect();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 || !total.is_finite() {
+            return valid[self.rng.gen_range(0..valid.len())];
+        }
+
+        let mut roll = self.rng.gen::<f64>() * total;
+        for (individual, weight) in valid.iter().zip(&weights) {
+            if roll < *weight {
+                return individual;
+            }
+            roll -= weight;
+        }
+        valid[valid.len() - 1]
+    }
+}
+
+// Wheel weight of the lowest valid score as a share of the score spread
+const ROULETTE_FLOOR: f64 = 0.05;
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// Expects sorted input
+fn median(values: &[f64]) -> f64 {
+    match values.len() {
+        0 => f64::NAN,
+        n if n % 2 == 1 => values[n / 2],
+        n => (values[n / 2 - 1] + values[n / 2]) / 2.0,
+    }
+}
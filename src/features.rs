@@ -1,7 +1,12 @@
-use crate::{data::types::*, traits::*};
+use crate::{
+    data::types::*,
+    gene::{ColumnContext, Expression, FunctionData, FunctionNode, TerminalData},
+    traits::*,
+};
 use diesel::prelude::*;
 use diesel::PgConnection;
-use std::error;
+use eyre::{eyre, Result};
+use std::{cmp::Ordering, collections::HashSet, error};
 
 #[derive(Debug)]
 pub struct ExecutionContext {
@@ -26,6 +31,17 @@ impl ExecutionContext {
     pub fn update_slippage(&mut self, slippage: f64) {
         self.estimated_slippage = Some(slippage);
     }
+    pub fn data(&self) -> &DataContext {
+        &self.data
+    }
+
+    // Bars of the active data in timestamp order, the feature generators run on these
+    pub fn bars(&self) -> Result<BarColumns> {
+        match &self.data.data {
+            NormalizedTypes::Bar(set) => Ok(set.columns()),
+            _ => Err(eyre!("feature generators need bar data")),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -61,19 +77,81 @@ pub enum Operation {
     Custom,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Smoothing {
+    Simple,
+    // Weight 2 / (period + 1), seeded with the simple average of the first `period` bars
+    Exponential,
+}
+
 #[derive(Debug, Clone)]
 pub struct MA {
     period: usize,
+    smoothing: Smoothing,
+}
+
+impl MA {
+    pub fn simple(period: usize) -> Self {
+        MA {
+            period,
+            smoothing: Smoothing::Simple,
+        }
+    }
+    pub fn exponential(period: usize) -> Self {
+        MA {
+            period,
+            smoothing: Smoothing::Exponential,
+        }
+    }
+    pub fn period(&self) -> usize {
+        self.period
+    }
+    pub fn smoothing(&self) -> Smoothing {
+        self.smoothing
+    }
+
+    fn series(&self, close: &[f64]) -> Result<Vec<f64>> {
+        if self.period == 0 {
+            return Err(eyre!("moving average period must be positive"));
+        }
+        match self.smoothing {
+            Smoothing::Simple => on_close(
+                close,
+                function(
+                    FunctionData::TsMean {
+                        window: self.period,
+                    },
+                    vec![closes()],
+                ),
+            ),
+            Smoothing::Exponential => Ok(exponential_average(close, self.period)),
+        }
+    }
 }
 
+//NOTE: 1 while the first (fast) average is above the second (slow) one, -1 while it is below and 0
+// where they are equal, NaN until both averages are defined
 #[derive(Debug, Clone)]
 pub struct CrossOverComponents {
     components: Vec<MA>,
 }
 
+impl CrossOverComponents {
+    pub fn new(fast: MA, slow: MA) -> Self {
+        CrossOverComponents {
+            components: vec![fast, slow],
+        }
+    }
+    pub fn components(&self) -> &[MA] {
+        &self.components
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum MomentumTypes {
-    TimeSeries,
+    // close / close `lookback` bars earlier - 1
+    TimeSeries { lookback: usize },
+    // Needs a panel of symbols, ExecutionContext holds a single one
     CrossSectional,
 }
 
@@ -85,3 +163,159 @@ pub enum ArbTypes {
     Funding,
     Statistical,
 }
+
+impl Operation {
+    // Variable name the series of this operation is exposed under in FeatureColumns
+    pub fn variable_name(&self) -> String {
+        let average = |ma: &MA| match ma.smoothing {
+            Smoothing::Simple => format!("sma_{}", ma.period),
+            Smoothing::Exponential => format!("ema_{}", ma.period),
+        };
+        match self {
+            Operation::MovingAverage(ma) => average(ma),
+            Operation::CrossOver(crossover) => {
+                let names: Vec<String> = crossover.components.iter().map(average).collect();
+                format!("crossover_{}", names.join("_"))
+            }
+            Operation::Momentum(MomentumTypes::TimeSeries { lookback }) => {
+                format!("ts_momentum_{}", lookback)
+            }
+            Operation::Momentum(MomentumTypes::CrossSectional) => "cs_momentum".to_string(),
+            Operation::Arb(kind) => format!("arb_{:?}", kind).to_lowercase(),
+            Operation::Pairs => "pairs".to_string(),
+            Operation::Custom => "custom".to_string(),
+        }
+    }
+}
+
+impl Executable for Operation {
+    fn execute(&self, context: &ExecutionContext) -> Result<Vec<f64>> {
+        let bars = context.bars()?;
+        let close = bars.close.as_slice();
+
+        match self {
+            Operation::MovingAverage(ma) => ma.series(close),
+            Operation::CrossOver(crossover) => {
+                let [fast, slow] = crossover.components.as_slice() else {
+                    return Err(eyre!(
+                        "a crossover needs exactly 2 moving averages, got {}",
+                        crossover.components.len()
+                    ));
+                };
+                let (fast, slow) = (fast.series(close)?, slow.series(close)?);
+                Ok(fast
+                    .iter()
+                    .zip(&slow)
+                    .map(|(f, s)| match f.partial_cmp(s) {
+                        Some(Ordering::Greater) => 1.0,
+                        Some(Ordering::Less) => -1.0,
+                        Some(Ordering::Equal) => 0.0,
+                        None => f64::NAN,
+                    })
+                    .collect())
+            }
+            Operation::Momentum(MomentumTypes::TimeSeries { lookback }) => {
+                if *lookback == 0 {
+                    return Err(eyre!("momentum lookback must be positive"));
+                }
+                let ratio = function(
+                    FunctionData::Divide,
+                    vec![
+                        closes(),
+                        function(FunctionData::Delay { window: *lookback }, vec![closes()]),
+                    ],
+                );
+                let momentum = function(
+                    FunctionData::Subtract,
+                    vec![ratio, Expression::Terminal(TerminalData::Constant(1.0))],
+                );
+                on_close(close, momentum)
+            }
+            other => Err(eyre!(
+                "{} has no feature generator over a single bar series",
+                other.variable_name()
+            )),
+        }
+    }
+}
+
+//NOTE: Owned bar columns plus the series of some Operations, the context exposes each series under
+// Operation::variable_name next to open, high, low, close and volume so expressions can read them.
+// Two operations with the same name, or one named after a bar column, are rejected by new.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureColumns {
+    bars: BarColumns,
+    features: Vec<(String, Vec<f64>)>,
+}
+
+impl FeatureColumns {
+    pub fn new(context: &ExecutionContext, operations: &[Operation]) -> Result<Self> {
+        let mut names = HashSet::new();
+        for operation in operations {
+            let name = operation.variable_name();
+            if BAR_COLUMNS.contains(&name.as_str()) {
+                return Err(eyre!("feature `{}` would shadow a bar column", name));
+            }
+            if !names.insert(name.clone()) {
+                return Err(eyre!("feature `{}` is requested twice", name));
+            }
+        }
+        let features = operations
+            .iter()
+            .map(|operation| Ok((operation.variable_name(), operation.execute(context)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(FeatureColumns {
+            bars: context.bars()?,
+            features,
+        })
+    }
+
+    pub fn bars(&self) -> &BarColumns {
+        &self.bars
+    }
+    pub fn feature(&self, name: &str) -> Option<&[f64]> {
+        self.features
+            .iter()
+            .find(|(feature, _)| feature == name)
+            .map(|(_, values)| values.as_slice())
+    }
+
+    pub fn context(&self) -> Result<ColumnContext<'_>> {
+        let mut context = self.bars.context();
+        for (name, values) in &self.features {
+            context.push_column(name, values)?;
+        }
+        Ok(context)
+    }
+}
+
+const BAR_COLUMNS: [&str; 5] = ["open", "high", "low", "close", "volume"];
+
+fn closes() -> Expression {
+    Expression::Terminal(TerminalData::Variable("close".to_string()))
+}
+
+fn function(function: FunctionData, operands: Vec<Expression>) -> Expression {
+    Expression::Operation(FunctionNode::new(function, operands))
+}
+
+// The feature series follow the same warm-up and NaN semantics as the gene functions
+fn on_close(close: &[f64], expression: Expression) -> Result<Vec<f64>> {
+    let mut context = ColumnContext::new(close.len());
+    context.push_column("close", close)?;
+    Ok(expression.evaluate_columns(&context)?)
+}
+
+// NaN until `period` bars are in, a NaN close makes the rest of the series NaN
+fn exponential_average(close: &[f64], period: usize) -> Vec<f64> {
+    let weight = 2.0 / (period as f64 + 1.0);
+    let mut average = vec![f64::NAN; close.len()];
+    if close.len() < period {
+        return average;
+    }
+    average[period - 1] = close[..period].iter().sum::<f64>() / period as f64;
+    for i in period..close.len() {
+        average[i] = weight * close[i] + (1.0 - weight) * average[i - 1];
+    }
+    average
+}
@@ -0,0 +1,308 @@
+use super::{
+    timeseries::apply_rolling, ColumnContext, Context, EvalError, EvalErrorKind, EvalPolicy,
+    Expression, FunctionData, TerminalData,
+};
+use std::collections::HashMap;
+
+//NOTE: Postfix (stack machine) form of an Expression. Variables are resolved to slot indices at
+// compile time, so running a program never looks a name up. Operand counts and Root degrees are
+// validated once in `compile`, the interpreter loops trust them. Programs holding time-series
+// functions only run over columns, cross-sectional functions are not compiled. run/run_slices
+// evaluate under the program's EvalPolicy, run_context/run_columns under the context's. Every Call
+// keeps the path of its node, so errors point at the same node as the tree walk.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Constant(f64),
+    Load(usize),
+    // Pops `operands` values and pushes function.apply(values)
+    Call {
+        function: FunctionData,
+        operands: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    // Node path of each instruction
+    paths: Vec<Vec<usize>>,
+    slots: Vec<String>,
+    max_stack: usize,
+    warmup: usize,
+    time_series: bool,
+    eval_policy: EvalPolicy,
+}
+
+impl Program {
+    pub fn compile(expression: &Expression) -> Result<Program, EvalError> {
+        let mut compiler = Compiler {
+            instructions: Vec::new(),
+            paths: Vec::new(),
+            path: Vec::new(),
+            slots: Vec::new(),
+            slot_index: HashMap::new(),
+            depth: 0,
+            max_stack: 0,
+        };
+        compiler.emit(expression)?;
+        let time_series = compiler.instructions.iter().any(|instruction| {
+            matches!(instruction, Instruction::Call { function, .. } if function.is_time_series())
+        });
+
+        Ok(Program {
+            instructions: compiler.instructions,
+            paths: compiler.paths,
+            slots: compiler.slots,
+            max_stack: compiler.max_stack,
+            warmup: expression.warmup(),
+            time_series,
+            eval_policy: EvalPolicy::default(),
+        })
+    }
+
+    pub fn with_eval_policy(mut self, eval_policy: EvalPolicy) -> Self {
+        self.eval_policy = eval_policy;
+        self
+    }
+
+    pub fn eval_policy(&self) -> EvalPolicy {
+        self.eval_policy
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    // Variable names in slot order, `run` expects its values in this order
+    pub fn slots(&self) -> &[String] {
+        &self.slots
+    }
+
+    // See Expression::warmup
+    pub fn warmup(&self) -> usize {
+        self.warmup
+    }
+
+    pub fn is_time_series(&self) -> bool {
+        self.time_series
+    }
+
+    pub fn run(&self, values: &[f64]) -> Result<f64, EvalError> {
+        self.run_with(values, self.eval_policy)
+    }
+
+    fn run_with(&self, values: &[f64], policy: EvalPolicy) -> Result<f64, EvalError> {
+        if values.len() != self.slots.len() {
+            return Err(EvalErrorKind::InvalidInput.into());
+        }
+        if self.time_series {
+            return Err(EvalErrorKind::UnsupportedOperation.into());
+        }
+
+        let mut stack: Vec<f64> = Vec::with_capacity(self.max_stack);
+        for (at, instruction) in self.instructions.iter().enumerate() {
+            match instruction {
+                Instruction::Constant(value) => stack.push(*value),
+                Instruction::Load(slot) => stack.push(values[*slot]),
+                Instruction::Call { operands, .. } => {
+                    let base = stack.len() - operands;
+                    let value = self.apply(at, policy, &stack[base..])?;
+                    stack.truncate(base);
+                    stack.push(value);
+                }
+            }
+        }
+
+        stack
+            .pop()
+            .ok_or_else(|| EvalErrorKind::InvalidInput.into())
+    }
+
+    pub fn run_context(&self, context: &Context) -> Result<f64, EvalError> {
+        let values = self
+            .slots
+            .iter()
+            .map(|name| context.try_get_variable_value(name))
+            .collect::<Result<Vec<f64>, EvalError>>()?;
+        self.run_with(&values, context.eval_policy())
+    }
+
+    pub fn run_columns(&self, context: &ColumnContext) -> Result<Vec<f64>, EvalError> {
+        let columns = self
+            .slots
+            .iter()
+            .map(|name| context.load_column(name))
+            .collect::<Result<Vec<Vec<f64>>, EvalError>>()?;
+        let columns: Vec<&[f64]> = columns.iter().map(|column| column.as_slice()).collect();
+
+        self.run_slices_with(&columns, context.len(), context.eval_policy())
+    }
+
+    // Columnar run over columns given in slot order, each instruction is applied to whole
+    // columns at a time and column buffers are recycled between instructions
+    pub fn run_slices(&self, columns: &[&[f64]], len: usize) -> Result<Vec<f64>, EvalError> {
+        self.run_slices_with(columns, len, self.eval_policy)
+    }
+
+    fn run_slices_with(
+        &self,
+        columns: &[&[f64]],
+        len: usize,
+        policy: EvalPolicy,
+    ) -> Result<Vec<f64>, EvalError> {
+        if columns.len() != self.slots.len() || columns.iter().any(|column| column.len() != len) {
+            return Err(EvalErrorKind::InvalidInput.into());
+        }
+
+        let mut stack: Vec<Vec<f64>> = Vec::with_capacity(self.max_stack);
+        let mut pool: Vec<Vec<f64>> = Vec::new();
+
+        for (at, instruction) in self.instructions.iter().enumerate() {
+            match instruction {
+                Instruction::Constant(value) => {
+                    let mut buffer = take_buffer(&mut pool);
+                    buffer.resize(len, *value);
+                    stack.push(buffer);
+                }
+                Instruction::Load(slot) => {
+                    let mut buffer = take_buffer(&mut pool);
+                    buffer.extend_from_slice(columns[*slot]);
+                    stack.push(buffer);
+                }
+                Instruction::Call { function, operands } => {
+                    let base = stack.len() - operands;
+                    let result = match operands {
+                        _ if function.is_time_series() => {
+                            let buffer = apply_rolling(function, &stack[base..], len);
+                            pool.extend(stack.drain(base..));
+                            buffer
+                        }
+                        0 => {
+                            let mut buffer = take_buffer(&mut pool);
+                            buffer.resize(len, self.apply(at, policy, &[])?);
+                            buffer
+                        }
+                        1 => {
+                            let mut buffer = stack.pop().unwrap_or_default();
+                            for value in buffer.iter_mut() {
+                                *value = self.apply(at, policy, &[*value])?;
+                            }
+                            buffer
+                        }
+                        2 => {
+                            let rhs = stack.pop().unwrap_or_default();
+                            let mut lhs = stack.pop().unwrap_or_default();
+                            for (l, r) in lhs.iter_mut().zip(&rhs) {
+                                *l = self.apply(at, policy, &[*l, *r])?;
+                            }
+                            pool.push(rhs);
+                            lhs
+                        }
+                        width => {
+                            let mut buffer = take_buffer(&mut pool);
+                            let mut row = Vec::with_capacity(*width);
+                            for i in 0..len {
+                                row.clear();
+                                row.extend(stack[base..].iter().map(|column| column[i]));
+                                buffer.push(self.apply(at, policy, &row)?);
+                            }
+                            pool.extend(stack.drain(base..));
+                            buffer
+                        }
+                    };
+                    stack.push(result);
+                }
+            }
+        }
+
+        stack
+            .pop()
+            .ok_or_else(|| EvalErrorKind::InvalidInput.into())
+    }
+
+    // Applies the Call at instruction `at`
+    fn apply(&self, at: usize, policy: EvalPolicy, operands: &[f64]) -> Result<f64, EvalError> {
+        let Instruction::Call { function, .. } = &self.instructions[at] else {
+            return Err(EvalErrorKind::InvalidInput.into());
+        };
+        function
+            .apply_policy(policy, operands)
+            .map_err(|kind| EvalError {
+                kind,
+                path: self.paths[at].clone(),
+                operator: Some(function.clone()),
+            })
+    }
+}
+
+impl Expression {
+    pub fn compile(&self) -> Result<Program, EvalError> {
+        Program::compile(self)
+    }
+}
+
+struct Compiler {
+    instructions: Vec<Instruction>,
+    paths: Vec<Vec<usize>>,
+    // Path of the node being emitted
+    path: Vec<usize>,
+    slots: Vec<String>,
+    slot_index: HashMap<String, usize>,
+    depth: usize,
+    max_stack: usize,
+}
+
+impl Compiler {
+    fn push(&mut self, instruction: Instruction, popped: usize) {
+        self.depth = self.depth - popped + 1;
+        self.max_stack = self.max_stack.max(self.depth);
+        self.instructions.push(instruction);
+        self.paths.push(self.path.clone());
+    }
+
+    fn emit(&mut self, expression: &Expression) -> Result<(), EvalError> {
+        match expression {
+            Expression::Terminal(TerminalData::Constant(value)) => {
+                self.push(Instruction::Constant(*value), 0);
+            }
+            Expression::Terminal(TerminalData::Variable(name)) => {
+                let slot = match self.slot_index.get(name) {
+                    Some(slot) => *slot,
+                    None => {
+                        self.slots.push(name.clone());
+                        self.slot_index.insert(name.clone(), self.slots.len() - 1);
+                        self.slots.len() - 1
+                    }
+                };
+                self.push(Instruction::Load(slot), 0);
+            }
+            Expression::Operation(function_node) => {
+                function_node.check_operands()?;
+                if function_node.operation.is_cross_sectional() {
+                    return Err(function_node.error(EvalErrorKind::UnsupportedOperation));
+                }
+                for (index, operand) in function_node.operands.iter().enumerate() {
+                    self.path.push(index);
+                    let emitted = self.emit(operand);
+                    self.path.pop();
+                    emitted.map_err(|e| e.within(index))?;
+                }
+                let operands = function_node.operands.len();
+                self.push(
+                    Instruction::Call {
+                        function: function_node.operation.clone(),
+                        operands,
+                    },
+                    operands,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn take_buffer(pool: &mut Vec<Vec<f64>>) -> Vec<f64> {
+    let mut buffer = pool.pop().unwrap_or_default();
+    buffer.clear();
+    buffer
+}
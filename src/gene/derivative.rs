@@ -0,0 +1,390 @@
+use super::{EvalError, EvalErrorKind, Expression, FunctionData, FunctionNode, TerminalData};
+
+//NOTE: Symbolic derivatives. Constant slots count the constants of the tree in pre-order (the order
+// Display prints them), slot 0 is the first. Terms that are zero by construction (the derivative of
+// an unrelated terminal or of a piecewise constant function such as sign, the comparisons or rank)
+// are dropped while building, the result is then run through the exact Simplifier. Min, max, clip
+// and ts_min/ts_max take the derivative of the operand they pick, ties pick the first. Derivatives
+// of the cross-sectional statistics need the number of symbols N, it is computed as
+// 1 / scale(0 * x + 1) so that symbols where x is NaN are skipped like in the statistic itself.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffTarget {
+    Variable(String),
+    Constant(usize),
+}
+
+impl Expression {
+    pub fn derivative(&self, target: &DiffTarget) -> Result<Expression, EvalError> {
+        let mut slot = 0;
+        let derivative = differentiate(self, target, &mut slot)?;
+        if let DiffTarget::Constant(target_slot) = target {
+            if *target_slot >= slot {
+                return Err(EvalErrorKind::InvalidInput.into());
+            }
+        }
+
+        let (simplified, _) = derivative.unwrap_or_else(|| number(0.0)).simplify();
+        Ok(simplified)
+    }
+}
+
+// None is a derivative that is zero by construction
+fn differentiate(
+    expression: &Expression,
+    target: &DiffTarget,
+    slot: &mut usize,
+) -> Result<Option<Expression>, EvalError> {
+    let node = match expression {
+        Expression::Terminal(TerminalData::Constant(_)) => {
+            *slot += 1;
+            let selected = *target == DiffTarget::Constant(*slot - 1);
+            return Ok(selected.then(|| number(1.0)));
+        }
+        Expression::Terminal(TerminalData::Variable(name)) => {
+            let selected = matches!(target, DiffTarget::Variable(variable) if variable == name);
+            return Ok(selected.then(|| number(1.0)));
+        }
+        Expression::Operation(node) => node,
+    };
+    node.check_operands()?;
+
+    // Every operand is visited so that the constant slots stay in pre-order
+    let mut derivatives = node
+        .operands
+        .iter()
+        .enumerate()
+        .map(|(index, operand)| differentiate(operand, target, slot).map_err(|e| e.within(index)))
+        .collect::<Result<Vec<Option<Expression>>, EvalError>>()?;
+    if derivatives.iter().all(Option::is_none) {
+        return Ok(None);
+    }
+
+    let operands = node.operands.as_slice();
+    let x = || operands[0].clone();
+    let dx = derivatives[0].take();
+    let unary =
+        |function: &FunctionData, d: Option<Expression>| d.map(|d| call(function.clone(), vec![d]));
+
+    let derivative = match &node.operation {
+        FunctionData::Add => sum(derivatives_with(dx, derivatives)),
+        FunctionData::Subtract => difference(dx, derivatives[1].take()),
+        FunctionData::Multiply => {
+            let derivatives = derivatives_with(dx, derivatives);
+            sum(derivatives
+                .into_iter()
+                .enumerate()
+                .map(|(index, d)| {
+                    d.map(|d| {
+                        let mut factors = operands.to_vec();
+                        factors[index] = d;
+                        call(FunctionData::Multiply, factors)
+                    })
+                })
+                .collect())
+        }
+        // (a' b - a b') / b^2
+        FunctionData::Divide => {
+            let (a, b) = (x(), operands[1].clone());
+            difference(
+                dx.map(|d| divide(d, b.clone())),
+                derivatives[1]
+                    .take()
+                    .map(|d| divide(multiply(vec![a, d]), multiply(vec![b.clone(), b]))),
+            )
+        }
+        // a^b (b' log(a) + b a' / a), a constant exponent avoids the log of a negative base
+        FunctionData::Exponent => {
+            let (a, b) = (x(), operands[1].clone());
+            let power = call(FunctionData::Exponent, vec![a.clone(), b.clone()]);
+            match (dx, derivatives[1].take()) {
+                (Some(da), None) => Some(multiply(vec![
+                    b.clone(),
+                    call(
+                        FunctionData::Exponent,
+                        vec![a, call(FunctionData::Subtract, vec![b, number(1.0)])],
+                    ),
+                    da,
+                ])),
+                (None, Some(db)) => Some(multiply(vec![power, log(a), db])),
+                (da, db) => sum(vec![
+                    db.map(|db| multiply(vec![db, log(a.clone())])),
+                    da.map(|da| divide(multiply(vec![b, da]), a)),
+                ])
+                .map(|inner| multiply(vec![power, inner])),
+            }
+        }
+        FunctionData::Sqrt => dx.map(|d| {
+            divide(
+                d,
+                multiply(vec![number(2.0), call(FunctionData::Sqrt, vec![x()])]),
+            )
+        }),
+        // x^(1/degree) / (degree * x)
+        FunctionData::Root { degree } => dx.map(|d| {
+            divide(
+                multiply(vec![call(node.operation.clone(), vec![x()]), d]),
+                multiply(vec![number(*degree), x()]),
+            )
+        }),
+        FunctionData::Log => dx.map(|d| divide(d, x())),
+        FunctionData::Exp => dx.map(|d| multiply(vec![call(FunctionData::Exp, vec![x()]), d])),
+        FunctionData::Abs => dx.map(|d| multiply(vec![call(FunctionData::Sign, vec![x()]), d])),
+        FunctionData::Neg => unary(&FunctionData::Neg, dx),
+        FunctionData::Tanh => dx.map(|d| {
+            let tanh = call(FunctionData::Tanh, vec![x()]);
+            multiply(vec![
+                call(
+                    FunctionData::Subtract,
+                    vec![number(1.0), multiply(vec![tanh.clone(), tanh])],
+                ),
+                d,
+            ])
+        }),
+        FunctionData::Sigmoid => dx.map(|d| {
+            let sigmoid = call(FunctionData::Sigmoid, vec![x()]);
+            multiply(vec![
+                sigmoid.clone(),
+                call(FunctionData::Subtract, vec![number(1.0), sigmoid]),
+                d,
+            ])
+        }),
+        FunctionData::Min | FunctionData::Max => {
+            let order = if node.operation == FunctionData::Min {
+                FunctionData::LessOrEqual
+            } else {
+                FunctionData::GreaterOrEqual
+            };
+            let condition = call(order, operands.to_vec());
+            Some(choose(condition, dx, derivatives[1].take()))
+        }
+        FunctionData::Clip { lo, hi } => dx.map(|d| {
+            let below = call(FunctionData::LessThan, vec![x(), number(*hi)]);
+            let above = call(FunctionData::GreaterThan, vec![x(), number(*lo)]);
+            choose(above, Some(choose(below, Some(d), None)), None)
+        }),
+        FunctionData::IfThenElse => Some(choose(x(), derivatives[1].take(), derivatives[2].take())),
+        FunctionData::Sign
+        | FunctionData::GreaterThan
+        | FunctionData::LessThan
+        | FunctionData::GreaterOrEqual
+        | FunctionData::LessOrEqual
+        | FunctionData::Equal
+        | FunctionData::NotEqual
+        | FunctionData::TsRank { .. }
+        | FunctionData::TsArgMax { .. }
+        | FunctionData::Rank => None,
+
+        FunctionData::Delay { .. }
+        | FunctionData::Delta { .. }
+        | FunctionData::TsMean { .. }
+        | FunctionData::Decay { .. }
+        | FunctionData::Demean
+        | FunctionData::IndustryNeutralize { .. } => unary(&node.operation, dx),
+        // w / (w - 1) * (mean(x x') - mean(x) mean(x')) / std(x)
+        FunctionData::TsStd { window } => dx.map(|d| {
+            let mean = |e: Expression| call(FunctionData::TsMean { window: *window }, vec![e]);
+            divide(
+                multiply(vec![
+                    number(*window as f64 / (*window as f64 - 1.0)),
+                    covariance(mean, x(), d),
+                ]),
+                call(node.operation.clone(), vec![x()]),
+            )
+        }),
+        // The derivative of the value ts_argmax picks, ts_min picks the maximum of -x
+        FunctionData::TsMin { window } | FunctionData::TsMax { window } => dx.map(|d| {
+            let picked = match node.operation {
+                FunctionData::TsMin { .. } => call(FunctionData::Neg, vec![x()]),
+                _ => x(),
+            };
+            let argmax = call(FunctionData::TsArgMax { window: *window }, vec![picked]);
+            let terms = (0..*window)
+                .map(|lag| {
+                    let lagged = match lag {
+                        0 => d.clone(),
+                        _ => call(FunctionData::Delay { window: lag }, vec![d.clone()]),
+                    };
+                    let selected = call(
+                        FunctionData::Equal,
+                        vec![argmax.clone(), number(lag as f64)],
+                    );
+                    // A NaN or inf derivative at a lag that is not picked stays out of the sum
+                    Some(choose(selected, Some(lagged), None))
+                })
+                .collect();
+            sum(terms).unwrap_or_else(|| number(0.0))
+        }),
+        // corr = C / sqrt(Vx Vy), corr' = C' / sqrt(Vx Vy) - corr / 2 (Vx' / Vx + Vy' / Vy) with
+        // C, Vx and Vy the population (co)variances over the window
+        FunctionData::TsCorr { window } => {
+            let mean = |e: Expression| call(FunctionData::TsMean { window: *window }, vec![e]);
+            let (y, dy) = (operands[1].clone(), derivatives[1].take());
+            let variance = |e: Expression| covariance(mean, e.clone(), e);
+
+            let d_covariance = sum(vec![
+                dx.clone().map(|d| covariance(mean, d, y.clone())),
+                dy.clone().map(|d| covariance(mean, x(), d)),
+            ]);
+            let relative = |e: Expression, d: Option<Expression>| {
+                d.map(|d| {
+                    divide(
+                        multiply(vec![number(2.0), covariance(mean, e.clone(), d)]),
+                        variance(e),
+                    )
+                })
+            };
+            let d_variances = sum(vec![relative(x(), dx), relative(y.clone(), dy)]);
+
+            let deviation = call(
+                FunctionData::Sqrt,
+                vec![multiply(vec![variance(x()), variance(y.clone())])],
+            );
+            difference(
+                d_covariance.map(|d| divide(d, deviation)),
+                d_variances.map(|d| {
+                    multiply(vec![
+                        number(0.5),
+                        call(node.operation.clone(), operands.to_vec()),
+                        d,
+                    ])
+                }),
+            )
+        }
+        // (demean(x') - zscore(x) s') / s with s the sample deviation across symbols and
+        // s' = N / (N - 1) (mean(x x') - mean(x) mean(x')) / s
+        FunctionData::ZScore => dx.map(|d| {
+            let correction = divide(
+                number(1.0),
+                call(
+                    FunctionData::Subtract,
+                    vec![number(1.0), inverse_count(x())],
+                ),
+            );
+            let deviation = call(
+                FunctionData::Sqrt,
+                vec![multiply(vec![
+                    correction.clone(),
+                    covariance(cross_mean, x(), x()),
+                ])],
+            );
+            let d_deviation = divide(
+                multiply(vec![correction, covariance(cross_mean, x(), d.clone())]),
+                deviation.clone(),
+            );
+            divide(
+                call(
+                    FunctionData::Subtract,
+                    vec![
+                        call(FunctionData::Demean, vec![d]),
+                        multiply(vec![call(FunctionData::ZScore, vec![x()]), d_deviation]),
+                    ],
+                ),
+                deviation,
+            )
+        }),
+        // (x' / N - scale(x) mean(sign(x) x')) / mean(|x|)
+        FunctionData::Scale => dx.map(|d| {
+            let signed = multiply(vec![call(FunctionData::Sign, vec![x()]), d.clone()]);
+            divide(
+                call(
+                    FunctionData::Subtract,
+                    vec![
+                        multiply(vec![d, inverse_count(x())]),
+                        multiply(vec![
+                            call(FunctionData::Scale, vec![x()]),
+                            cross_mean(signed),
+                        ]),
+                    ],
+                ),
+                cross_mean(call(FunctionData::Abs, vec![x()])),
+            )
+        }),
+    };
+    Ok(derivative)
+}
+
+fn derivatives_with(
+    first: Option<Expression>,
+    mut derivatives: Vec<Option<Expression>>,
+) -> Vec<Option<Expression>> {
+    derivatives[0] = first;
+    derivatives
+}
+
+// mean(a b) - mean(a) mean(b) for a windowed or cross-sectional mean
+fn covariance(mean: impl Fn(Expression) -> Expression, a: Expression, b: Expression) -> Expression {
+    call(
+        FunctionData::Subtract,
+        vec![
+            mean(multiply(vec![a.clone(), b.clone()])),
+            multiply(vec![mean(a), mean(b)]),
+        ],
+    )
+}
+
+fn cross_mean(expression: Expression) -> Expression {
+    call(
+        FunctionData::Subtract,
+        vec![
+            expression.clone(),
+            call(FunctionData::Demean, vec![expression]),
+        ],
+    )
+}
+
+// 1 / N over the symbols where `expression` is not NaN
+fn inverse_count(expression: Expression) -> Expression {
+    let one = call(
+        FunctionData::Add,
+        vec![multiply(vec![number(0.0), expression]), number(1.0)],
+    );
+    call(FunctionData::Scale, vec![one])
+}
+
+fn choose(condition: Expression, a: Option<Expression>, b: Option<Expression>) -> Expression {
+    call(
+        FunctionData::IfThenElse,
+        vec![
+            condition,
+            a.unwrap_or_else(|| number(0.0)),
+            b.unwrap_or_else(|| number(0.0)),
+        ],
+    )
+}
+
+fn sum(terms: Vec<Option<Expression>>) -> Option<Expression> {
+    let mut terms: Vec<Expression> = terms.into_iter().flatten().collect();
+    match terms.len() {
+        0 => None,
+        1 => terms.pop(),
+        _ => Some(call(FunctionData::Add, terms)),
+    }
+}
+
+fn difference(a: Option<Expression>, b: Option<Expression>) -> Option<Expression> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(call(FunctionData::Subtract, vec![a, b])),
+        (Some(a), None) => Some(a),
+        (None, b) => b.map(|b| call(FunctionData::Neg, vec![b])),
+    }
+}
+
+fn multiply(factors: Vec<Expression>) -> Expression {
+    call(FunctionData::Multiply, factors)
+}
+
+fn divide(a: Expression, b: Expression) -> Expression {
+    call(FunctionData::Divide, vec![a, b])
+}
+
+fn log(expression: Expression) -> Expression {
+    call(FunctionData::Log, vec![expression])
+}
+
+fn number(value: f64) -> Expression {
+    Expression::Terminal(TerminalData::Constant(value))
+}
+
+fn call(function: FunctionData, operands: Vec<Expression>) -> Expression {
+    Expression::Operation(FunctionNode::new(function, operands))
+}
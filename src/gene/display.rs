@@ -0,0 +1,170 @@
+use super::{Expression, FunctionData, FunctionNode, TerminalData};
+use std::fmt;
+
+//NOTE: Canonical infix form, the exact inverse of `parser`. Parentheses are only emitted where the
+// parser would otherwise build a different tree, so parse(print(e)) reproduces e and printing is
+// idempotent. Nested Add/Multiply nodes keep their parentheses since the parser flattens chains.
+// Nodes that have no infix form (or the wrong operand count for one) are written as calls.
+
+const ADDITIVE: u8 = 1;
+const MULTIPLICATIVE: u8 = 2;
+const UNARY: u8 = 3;
+const POWER: u8 = 4;
+const PRIMARY: u8 = 5;
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Terminal(TerminalData::Constant(value)) => write_number(f, *value),
+            Expression::Terminal(TerminalData::Variable(name)) => write_variable(f, name),
+            Expression::Operation(node) => write_operation(f, node),
+        }
+    }
+}
+
+fn precedence(expression: &Expression) -> u8 {
+    match expression {
+        Expression::Terminal(TerminalData::Constant(value)) if value.is_sign_negative() => {
+            if value.is_nan() {
+                PRIMARY
+            } else {
+                UNARY
+            }
+        }
+        Expression::Terminal(_) => PRIMARY,
+        Expression::Operation(node) => operation_precedence(node),
+    }
+}
+
+fn operation_precedence(node: &FunctionNode) -> u8 {
+    match (&node.operation, node.operands.len()) {
+        (FunctionData::Add, n) if n >= 2 => ADDITIVE,
+        (FunctionData::Subtract, 2) => ADDITIVE,
+        (FunctionData::Multiply, n) if n >= 2 => MULTIPLICATIVE,
+        (FunctionData::Divide, 2) => MULTIPLICATIVE,
+        (FunctionData::Exponent, 2) => POWER,
+        _ => PRIMARY,
+    }
+}
+
+fn is_chain(expression: &Expression, function: &FunctionData) -> bool {
+    match expression {
+        Expression::Operation(node) => {
+            matches!(function, FunctionData::Add | FunctionData::Multiply)
+                && node.operation == *function
+                && node.operands.len() >= 2
+        }
+        Expression::Terminal(_) => false,
+    }
+}
+
+fn write_operand(f: &mut fmt::Formatter<'_>, operand: &Expression, wrap: bool) -> fmt::Result {
+    if wrap {
+        write!(f, "({})", operand)
+    } else {
+        write!(f, "{}", operand)
+    }
+}
+
+fn write_operation(f: &mut fmt::Formatter<'_>, node: &FunctionNode) -> fmt::Result {
+    let operands = node.operands.as_slice();
+
+    match operation_precedence(node) {
+        ADDITIVE | MULTIPLICATIVE => {
+            let level = if matches!(node.operation, FunctionData::Add | FunctionData::Subtract) {
+                ADDITIVE
+            } else {
+                MULTIPLICATIVE
+            };
+            let symbol = match node.operation {
+                FunctionData::Add => "+",
+                FunctionData::Subtract => "-",
+                FunctionData::Multiply => "*",
+                _ => "/",
+            };
+
+            let first = &operands[0];
+            write_operand(
+                f,
+                first,
+                precedence(first) < level || is_chain(first, &node.operation),
+            )?;
+            for operand in &operands[1..] {
+                write!(f, " {} ", symbol)?;
+                write_operand(f, operand, precedence(operand) <= level)?;
+            }
+            Ok(())
+        }
+        POWER => {
+            write_operand(f, &operands[0], precedence(&operands[0]) < PRIMARY)?;
+            write!(f, " ^ ")?;
+            write_operand(f, &operands[1], precedence(&operands[1]) < UNARY)
+        }
+        _ => {
+            write!(f, "{}(", node.operation.name())?;
+            for (index, operand) in operands.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", operand)?;
+            }
+            if let FunctionData::Root { degree } = node.operation {
+                if !operands.is_empty() {
+                    write!(f, ", ")?;
+                }
+                write_number(f, degree)?;
+            }
+            if let FunctionData::Clip { lo, hi } = node.operation {
+                if !operands.is_empty() {
+                    write!(f, ", ")?;
+                }
+                write_number(f, lo)?;
+                write!(f, ", ")?;
+                write_number(f, hi)?;
+            }
+            if let FunctionData::IndustryNeutralize { group } = &node.operation {
+                if !operands.is_empty() {
+                    write!(f, ", ")?;
+                }
+                write_variable(f, group)?;
+            }
+            if let Some(window) = node.operation.window() {
+                if !operands.is_empty() {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", window)?;
+            }
+            write!(f, ")")
+        }
+    }
+}
+
+fn write_number(f: &mut fmt::Formatter<'_>, value: f64) -> fmt::Result {
+    let magnitude = value.abs();
+
+    if value.is_nan() {
+        write!(f, "nan")
+    } else if value.is_infinite() {
+        write!(f, "{}inf", if value < 0.0 { "-" } else { "" })
+    } else if magnitude != 0.0 && !(1e-5..1e16).contains(&magnitude) {
+        write!(f, "{:e}", value)
+    } else {
+        write!(f, "{}", value)
+    }
+}
+
+fn write_variable(f: &mut fmt::Formatter<'_>, name: &str) -> fmt::Result {
+    let mut chars = name.chars();
+    let plain = chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name != "nan"
+        && name != "inf";
+
+    if plain {
+        write!(f, "{}", name)
+    } else {
+        write!(f, "`{}`", name)
+    }
+}
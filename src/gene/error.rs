@@ -0,0 +1,97 @@
+use super::{Arity, FunctionData};
+use std::{error, fmt};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalErrorKind {
+    UnsupportedOperation,
+    IncorrectOperandCount { expected: Arity, found: usize },
+    InvalidInput,
+    UndefinedVariable(String),
+    UninitializedContext,
+    NegativeSqrt,
+    NonPositiveLog,
+    DivisionByZero,
+    NonFinite,
+}
+
+//NOTE: `path` holds the child indices leading from the root to the failing node, the root itself is
+// the empty path. `operator` is the function of that node, None when the error is not tied to a
+// function (undefined variables, mismatched inputs).
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    pub path: Vec<usize>,
+    pub operator: Option<FunctionData>,
+}
+
+impl EvalError {
+    pub fn new(kind: EvalErrorKind) -> Self {
+        EvalError {
+            kind,
+            path: Vec::new(),
+            operator: None,
+        }
+    }
+
+    pub fn with_operator(mut self, operator: &FunctionData) -> Self {
+        self.operator = Some(operator.clone());
+        self
+    }
+
+    // Re-roots the error one level up, `index` is the position of the failing operand in its parent
+    pub fn within(mut self, index: usize) -> Self {
+        self.path.insert(0, index);
+        self
+    }
+}
+
+impl From<EvalErrorKind> for EvalError {
+    fn from(kind: EvalErrorKind) -> Self {
+        EvalError::new(kind)
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exact(count) => write!(f, "{}", count),
+            Arity::Variadic => write!(f, "any number of"),
+        }
+    }
+}
+
+impl fmt::Display for EvalErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalErrorKind::UnsupportedOperation => write!(f, "unsupported operation"),
+            EvalErrorKind::IncorrectOperandCount { expected, found } => {
+                write!(f, "expected {} operand(s), found {}", expected, found)
+            }
+            EvalErrorKind::InvalidInput => write!(f, "invalid input"),
+            EvalErrorKind::UndefinedVariable(name) => write!(f, "undefined variable `{}`", name),
+            EvalErrorKind::UninitializedContext => write!(f, "uninitialized context"),
+            EvalErrorKind::NegativeSqrt => write!(f, "root of a negative number"),
+            EvalErrorKind::NonPositiveLog => write!(f, "logarithm of a non-positive number"),
+            EvalErrorKind::DivisionByZero => write!(f, "division by zero"),
+            EvalErrorKind::NonFinite => write!(f, "non-finite result"),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(operator) = &self.operator {
+            write!(f, " in `{}`", operator.name())?;
+        }
+        if !self.path.is_empty() {
+            let path: Vec<String> = self.path.iter().map(|index| index.to_string()).collect();
+            write!(f, " at node path {}", path.join("."))?;
+        } else if self.operator.is_some() {
+            write!(f, " at the root")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for EvalError {}
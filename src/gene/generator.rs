@@ -0,0 +1,501 @@
+use super::{
+    typing::{operand_types, output_types, NodeType, TypeEnv, TypeSet},
+    Arity, Expression, FunctionData, TerminalData,
+};
+use crate::traits::GeneticTree;
+use eyre::{eyre, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TerminalSpec {
+    Variable(String),
+    Constant(f64),
+    // Ephemeral random constant, drawn uniformly from [min, max) every time the terminal is used
+    EphemeralConstant { min: f64, max: f64 },
+}
+
+impl TerminalSpec {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TerminalData {
+        match self {
+            TerminalSpec::Variable(name) => TerminalData::Variable(name.clone()),
+            TerminalSpec::Constant(value) => TerminalData::Constant(*value),
+            TerminalSpec::EphemeralConstant { min, max } => {
+                TerminalData::Constant(rng.gen_range(*min..*max))
+            }
+        }
+    }
+
+    fn types(&self, env: &TypeEnv) -> TypeSet {
+        match self {
+            TerminalSpec::Variable(name) => {
+                env.variable_type(name).map_or(TypeSet::EMPTY, TypeSet::of)
+            }
+            _ => TypeSet::LITERAL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerationMethod {
+    // Any node above the depth limit may be a function or a terminal
+    Grow,
+    // Every branch reaches exactly the depth limit
+    Full,
+}
+
+//NOTE: Depth counts edges from the root, a lone terminal has depth 0. Variadic functions
+// (Add/Multiply) get between 2 and max_variadic_arity operands, every other function gets exactly
+// its FunctionData::arity, so generated trees always pass the operand checks in evaluate.
+// `with_types` makes generation strongly typed, see Typing.
+#[derive(Debug, Clone)]
+pub struct TreeGenerator {
+    terminals: Vec<TerminalSpec>,
+    functions: Vec<FunctionData>,
+    min_depth: usize,
+    max_depth: usize,
+    max_variadic_arity: usize,
+    typing: Option<Typing>,
+}
+
+//NOTE: Every node is built for a required NodeType: a terminal of that type or a function whose
+// output can have it with operands that can still be built within the remaining depth. The
+// operand types are then drawn from the output_type rules (see typing::operand_types). Full trees
+// may stop early where only terminals fit. The tables depend on the depth and variadic arity,
+// which is why the setters rebuild them.
+#[derive(Debug, Clone)]
+struct Typing {
+    env: TypeEnv,
+    root: NodeType,
+    // (index into TreeGenerator::functions, operand count) of every node generation may build
+    shapes: Vec<(usize, usize)>,
+    // outputs[d][i] holds the types shapes[i] can have with operands of depth at most d
+    outputs: Vec<Vec<TypeSet>>,
+    // buildable[d] holds the types of the trees of depth at most d
+    buildable: Vec<TypeSet>,
+}
+
+impl TreeGenerator {
+    pub fn new(terminals: Vec<TerminalSpec>, functions: Vec<FunctionData>) -> Result<Self> {
+        if terminals.is_empty() {
+            return Err(eyre!("TreeGenerator needs at least one terminal"));
+        }
+        for terminal in &terminals {
+            if let TerminalSpec::EphemeralConstant { min, max } = terminal {
+                if !min.is_finite() || !max.is_finite() || min >= max {
+                    return Err(eyre!("invalid ephemeral constant range [{}, {})", min, max));
+                }
+            }
+        }
+        for function in &functions {
+            if let Some(message) = function.invalid_parameters() {
+                return Err(eyre!(message));
+            }
+        }
+        // Same check as with_depth for the default min_depth of 2
+        if functions.is_empty() {
+            return Err(eyre!("min_depth 2 needs at least one function"));
+        }
+
+        Ok(TreeGenerator {
+            terminals,
+            functions,
+            min_depth: 2,
+            max_depth: 6,
+            max_variadic_arity: 2,
+            typing: None,
+        })
+    }
+
+    // Generated trees have type `root` under `env`, and every variable terminal needs a type in it
+    pub fn with_types(mut self, env: TypeEnv, root: NodeType) -> Result<Self> {
+        for terminal in &self.terminals {
+            if let TerminalSpec::Variable(name) = terminal {
+                if env.variable_type(name).is_none() {
+                    return Err(eyre!("variable `{}` has no type", name));
+                }
+            }
+        }
+        self.typing = Some(Typing {
+            env,
+            root,
+            shapes: Vec::new(),
+            outputs: Vec::new(),
+            buildable: Vec::new(),
+        });
+        self.build_typing()?;
+        Ok(self)
+    }
+
+    pub fn with_depth(mut self, min_depth: usize, max_depth: usize) -> Result<Self> {
+        if min_depth > max_depth {
+            return Err(eyre!(
+                "min_depth {} is greater than max_depth {}",
+                min_depth,
+                max_depth
+            ));
+        }
+        if min_depth > 0 && self.functions.is_empty() {
+            return Err(eyre!("min_depth {} needs at least one function", min_depth));
+        }
+        self.min_depth = min_depth;
+        self.max_depth = max_depth;
+        self.build_typing()?;
+        Ok(self)
+    }
+
+    pub fn with_max_variadic_arity(mut self, arity: usize) -> Result<Self> {
+        if arity < 2 {
+            return Err(eyre!(
+                "max_variadic_arity must be at least 2, got {}",
+                arity
+            ));
+        }
+        self.max_variadic_arity = arity;
+        self.build_typing()?;
+        Ok(self)
+    }
+
+    pub fn seeded_rng(seed: u64) -> StdRng {
+        StdRng::seed_from_u64(seed)
+    }
+
+    pub fn terminals(&self) -> &[TerminalSpec] {
+        &self.terminals
+    }
+    pub fn functions(&self) -> &[FunctionData] {
+        &self.functions
+    }
+    pub fn min_depth(&self) -> usize {
+        self.min_depth
+    }
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+    pub fn type_env(&self) -> Option<&TypeEnv> {
+        self.typing.as_ref().map(|typing| &typing.env)
+    }
+    pub fn root_type(&self) -> Option<NodeType> {
+        self.typing.as_ref().map(|typing| typing.root)
+    }
+
+    // Always true for an untyped generator
+    pub fn typechecks<T: GeneticTree>(&self, tree: &T) -> bool {
+        match &self.typing {
+            Some(typing) => typing.env.typecheck_as(tree, typing.root).is_ok(),
+            None => true,
+        }
+    }
+
+    // Type of every node in GeneticTree::subtrees order when the tree is used as the root type
+    // (TypeEnv::resolve), every entry is None for an untyped generator. None if the tree does not
+    // type-check.
+    pub fn node_types<T: GeneticTree>(&self, tree: &T) -> Option<Vec<Option<NodeType>>> {
+        match &self.typing {
+            Some(typing) => typing
+                .env
+                .resolve(tree, typing.root)
+                .ok()
+                .map(|types| types.into_iter().map(Some).collect()),
+            None => Some(vec![None; tree.node_count()]),
+        }
+    }
+
+    // Records the node types of a tree of the root type (TypeEnv::assign), false if it does not
+    // type-check. Always true for an untyped generator.
+    pub fn assign_types<T: GeneticTree>(&self, tree: &mut T) -> bool {
+        match &self.typing {
+            Some(typing) => typing.env.assign(tree, typing.root).is_ok(),
+            None => true,
+        }
+    }
+
+    pub fn generate<T: GeneticTree, R: Rng + ?Sized>(
+        &self,
+        method: GenerationMethod,
+        depth: usize,
+        annotation: &T::Annotation,
+        rng: &mut R,
+    ) -> Result<T> {
+        match &self.typing {
+            Some(typing) => self
+                .generate_typed(method, depth, typing.root, annotation, rng)
+                .ok_or_else(|| {
+                    eyre!(
+                        "no tree of type {} can be built within depth {}",
+                        typing.root,
+                        self.max_depth
+                    )
+                }),
+            None => {
+                let depth = depth.clamp(self.min_depth, self.max_depth);
+                Ok(self.build(method, 0, depth, annotation, rng))
+            }
+        }
+    }
+
+    // Tree of type `node_type`, the depth limit is raised as far as max_depth if no such tree fits
+    // within it. None if no tree of that type can be built, an untyped generator ignores the type.
+    pub fn generate_typed<T: GeneticTree, R: Rng + ?Sized>(
+        &self,
+        method: GenerationMethod,
+        depth: usize,
+        node_type: NodeType,
+        annotation: &T::Annotation,
+        rng: &mut R,
+    ) -> Option<T> {
+        let depth = depth.clamp(self.min_depth, self.max_depth);
+        let Some(typing) = &self.typing else {
+            return Some(self.build(method, 0, depth, annotation, rng));
+        };
+
+        let limit =
+            (depth..=self.max_depth).find(|limit| typing.buildable[*limit].contains(node_type))?;
+        self.build_typed(typing, method, 0, limit, node_type, annotation, rng)
+    }
+
+    pub fn grow<T: GeneticTree, R: Rng + ?Sized>(
+        &self,
+        annotation: &T::Annotation,
+        rng: &mut R,
+    ) -> Result<T> {
+        self.generate(GenerationMethod::Grow, self.max_depth, annotation, rng)
+    }
+
+    pub fn full<T: GeneticTree, R: Rng + ?Sized>(
+        &self,
+        annotation: &T::Annotation,
+        rng: &mut R,
+    ) -> Result<T> {
+        self.generate(GenerationMethod::Full, self.max_depth, annotation, rng)
+    }
+
+    //NOTE: depth limits are spread evenly over min_depth..=max_depth, each depth alternating
+    // between grow and full
+    pub fn ramped_half_and_half<T: GeneticTree, R: Rng + ?Sized>(
+        &self,
+        count: usize,
+        annotation: &T::Annotation,
+        rng: &mut R,
+    ) -> Result<Vec<T>> {
+        let depths = self.max_depth - self.min_depth + 1;
+
+        (0..count)
+            .map(|index| {
+                let depth = self.min_depth + (index / 2) % depths;
+                let method = if index % 2 == 0 {
+                    GenerationMethod::Grow
+                } else {
+                    GenerationMethod::Full
+                };
+                self.generate(method, depth, annotation, rng)
+            })
+            .collect()
+    }
+
+    pub fn random_terminal<T: GeneticTree, R: Rng + ?Sized>(
+        &self,
+        annotation: &T::Annotation,
+        rng: &mut R,
+    ) -> T {
+        let terminal = &self.terminals[rng.gen_range(0..self.terminals.len())];
+        T::terminal(annotation, terminal.sample(rng))
+    }
+
+    // None if no terminal has type `node_type`, an untyped generator ignores the type
+    pub fn random_terminal_of<T: GeneticTree, R: Rng + ?Sized>(
+        &self,
+        node_type: NodeType,
+        annotation: &T::Annotation,
+        rng: &mut R,
+    ) -> Option<T> {
+        let Some(typing) = &self.typing else {
+            return Some(self.random_terminal(annotation, rng));
+        };
+        let terminals = typing.terminals(&self.terminals, node_type);
+        if terminals.is_empty() {
+            return None;
+        }
+        let terminal = terminals[rng.gen_range(0..terminals.len())];
+        let mut node = T::terminal(annotation, terminal.sample(rng));
+        node.set_node_type(node_type);
+        Some(node)
+    }
+
+    pub fn expression<R: Rng + ?Sized>(
+        &self,
+        method: GenerationMethod,
+        rng: &mut R,
+    ) -> Result<Expression> {
+        self.generate(method, self.max_depth, &(), rng)
+    }
+
+    fn build<T: GeneticTree, R: Rng + ?Sized>(
+        &self,
+        method: GenerationMethod,
+        depth: usize,
+        limit: usize,
+        annotation: &T::Annotation,
+        rng: &mut R,
+    ) -> T {
+        let must_branch = depth < self.min_depth || method == GenerationMethod::Full;
+        let can_branch = depth < limit && !self.functions.is_empty();
+
+        let pick_function = can_branch
+            && (must_branch
+                || rng.gen_range(0..self.functions.len() + self.terminals.len())
+                    < self.functions.len());
+
+        if !pick_function {
+            return self.random_terminal(annotation, rng);
+        }
+
+        let function = self.functions[rng.gen_range(0..self.functions.len())].clone();
+        let operand_count = self.operand_count(&function, rng);
+        let children = (0..operand_count)
+            .map(|_| self.build(method, depth + 1, limit, annotation, rng))
+            .collect();
+
+        T::function(annotation, function, children)
+    }
+
+    // None if neither a terminal nor a function fits, which `buildable` rules out for the types
+    // generate_typed asks for
+    #[allow(clippy::too_many_arguments)]
+    fn build_typed<T: GeneticTree, R: Rng + ?Sized>(
+        &self,
+        typing: &Typing,
+        method: GenerationMethod,
+        depth: usize,
+        limit: usize,
+        node_type: NodeType,
+        annotation: &T::Annotation,
+        rng: &mut R,
+    ) -> Option<T> {
+        let must_branch = depth < self.min_depth || method == GenerationMethod::Full;
+        let terminals = typing.terminals(&self.terminals, node_type);
+        let budget = (limit - depth).checked_sub(1);
+        let shapes: Vec<(usize, usize)> = match budget {
+            Some(budget) => typing
+                .shapes
+                .iter()
+                .zip(&typing.outputs[budget])
+                .filter(|(_, outputs)| outputs.contains(node_type))
+                .map(|(shape, _)| *shape)
+                .collect(),
+            None => Vec::new(),
+        };
+        // Shapes are grouped by function, every function is as likely as a terminal
+        let mut functions: Vec<usize> = shapes.iter().map(|(function, _)| *function).collect();
+        functions.dedup();
+
+        let pick_function = !functions.is_empty()
+            && (must_branch
+                || terminals.is_empty()
+                || rng.gen_range(0..functions.len() + terminals.len()) < functions.len());
+
+        if !pick_function {
+            if terminals.is_empty() {
+                return None;
+            }
+            let terminal = terminals[rng.gen_range(0..terminals.len())];
+            let mut node = T::terminal(annotation, terminal.sample(rng));
+            node.set_node_type(node_type);
+            return Some(node);
+        }
+
+        let function = functions[rng.gen_range(0..functions.len())];
+        let counts: Vec<usize> = shapes
+            .iter()
+            .filter(|(index, _)| *index == function)
+            .map(|(_, count)| *count)
+            .collect();
+        let count = counts[rng.gen_range(0..counts.len())];
+        let operands = vec![typing.buildable[budget?]; count];
+        let types = operand_types(
+            &self.functions[function],
+            &operands,
+            node_type,
+            &mut |candidates| rng.gen_range(0..candidates),
+        )?;
+        let children = types
+            .into_iter()
+            .map(|operand| {
+                self.build_typed(typing, method, depth + 1, limit, operand, annotation, rng)
+            })
+            .collect::<Option<Vec<T>>>()?;
+
+        let mut node = T::function(annotation, self.functions[function].clone(), children);
+        node.set_node_type(node_type);
+        Some(node)
+    }
+
+    fn build_typing(&mut self) -> Result<()> {
+        let Some(mut typing) = self.typing.take() else {
+            return Ok(());
+        };
+
+        typing.shapes.clear();
+        for (index, function) in self.functions.iter().enumerate() {
+            let counts = match function.arity() {
+                Arity::Exact(count) => count..=count,
+                Arity::Variadic => 2..=self.max_variadic_arity,
+            };
+            typing.shapes.extend(counts.map(|count| (index, count)));
+        }
+
+        let leaves = self
+            .terminals
+            .iter()
+            .fold(TypeSet::EMPTY, |types, terminal| {
+                types.union(terminal.types(&typing.env))
+            });
+        typing.buildable = vec![leaves];
+        typing.outputs.clear();
+        for depth in 0..self.max_depth {
+            let below = typing.buildable[depth];
+            let outputs: Vec<TypeSet> = typing
+                .shapes
+                .iter()
+                .map(|(function, count)| {
+                    output_types(&self.functions[*function], &vec![below; *count])
+                })
+                .collect();
+            typing.buildable.push(
+                outputs
+                    .iter()
+                    .fold(below, |types, output| types.union(*output)),
+            );
+            typing.outputs.push(outputs);
+        }
+
+        if !typing.buildable[self.max_depth].contains(typing.root) {
+            return Err(eyre!(
+                "no tree of type {} can be built within depth {}",
+                typing.root,
+                self.max_depth
+            ));
+        }
+        self.typing = Some(typing);
+        Ok(())
+    }
+
+    fn operand_count<R: Rng + ?Sized>(&self, function: &FunctionData, rng: &mut R) -> usize {
+        match function.arity() {
+            Arity::Exact(count) => count,
+            Arity::Variadic => rng.gen_range(2..=self.max_variadic_arity),
+        }
+    }
+}
+
+impl Typing {
+    fn terminals<'a>(
+        &self,
+        terminals: &'a [TerminalSpec],
+        node_type: NodeType,
+    ) -> Vec<&'a TerminalSpec> {
+        terminals
+            .iter()
+            .filter(|terminal| terminal.types(&self.env).contains(node_type))
+            .collect()
+    }
+}
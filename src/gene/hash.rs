@@ -0,0 +1,113 @@
+use super::{FunctionData, TerminalData};
+use crate::traits::GeneticTree;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    mem,
+};
+
+//NOTE: Canonical hash of the tree structure, annotations (the Operation of a Gene) are ignored.
+// Operands of Add/Multiply are hashed as a multiset, so trees that only differ in their order hash
+// equal, note that a reordered chain of three or more operands can still round differently. All NaN
+// constants hash equal, -0 and 0 do not. The hasher has fixed keys, hashes are stable within a
+// build but should not be persisted.
+pub fn structural_hash<T: GeneticTree>(tree: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    if let Some(terminal) = tree.terminal_data() {
+        hash_terminal(terminal, &mut hasher);
+        return hasher.finish();
+    }
+
+    let Some(function) = tree.function_data() else {
+        return hasher.finish();
+    };
+    hash_function(function, &mut hasher);
+    let mut children: Vec<u64> = tree.children().into_iter().map(structural_hash).collect();
+    if matches!(function, FunctionData::Add | FunctionData::Multiply) {
+        children.sort_unstable();
+    }
+    children.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn hash_terminal<H: Hasher>(terminal: &TerminalData, hasher: &mut H) {
+    mem::discriminant(terminal).hash(hasher);
+    match terminal {
+        TerminalData::Constant(value) => hash_f64(*value, hasher),
+        TerminalData::Variable(name) => name.hash(hasher),
+    }
+}
+
+fn hash_function<H: Hasher>(function: &FunctionData, hasher: &mut H) {
+    mem::discriminant(function).hash(hasher);
+    match function {
+        FunctionData::Root { degree } => hash_f64(*degree, hasher),
+        FunctionData::Clip { lo, hi } => {
+            hash_f64(*lo, hasher);
+            hash_f64(*hi, hasher);
+        }
+        FunctionData::Delay { window }
+        | FunctionData::Delta { window }
+        | FunctionData::TsMean { window }
+        | FunctionData::TsStd { window }
+        | FunctionData::TsMin { window }
+        | FunctionData::TsMax { window }
+        | FunctionData::TsRank { window }
+        | FunctionData::TsArgMax { window }
+        | FunctionData::Decay { window }
+        | FunctionData::TsCorr { window } => window.hash(hasher),
+        FunctionData::IndustryNeutralize { group } => group.hash(hasher),
+        _ => {}
+    }
+}
+
+fn hash_f64<H: Hasher>(value: f64, hasher: &mut H) {
+    let bits = if value.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        value.to_bits()
+    };
+    bits.hash(hasher);
+}
+
+// Copy of the tree with the two operands of every binary Add/Multiply in hash order. a + b and
+// b + a are the same value, longer chains keep their order since a reordered chain can round
+// differently.
+pub fn canonical<T: GeneticTree>(tree: &T) -> T {
+    fn sort<T: GeneticTree>(node: &mut T) {
+        let commutative = matches!(
+            node.function_data(),
+            Some(FunctionData::Add | FunctionData::Multiply)
+        );
+        let mut children = node.children_mut();
+        for child in children.iter_mut() {
+            sort(&mut **child);
+        }
+        if let [a, b] = children.as_mut_slice() {
+            if commutative && structural_hash(&**b) < structural_hash(&**a) {
+                mem::swap(&mut **a, &mut **b);
+            }
+        }
+    }
+    let mut tree = tree.clone();
+    sort(&mut tree);
+    tree
+}
+
+// Same nodes in the same order, constants compared by bits (every NaN matches every NaN)
+pub fn identical<A: GeneticTree, B: GeneticTree>(a: &A, b: &B) -> bool {
+    let terminals = match (a.terminal_data(), b.terminal_data()) {
+        (Some(TerminalData::Constant(x)), Some(TerminalData::Constant(y))) => {
+            x.to_bits() == y.to_bits() || (x.is_nan() && y.is_nan())
+        }
+        (x, y) => x == y,
+    };
+    let (a_children, b_children) = (a.children(), b.children());
+    terminals
+        && a.function_data() == b.function_data()
+        && a_children.len() == b_children.len()
+        && a_children
+            .into_iter()
+            .zip(b_children)
+            .all(|(a, b)| identical(a, b))
+}
@@ -0,0 +1,894 @@
+pub mod bytecode;
+pub mod derivative;
+pub mod display;
+pub mod error;
+pub mod generator;
+pub mod hash;
+pub mod operators;
+pub mod optimize;
+pub mod panel;
+pub mod parser;
+pub mod simplify;
+pub mod timeseries;
+pub mod typing;
+pub mod vectorized;
+
+use crate::features::{ExecutionContext, Operation};
+use crate::traits::GeneticTree;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub use bytecode::{Instruction, Program};
+pub use derivative::DiffTarget;
+pub use error::{EvalError, EvalErrorKind};
+pub use optimize::{ConstantOptimizer, FitData, OptimizationMethod, Optimized};
+pub use panel::PanelContext;
+pub use parser::ParseError;
+pub use simplify::Simplifier;
+pub use typing::{NodeType, TypeEnv, TypeError, TypeErrorKind};
+pub use vectorized::{ColumnContext, NanPolicy};
+
+#[derive(Debug, Clone)]
+pub enum GeneType {
+    Terminal(TerminalData),
+    Function(FunctionData),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TerminalData {
+    Constant(#[serde(with = "non_finite_f64")] f64),
+    Variable(String),
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FunctionData {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Exponent,
+    Sqrt,
+    Root {
+        #[serde(with = "non_finite_f64")]
+        degree: f64,
+    },
+    Log,
+    Exp,
+    Abs,
+    Neg,
+    Sign,
+    Min,
+    Max,
+    Tanh,
+    Sigmoid,
+    Clip {
+        #[serde(with = "non_finite_f64")]
+        lo: f64,
+        #[serde(with = "non_finite_f64")]
+        hi: f64,
+    },
+    // Comparisons give 1 or 0
+    GreaterThan,
+    LessThan,
+    GreaterOrEqual,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    // Picks the second operand where the first is positive, the third otherwise. Both branches are
+    // still evaluated, so under EvalPolicy::Strict an error in either one fails the node.
+    IfThenElse,
+    Delay {
+        window: usize,
+    },
+    Delta {
+        window: usize,
+    },
+    TsMean {
+        window: usize,
+    },
+    TsStd {
+        window: usize,
+    },
+    TsMin {
+        window: usize,
+    },
+    TsMax {
+        window: usize,
+    },
+    TsRank {
+        window: usize,
+    },
+    TsArgMax {
+        window: usize,
+    },
+    Decay {
+        window: usize,
+    },
+    TsCorr {
+        window: usize,
+    },
+    Rank,
+    ZScore,
+    Demean,
+    Scale,
+    // Demeans within the symbols sharing a label of the panel group `group`
+    IndustryNeutralize {
+        group: String,
+    },
+}
+
+impl FunctionData {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FunctionData::Add => "add",
+            FunctionData::Subtract => "sub",
+            FunctionData::Multiply => "mul",
+            FunctionData::Divide => "div",
+            FunctionData::Exponent => "pow",
+            FunctionData::Sqrt => "sqrt",
+            FunctionData::Root { .. } => "root",
+            FunctionData::Log => "log",
+            FunctionData::Exp => "exp",
+            FunctionData::Abs => "abs",
+            FunctionData::Neg => "neg",
+            FunctionData::Sign => "sign",
+            FunctionData::Min => "min",
+            FunctionData::Max => "max",
+            FunctionData::Tanh => "tanh",
+            FunctionData::Sigmoid => "sigmoid",
+            FunctionData::Clip { .. } => "clip",
+            FunctionData::GreaterThan => "gt",
+            FunctionData::LessThan => "lt",
+            FunctionData::GreaterOrEqual => "ge",
+            FunctionData::LessOrEqual => "le",
+            FunctionData::Equal => "eq",
+            FunctionData::NotEqual => "ne",
+            FunctionData::IfThenElse => "if_then_else",
+            FunctionData::Delay { .. } => "delay",
+            FunctionData::Delta { .. } => "delta",
+            FunctionData::TsMean { .. } => "ts_mean",
+            FunctionData::TsStd { .. } => "ts_std",
+            FunctionData::TsMin { .. } => "ts_min",
+            FunctionData::TsMax { .. } => "ts_max",
+            FunctionData::TsRank { .. } => "ts_rank",
+            FunctionData::TsArgMax { .. } => "ts_argmax",
+            FunctionData::Decay { .. } => "decay",
+            FunctionData::TsCorr { .. } => "ts_corr",
+            FunctionData::Rank => "rank",
+            FunctionData::ZScore => "zscore",
+            FunctionData::Demean => "demean",
+            FunctionData::Scale => "scale",
+            FunctionData::IndustryNeutralize { .. } => "industry_neutralize",
+        }
+    }
+
+    //NOTE: pointwise kernel shared by all evaluators, callers check the operand count first.
+    // Time-series and cross-sectional functions need their operands' history or the whole universe
+    // and go through `apply_window`/`evaluate_panel` instead, calling this on them is a bug. Like
+    // the arithmetic functions, min/max and comparisons are NaN if any operand is, if_then_else only
+    // if the condition or the picked branch is.
+    pub fn apply(&self, operands: &[f64]) -> f64 {
+        if self.propagates_nan() && operands.iter().any(|operand| operand.is_nan()) {
+            return f64::NAN;
+        }
+
+        match self {
+            FunctionData::Add => operands.iter().sum(),
+            FunctionData::Subtract => operands[0] - operands[1],
+            FunctionData::Multiply => operands.iter().product(),
+            FunctionData::Divide => operands[0] / operands[1],
+            FunctionData::Exponent => operands[0].powf(operands[1]),
+            FunctionData::Sqrt => operands[0].sqrt(),
+            FunctionData::Root { degree } => Expression::custom_root(operands[0], *degree),
+            FunctionData::Log => operands[0].ln(),
+            FunctionData::Exp => operands[0].exp(),
+            FunctionData::Abs => operands[0].abs(),
+            FunctionData::Neg => -operands[0],
+            // signum is 1 for +0
+            FunctionData::Sign if operands[0] == 0.0 => 0.0,
+            FunctionData::Sign => operands[0].signum(),
+            FunctionData::Min => operands[0].min(operands[1]),
+            FunctionData::Max => operands[0].max(operands[1]),
+            FunctionData::Tanh => operands[0].tanh(),
+            FunctionData::Sigmoid => 1.0 / (1.0 + (-operands[0]).exp()),
+            FunctionData::Clip { lo, hi } => clip(operands[0], *lo, *hi),
+            FunctionData::GreaterThan => truth(operands[0] > operands[1]),
+            FunctionData::LessThan => truth(operands[0] < operands[1]),
+            FunctionData::GreaterOrEqual => truth(operands[0] >= operands[1]),
+            FunctionData::LessOrEqual => truth(operands[0] <= operands[1]),
+            FunctionData::Equal => truth(operands[0] == operands[1]),
+            FunctionData::NotEqual => truth(operands[0] != operands[1]),
+            FunctionData::IfThenElse if operands[0].is_nan() => f64::NAN,
+            FunctionData::IfThenElse if operands[0] > 0.0 => operands[1],
+            FunctionData::IfThenElse => operands[2],
+            FunctionData::Delay { .. }
+            | FunctionData::Delta { .. }
+            | FunctionData::TsMean { .. }
+            | FunctionData::TsStd { .. }
+            | FunctionData::TsMin { .. }
+            | FunctionData::TsMax { .. }
+            | FunctionData::TsRank { .. }
+            | FunctionData::TsArgMax { .. }
+            | FunctionData::Decay { .. }
+            | FunctionData::TsCorr { .. }
+            | FunctionData::Rank
+            | FunctionData::ZScore
+            | FunctionData::Demean
+            | FunctionData::Scale
+            | FunctionData::IndustryNeutralize { .. } => {
+                unreachable!("`{}` is not a pointwise function", self.name())
+            }
+        }
+    }
+
+    pub fn apply_policy(&self, policy: EvalPolicy, operands: &[f64]) -> Result<f64, EvalErrorKind> {
+        match policy {
+            EvalPolicy::Ieee => Ok(self.apply(operands)),
+            EvalPolicy::Protected => Ok(self.apply_protected(operands)),
+            EvalPolicy::Strict => self.apply_checked(operands),
+        }
+    }
+
+    // Division by zero gives 1, roots, logs and powers of negative numbers are taken of |x|, log(0)
+    // gives 0
+    pub fn apply_protected(&self, operands: &[f64]) -> f64 {
+        match self {
+            FunctionData::Divide if operands[1] == 0.0 => 1.0,
+            FunctionData::Exponent if operands[0] == 0.0 && operands[1] < 0.0 => 1.0,
+            FunctionData::Exponent if operands[0] < 0.0 => {
+                let value = operands[0].powf(operands[1]);
+                if value.is_nan() && !operands[1].is_nan() {
+                    operands[0].abs().powf(operands[1])
+                } else {
+                    value
+                }
+            }
+            FunctionData::Sqrt => operands[0].abs().sqrt(),
+            FunctionData::Root { degree } => Expression::custom_root(operands[0].abs(), *degree),
+            FunctionData::Log if operands[0] == 0.0 => 0.0,
+            FunctionData::Log => operands[0].abs().ln(),
+            function => function.apply(operands),
+        }
+    }
+
+    // Like apply, but domain errors are reported instead of turning into NaN/inf. Non-finite
+    // operands (e.g. a warm-up NaN) pass through as in apply.
+    pub fn apply_checked(&self, operands: &[f64]) -> Result<f64, EvalErrorKind> {
+        let value = self.apply(operands);
+        if value.is_finite() || operands.iter().any(|operand| !operand.is_finite()) {
+            return Ok(value);
+        }
+
+        match self {
+            FunctionData::Divide if operands[1] == 0.0 => Err(EvalErrorKind::DivisionByZero),
+            FunctionData::Sqrt | FunctionData::Root { .. } if value.is_nan() => {
+                Err(EvalErrorKind::NegativeSqrt)
+            }
+            FunctionData::Log => Err(EvalErrorKind::NonPositiveLog),
+            _ => Err(EvalErrorKind::NonFinite),
+        }
+    }
+
+    pub fn arity(&self) -> Arity {
+        match self {
+            FunctionData::Add | FunctionData::Multiply => Arity::Variadic,
+            FunctionData::Subtract
+            | FunctionData::Divide
+            | FunctionData::Exponent
+            | FunctionData::Min
+            | FunctionData::Max
+            | FunctionData::GreaterThan
+            | FunctionData::LessThan
+            | FunctionData::GreaterOrEqual
+            | FunctionData::LessOrEqual
+            | FunctionData::Equal
+            | FunctionData::NotEqual
+            | FunctionData::TsCorr { .. } => Arity::Exact(2),
+            FunctionData::IfThenElse => Arity::Exact(3),
+            FunctionData::Sqrt
+            | FunctionData::Root { .. }
+            | FunctionData::Log
+            | FunctionData::Exp
+            | FunctionData::Abs
+            | FunctionData::Neg
+            | FunctionData::Sign
+            | FunctionData::Tanh
+            | FunctionData::Sigmoid
+            | FunctionData::Clip { .. }
+            | FunctionData::Delay { .. }
+            | FunctionData::Delta { .. }
+            | FunctionData::TsMean { .. }
+            | FunctionData::TsStd { .. }
+            | FunctionData::TsMin { .. }
+            | FunctionData::TsMax { .. }
+            | FunctionData::TsRank { .. }
+            | FunctionData::TsArgMax { .. }
+            | FunctionData::Decay { .. }
+            | FunctionData::Rank
+            | FunctionData::ZScore
+            | FunctionData::Demean
+            | FunctionData::Scale
+            | FunctionData::IndustryNeutralize { .. } => Arity::Exact(1),
+        }
+    }
+
+    // Computed across the symbols of a panel at each timestamp, see PanelContext
+    pub fn is_cross_sectional(&self) -> bool {
+        matches!(
+            self,
+            FunctionData::Rank
+                | FunctionData::ZScore
+                | FunctionData::Demean
+                | FunctionData::Scale
+                | FunctionData::IndustryNeutralize { .. }
+        )
+    }
+
+    // Lookback window of a time-series function, None for pointwise functions
+    pub fn window(&self) -> Option<usize> {
+        match self {
+            FunctionData::Delay { window }
+            | FunctionData::Delta { window }
+            | FunctionData::TsMean { window }
+            | FunctionData::TsStd { window }
+            | FunctionData::TsMin { window }
+            | FunctionData::TsMax { window }
+            | FunctionData::TsRank { window }
+            | FunctionData::TsArgMax { window }
+            | FunctionData::Decay { window }
+            | FunctionData::TsCorr { window } => Some(*window),
+            _ => None,
+        }
+    }
+
+    pub fn is_time_series(&self) -> bool {
+        self.window().is_some()
+    }
+
+    // Rows of operand history read for one output row, the current row included
+    pub fn span(&self) -> usize {
+        match self {
+            FunctionData::Delay { window } | FunctionData::Delta { window } => window + 1,
+            function => function.window().unwrap_or(1),
+        }
+    }
+
+    // Leading output rows that are NaN because the first full window is not available yet
+    pub fn warmup(&self) -> usize {
+        self.span().saturating_sub(1)
+    }
+
+    // f64::min/max skip NaN and comparisons are false for it, these have to check their operands
+    fn propagates_nan(&self) -> bool {
+        matches!(
+            self,
+            FunctionData::Min
+                | FunctionData::Max
+                | FunctionData::GreaterThan
+                | FunctionData::LessThan
+                | FunctionData::GreaterOrEqual
+                | FunctionData::LessOrEqual
+                | FunctionData::Equal
+                | FunctionData::NotEqual
+        )
+    }
+
+    // Parameters the operand checks reject, None if the function is well formed
+    fn invalid_parameters(&self) -> Option<String> {
+        match self {
+            FunctionData::Root { degree } if *degree <= 0.0 => {
+                Some(format!("Root degree must be positive, got {}", degree))
+            }
+            FunctionData::Clip { lo, hi } if !(lo <= hi) => Some(format!(
+                "Clip bounds must satisfy lo <= hi, got [{}, {}]",
+                lo, hi
+            )),
+            function => match function.window() {
+                Some(window) if window < function.min_window() => Some(format!(
+                    "`{}` window must be at least {}, got {}",
+                    function.name(),
+                    function.min_window(),
+                    window
+                )),
+                _ => None,
+            },
+        }
+    }
+
+    // Std, rank and correlation are undefined over a single value
+    fn min_window(&self) -> usize {
+        match self {
+            FunctionData::TsStd { .. }
+            | FunctionData::TsRank { .. }
+            | FunctionData::TsCorr { .. } => 2,
+            _ => 1,
+        }
+    }
+}
+
+//NOTE: How the pointwise functions treat domain violations (x / 0, sqrt of a negative, overflow).
+// Ieee returns whatever f64 arithmetic gives (NaN/inf), Protected substitutes a finite value as is
+// usual in GP, Strict fails with the matching EvalErrorKind. Only pointwise nodes follow the policy:
+// time-series and cross-sectional functions always follow IEEE semantics, e.g. ts_corr over a
+// constant window or the warm-up rows are NaN under Strict and Protected as well, while pointwise
+// nodes below and above them still apply the policy. Strict passes NaN/inf operands through, so such
+// a NaN does not fail higher up either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EvalPolicy {
+    #[default]
+    Ieee,
+    Protected,
+    Strict,
+}
+
+//NOTE: mirrors the operand count checks in Expression::evaluate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    Variadic,
+}
+
+impl Arity {
+    pub fn accepts(&self, operand_count: usize) -> bool {
+        match self {
+            Arity::Exact(count) => *count == operand_count,
+            Arity::Variadic => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionNode {
+    operation: FunctionData,
+    operands: Vec<Expression>,
+}
+
+impl FunctionNode {
+    pub fn new(operation: FunctionData, operands: Vec<Expression>) -> Self {
+        FunctionNode {
+            operation,
+            operands,
+        }
+    }
+    pub fn operation(&self) -> &FunctionData {
+        &self.operation
+    }
+    pub fn operands(&self) -> &[Expression] {
+        &self.operands
+    }
+
+    fn check_operands(&self) -> Result<(), EvalError> {
+        if self.operation.invalid_parameters().is_some() {
+            return Err(self.error(EvalErrorKind::InvalidInput));
+        }
+        let expected = self.operation.arity();
+        if !expected.accepts(self.operands.len()) {
+            return Err(self.error(EvalErrorKind::IncorrectOperandCount {
+                expected,
+                found: self.operands.len(),
+            }));
+        }
+        Ok(())
+    }
+
+    // Error raised at this node, the caller adds the path to it
+    fn error(&self, kind: EvalErrorKind) -> EvalError {
+        EvalError::new(kind).with_operator(&self.operation)
+    }
+}
+
+pub struct Context {
+    variables: HashMap<String, f64>,
+    eval_policy: EvalPolicy,
+}
+impl Context {
+    pub fn new() -> Self {
+        Self {
+            variables: HashMap::new(),
+            eval_policy: EvalPolicy::default(),
+        }
+    }
+    pub fn with_variables(variables: HashMap<String, f64>) -> Self {
+        Self {
+            variables,
+            eval_policy: EvalPolicy::default(),
+        }
+    }
+    pub fn with_eval_policy(mut self, eval_policy: EvalPolicy) -> Self {
+        self.eval_policy = eval_policy;
+        self
+    }
+    pub fn eval_policy(&self) -> EvalPolicy {
+        self.eval_policy
+    }
+    pub fn push_kv(&mut self, name: &str, value: f64) {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    pub fn try_get_variable_value(&self, name: &str) -> Result<f64, EvalError> {
+        self.variables
+            .get(name)
+            .cloned()
+            .ok_or_else(|| EvalErrorKind::UndefinedVariable(name.to_string()).into())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Expression {
+    Terminal(TerminalData),
+    Operation(FunctionNode),
+}
+
+impl Expression {
+    pub fn evaluate(&self, context: &Context) -> Result<f64, EvalError> {
+        match self {
+            Expression::Terminal(TerminalData::Constant(value)) => Ok(*value),
+            Expression::Terminal(TerminalData::Variable(name)) => {
+                Ok(context.try_get_variable_value(name)?)
+            }
+            Expression::Operation(function_node) => {
+                function_node.check_operands()?;
+                // A single row has no history or universe, see evaluate_at and evaluate_panel
+                if function_node.operation.is_time_series()
+                    || function_node.operation.is_cross_sectional()
+                {
+                    return Err(function_node.error(EvalErrorKind::UnsupportedOperation));
+                }
+                let op_values = function_node
+                    .operands
+                    .iter()
+                    .enumerate()
+                    .map(|(index, op)| op.evaluate(context).map_err(|e| e.within(index)))
+                    .collect::<Result<Vec<f64>, EvalError>>()?;
+
+                function_node
+                    .operation
+                    .apply_policy(context.eval_policy, &op_values)
+                    .map_err(|kind| function_node.error(kind))
+            }
+        }
+    }
+
+    pub fn custom_root(x: f64, root: f64) -> f64 {
+        x.powf(1.0 / root)
+    }
+}
+
+// NaN stays NaN, unlike f64::clamp this does not panic on bad bounds, check_operands rejects them
+fn clip(value: f64, lo: f64, hi: f64) -> f64 {
+    if value < lo {
+        lo
+    } else if value > hi {
+        hi
+    } else {
+        value
+    }
+}
+
+fn truth(condition: bool) -> f64 {
+    if condition {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+impl GeneticTree for Expression {
+    type Annotation = ();
+
+    fn terminal(_: &Self::Annotation, data: TerminalData) -> Self {
+        Expression::Terminal(data)
+    }
+    fn function(_: &Self::Annotation, func: FunctionData, children: Vec<Self>) -> Self {
+        Expression::Operation(FunctionNode::new(func, children))
+    }
+
+    fn annotation(&self) -> Self::Annotation {}
+    fn terminal_data(&self) -> Option<&TerminalData> {
+        match self {
+            Expression::Terminal(data) => Some(data),
+            Expression::Operation(_) => None,
+        }
+    }
+    fn terminal_data_mut(&mut self) -> Option<&mut TerminalData> {
+        match self {
+            Expression::Terminal(data) => Some(data),
+            Expression::Operation(_) => None,
+        }
+    }
+    fn function_data(&self) -> Option<&FunctionData> {
+        match self {
+            Expression::Operation(node) => Some(&node.operation),
+            Expression::Terminal(_) => None,
+        }
+    }
+    fn function_data_mut(&mut self) -> Option<&mut FunctionData> {
+        match self {
+            Expression::Operation(node) => Some(&mut node.operation),
+            Expression::Terminal(_) => None,
+        }
+    }
+    fn children(&self) -> Vec<&Self> {
+        match self {
+            Expression::Operation(node) => node.operands.iter().collect(),
+            Expression::Terminal(_) => Vec::new(),
+        }
+    }
+    fn children_mut(&mut self) -> Vec<&mut Self> {
+        match self {
+            Expression::Operation(node) => node.operands.iter_mut().collect(),
+            Expression::Terminal(_) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Gene {
+    op: Operation,
+    gene_type: GeneType,
+    // Set by typed generation and TypeEnv::assign
+    node_type: Option<NodeType>,
+    context: Option<usize>,
+    children: Vec<Box<Gene>>,
+}
+
+impl Gene {
+    pub fn operation(&self) -> &Operation {
+        &self.op
+    }
+    pub fn gene_type(&self) -> &GeneType {
+        &self.gene_type
+    }
+    pub fn context(&self) -> Option<usize> {
+        self.context
+    }
+    pub fn node_type(&self) -> Option<NodeType> {
+        self.node_type
+    }
+    pub fn with_context(mut self, context: Option<usize>) -> Self {
+        self.context = context;
+        self
+    }
+
+    fn new_terminal(op: Operation, value: f64) -> Gene {
+        Gene {
+            op,
+            gene_type: GeneType::Terminal(TerminalData::Constant(value)),
+            node_type: None,
+            context: None,
+            children: Vec::new(),
+        }
+    }
+    fn new_function_gene(
+        op: Operation,
+        func: FunctionData,
+        children: Vec<Box<Gene>>,
+        context: Option<usize>,
+    ) -> Gene {
+        Gene {
+            op,
+            gene_type: GeneType::Function(func),
+            node_type: None,
+            context,
+            children,
+        }
+    }
+}
+
+impl GeneticTree for Gene {
+    type Annotation = Operation;
+
+    fn terminal(op: &Self::Annotation, data: TerminalData) -> Self {
+        Gene {
+            op: op.clone(),
+            gene_type: GeneType::Terminal(data),
+            node_type: None,
+            context: None,
+            children: Vec::new(),
+        }
+    }
+    fn function(op: &Self::Annotation, func: FunctionData, children: Vec<Self>) -> Self {
+        Gene::new_function_gene(
+            op.clone(),
+            func,
+            children.into_iter().map(Box::new).collect(),
+            None,
+        )
+    }
+
+    fn annotation(&self) -> Self::Annotation {
+        self.op.clone()
+    }
+    fn terminal_data(&self) -> Option<&TerminalData> {
+        match &self.gene_type {
+            GeneType::Terminal(data) => Some(data),
+            GeneType::Function(_) => None,
+        }
+    }
+    fn terminal_data_mut(&mut self) -> Option<&mut TerminalData> {
+        match &mut self.gene_type {
+            GeneType::Terminal(data) => Some(data),
+            GeneType::Function(_) => None,
+        }
+    }
+    fn function_data(&self) -> Option<&FunctionData> {
+        match &self.gene_type {
+            GeneType::Function(func) => Some(func),
+            GeneType::Terminal(_) => None,
+        }
+    }
+    fn function_data_mut(&mut self) -> Option<&mut FunctionData> {
+        match &mut self.gene_type {
+            GeneType::Function(func) => Some(func),
+            GeneType::Terminal(_) => None,
+        }
+    }
+    fn children(&self) -> Vec<&Self> {
+        self.children.iter().map(|child| child.as_ref()).collect()
+    }
+    fn children_mut(&mut self) -> Vec<&mut Self> {
+        self.children
+            .iter_mut()
+            .map(|child| child.as_mut())
+            .collect()
+    }
+    fn set_node_type(&mut self, node_type: NodeType) {
+        self.node_type = Some(node_type);
+    }
+}
+
+//NOTE: Expression is the evaluated form of a Gene. Gene -> Expression keeps the tree but not the
+// per-node Operation, context and node type, a plain Expression -> Gene gives every node Operation::Custom
+// and neither of the others. Going through to_annotated / from_annotated keeps them in a GeneAnnotations
+// side table, so that round trip gives back the Gene it started from.
+impl From<&Gene> for Expression {
+    fn from(gene: &Gene) -> Self {
+        convert(gene, &())
+    }
+}
+
+impl From<Gene> for Expression {
+    fn from(gene: Gene) -> Self {
+        Expression::from(&gene)
+    }
+}
+
+impl From<Expression> for Gene {
+    fn from(expression: Expression) -> Self {
+        Gene::from_expression(Operation::Custom, &expression)
+    }
+}
+
+impl Gene {
+    pub fn from_expression(op: Operation, expression: &Expression) -> Gene {
+        convert(expression, &op)
+    }
+
+    pub fn to_expression(&self) -> Expression {
+        Expression::from(self)
+    }
+
+    pub fn to_annotated(&self) -> (Expression, GeneAnnotations) {
+        (self.to_expression(), self.annotations())
+    }
+
+    // Nodes the table has no entry for get `op` and no context or node type
+    pub fn from_annotated(
+        op: Operation,
+        expression: &Expression,
+        annotations: &GeneAnnotations,
+    ) -> Gene {
+        let mut gene = Gene::from_expression(op, expression);
+        for (path, node) in annotations.nodes.iter() {
+            if let Some(target) = gene.get_subtree_mut(path) {
+                target.op = node.op.clone();
+                target.context = node.context;
+                target.node_type = node.node_type;
+            }
+        }
+        gene
+    }
+
+    pub fn annotations(&self) -> GeneAnnotations {
+        let nodes = self
+            .subtrees()
+            .into_iter()
+            .map(|(path, gene)| {
+                let node = NodeAnnotation {
+                    op: gene.op.clone(),
+                    context: gene.context,
+                    node_type: gene.node_type,
+                };
+                (path, node)
+            })
+            .collect();
+        GeneAnnotations { nodes }
+    }
+
+    pub fn evaluate(&self, context: &Context) -> Result<f64, EvalError> {
+        self.to_expression().evaluate(context)
+    }
+
+    pub fn evaluate_columns(&self, context: &ColumnContext) -> Result<Vec<f64>, EvalError> {
+        self.to_expression().evaluate_columns(context)
+    }
+}
+
+// What a Gene node carries beyond its Expression node
+#[derive(Debug, Clone)]
+pub struct NodeAnnotation {
+    pub op: Operation,
+    pub context: Option<usize>,
+    pub node_type: Option<NodeType>,
+}
+
+// Per-node annotations of a Gene keyed by node path, see Gene::to_annotated
+#[derive(Debug, Clone, Default)]
+pub struct GeneAnnotations {
+    nodes: HashMap<Vec<usize>, NodeAnnotation>,
+}
+
+impl GeneAnnotations {
+    pub fn get(&self, path: &[usize]) -> Option<&NodeAnnotation> {
+        self.nodes.get(path)
+    }
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+fn convert<A: GeneticTree, B: GeneticTree>(tree: &A, annotation: &B::Annotation) -> B {
+    if let Some(function) = tree.function_data() {
+        let children = tree
+            .children()
+            .into_iter()
+            .map(|child| convert(child, annotation))
+            .collect();
+        return B::function(annotation, function.clone(), children);
+    }
+    // Every node that is not a function is a terminal
+    let data = tree
+        .terminal_data()
+        .cloned()
+        .unwrap_or(TerminalData::Constant(f64::NAN));
+    B::terminal(annotation, data)
+}
+
+//NOTE: serde_json has no representation for NaN/inf, they are written as strings so that trees
+// holding non-finite constants still round-trip
+mod non_finite_f64 {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            serializer.serialize_f64(*value)
+        } else if value.is_nan() {
+            serializer.serialize_str("NaN")
+        } else if value.is_sign_positive() {
+            serializer.serialize_str("inf")
+        } else {
+            serializer.serialize_str("-inf")
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Number(f64),
+            Text(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Number(value) => Ok(value),
+            Repr::Text(text) => match text.as_str() {
+                "NaN" => Ok(f64::NAN),
+                "inf" => Ok(f64::INFINITY),
+                "-inf" => Ok(f64::NEG_INFINITY),
+                other => Err(de::Error::custom(format!("invalid constant `{}`", other))),
+            },
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use super::{
+    generator::{GenerationMethod, TreeGenerator},
+    TerminalData,
+};
+use crate::traits::GeneticTree;
+use rand::Rng;
+use std::f64::consts::PI;
+
+//NOTE: Every operator draws all of its randomness from the rng that is passed in, so results are
+// reproducible under a seeded rng. Offspring that would break max_depth/max_size are rejected and
+// the draw is repeated up to max_attempts times, after which the parent is returned unchanged.
+// The nodes of a parent are listed once per call (GeneticTree::subtrees) and addressed by their
+// path. With a typed generator the parents' node types are resolved once per call as well
+// (TreeGenerator::node_types): subtrees are only exchanged, replaced or hoisted where their types
+// match, offspring that do not type-check are rejected the same way and the rest get their node
+// types recorded. Parents that do not type-check are returned unchanged.
+#[derive(Debug, Clone)]
+pub struct GeneticOperators {
+    generator: TreeGenerator,
+    max_depth: usize,
+    max_size: usize,
+    perturbation_scale: f64,
+    max_attempts: usize,
+}
+
+impl GeneticOperators {
+    pub fn new(generator: TreeGenerator) -> Self {
+        GeneticOperators {
+            max_depth: generator.max_depth().max(1) * 2,
+            max_size: 128,
+            perturbation_scale: 0.1,
+            max_attempts: 16,
+            generator,
+        }
+    }
+
+    pub fn with_limits(mut self, max_depth: usize, max_size: usize) -> Self {
+        self.max_depth = max_depth;
+        self.max_size = max_size.max(1);
+        self
+    }
+
+    // Standard deviation of constant perturbation, relative to max(|c|, 1)
+    pub fn with_perturbation_scale(mut self, scale: f64) -> Self {
+        self.perturbation_scale = scale;
+        self
+    }
+
+    pub fn with_max_attempts(mut self, attempts: usize) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    pub fn generator(&self) -> &TreeGenerator {
+        &self.generator
+    }
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn within_limits<T: GeneticTree>(&self, tree: &T) -> bool {
+        tree.depth() <= self.max_depth && tree.node_count() <= self.max_size
+    }
+
+    // Within limits and, with a typed generator, of its root type
+    pub fn admits<T: GeneticTree>(&self, tree: &T) -> bool {
+        self.within_limits(tree) && self.generator.typechecks(tree)
+    }
+
+    // Swaps a random subtree of `a` with a random subtree of `b`
+    pub fn subtree_crossover<T: GeneticTree, R: Rng + ?Sized>(
+        &self,
+        a: &T,
+        b: &T,
+        rng: &mut R,
+    ) -> (T, T) {
+        let (a_nodes, b_nodes) = (a.subtrees(), b.subtrees());
+        let (a_size, b_size) = (a_nodes.len(), b_nodes.len());
+        let (Some(a_types), Some(b_types)) =
+            (self.generator.node_types(a), self.generator.node_types(b))
+        else {
+            return (a.clone(), b.clone());
+        };
+
+        for _ in 0..self.max_attempts {
+            let a_index = rng.gen_range(0..a_size);
+            let matching: Vec<usize> = (0..b_size)
+                .filter(|index| b_types[*index] == a_types[a_index])
+                .collect();
+            if matching.is_empty() {
+                continue;
+            }
+            let (a_path, a_sub) = &a_nodes[a_index];
+            let (b_path, b_sub) = &b_nodes[matching[rng.gen_range(0..matching.len())]];
+            let (a_sub_size, b_sub_size) = (a_sub.node_count(), b_sub.node_count());
+
+            let fits = a_path.len() + b_sub.depth() <= self.max_depth
+                && b_path.len() + a_sub.depth() <= self.max_depth
+                && a_size - a_sub_size + b_sub_size <= self.max_size
+                && b_size - b_sub_size + a_sub_size <= self.max_size;
+            if !fits {
+                continue;
+            }
+
+            let mut first = a.clone();
+            let mut second = b.clone();
+            first.replace_subtree(a_path, (*b_sub).clone());
+            second.replace_subtree(b_path, (*a_sub).clone());
+            if self.generator.assign_types(&mut first) && self.generator.assign_types(&mut second) {
+                return (first, second);
+            }
+        }
+
+        (a.clone(), b.clone())
+    }
+
+    // Replaces the FunctionData of a random function node with another function from the
+    // generator's set that accepts the same number of operands
+    pub fn point_mutation<T: GeneticTree, R: Rng + ?Sized>(&self, tree: &T, rng: &mut R) -> T {
+        let mut candidates = Vec::new();
+        for (path, node) in tree.subtrees() {
+            if let Some(current) = node.function_data() {
+                let operand_count = node.children().len();
+                let replacements: Vec<_> = self
+                    .generator
+                    .functions()
+                    .iter()
+                    .filter(|f| *f != current && f.arity().accepts(operand_count))
+                    .cloned()
+                    .collect();
+                if !replacements.is_empty() {
+                    candidates.push((path, replacements));
+                }
+            }
+        }
+
+        if candidates.is_empty() {
+            return tree.clone();
+        }
+
+        for _ in 0..self.max_attempts {
+            let (path, replacements) = &candidates[rng.gen_range(0..candidates.len())];
+            let replacement = replacements[rng.gen_range(0..replacements.len())].clone();
+
+            let mut mutated = tree.clone();
+            if let Some(func) = mutated
+                .get_subtree_mut(path)
+                .and_then(|node| node.function_data_mut())
+            {
+                *func = replacement;
+            }
+            if self.generator.assign_types(&mut mutated) {
+                return mutated;
+            }
+        }
+        tree.clone()
+    }
+
+    // Replaces a random subtree with a freshly grown one
+    pub fn subtree_mutation<T: GeneticTree, R: Rng + ?Sized>(&self, tree: &T, rng: &mut R) -> T {
+        let nodes = tree.subtrees();
+        let size = nodes.len();
+        let Some(types) = self.generator.node_types(tree) else {
+            return tree.clone();
+        };
+
+        for _ in 0..self.max_attempts {
+            let index = rng.gen_range(0..size);
+            let (path, target) = &nodes[index];
+            let depth = path.len();
+            let budget = self.max_depth.saturating_sub(depth);
+            let replacement: Option<T> = match types[index] {
+                Some(node_type) => self.generator.generate_typed(
+                    GenerationMethod::Grow,
+                    budget,
+                    node_type,
+                    &target.annotation(),
+                    rng,
+                ),
+                None => self
+                    .generator
+                    .generate(GenerationMethod::Grow, budget, &target.annotation(), rng)
+                    .ok(),
+            };
+            let Some(replacement) = replacement else {
+                continue;
+            };
+
+            if depth + replacement.depth() > self.max_depth
+                || size - target.node_count() + replacement.node_count() > self.max_size
+            {
+                continue;
+            }
+
+            let mut mutated = tree.clone();
+            mutated.replace_subtree(path, replacement);
+            if self.generator.assign_types(&mut mutated) {
+                return mutated;
+            }
+        }
+
+        tree.clone()
+    }
+
+    // Picks a random function node, then lifts one of its own subtrees into its place
+    pub fn hoist_mutation<T: GeneticTree, R: Rng + ?Sized>(&self, tree: &T, rng: &mut R) -> T {
+        let nodes = tree.subtrees();
+        let branches = branches(&nodes);
+        if branches.is_empty() {
+            return tree.clone();
+        }
+        let Some(types) = self.generator.node_types(tree) else {
+            return tree.clone();
+        };
+
+        for _ in 0..self.max_attempts {
+            let index = branches[rng.gen_range(0..branches.len())];
+            let (path, target) = &nodes[index];
+            // The subtrees of a node follow it in pre-order
+            let below: Vec<usize> = (index + 1..index + target.node_count())
+                .filter(|below| types[*below] == types[index])
+                .collect();
+            if below.is_empty() {
+                continue;
+            }
+            let (_, hoisted) = nodes[below[rng.gen_range(0..below.len())]];
+
+            let mut mutated = tree.clone();
+            mutated.replace_subtree(path, hoisted.clone());
+            if self.generator.assign_types(&mut mutated) {
+                return mutated;
+            }
+        }
+        tree.clone()
+    }
+
+    // Collapses a random function node into a random terminal
+    pub fn shrink_mutation<T: GeneticTree, R: Rng + ?Sized>(&self, tree: &T, rng: &mut R) -> T {
+        let nodes = tree.subtrees();
+        let branches = branches(&nodes);
+        if branches.is_empty() {
+            return tree.clone();
+        }
+        let Some(types) = self.generator.node_types(tree) else {
+            return tree.clone();
+        };
+
+        for _ in 0..self.max_attempts {
+            let index = branches[rng.gen_range(0..branches.len())];
+            let (path, target) = &nodes[index];
+            let terminal = match types[index] {
+                Some(node_type) => {
+                    self.generator
+                        .random_terminal_of(node_type, &target.annotation(), rng)
+                }
+                None => Some(self.generator.random_terminal(&target.annotation(), rng)),
+            };
+            let Some(terminal) = terminal else {
+                continue;
+            };
+
+            let mut mutated = tree.clone();
+            mutated.replace_subtree(path, terminal);
+            if self.generator.assign_types(&mut mutated) {
+                return mutated;
+            }
+        }
+        tree.clone()
+    }
+
+    // Adds gaussian noise with sd perturbation_scale * max(|c|, 1) to one random constant
+    pub fn constant_perturbation<T: GeneticTree, R: Rng + ?Sized>(
+        &self,
+        tree: &T,
+        rng: &mut R,
+    ) -> T {
+        let constants: Vec<Vec<usize>> = tree
+            .subtrees()
+            .into_iter()
+            .filter(|(_, node)| {
+                matches!(node.terminal_data(), Some(TerminalData::Constant(value)) if value.is_finite())
+            })
+            .map(|(path, _)| path)
+            .collect();
+
+        if constants.is_empty() {
+            return tree.clone();
+        }
+        let path = &constants[rng.gen_range(0..constants.len())];
+        let noise = standard_normal(rng);
+
+        let mut mutated = tree.clone();
+        if let Some(TerminalData::Constant(value)) = mutated
+            .get_subtree_mut(path)
+            .and_then(|node| node.terminal_data_mut())
+        {
+            *value += noise * self.perturbation_scale * value.abs().max(1.0);
+        }
+        mutated
+    }
+}
+
+// Indices of the function nodes with at least one operand
+fn branches<T: GeneticTree>(nodes: &[(Vec<usize>, &T)]) -> Vec<usize> {
+    (0..nodes.len())
+        .filter(|index| {
+            let node = nodes[*index].1;
+            node.function_data().is_some() && !node.children().is_empty()
+        })
+        .collect()
+}
+
+// Box-Muller, rand 0.8 has no normal distribution without rand_distr
+fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
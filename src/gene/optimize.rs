@@ -0,0 +1,480 @@
+use super::{ColumnContext, DiffTarget, EvalError, EvalErrorKind, Expression, TerminalData};
+
+//NOTE: Fits the constants of a tree to a target series by least squares, the parameters are the
+// constants in pre-order (the DiffTarget::Constant slots). Rows in the warm-up or where the target
+// is not finite are left out, a non-finite prediction on any other row makes the loss infinite so
+// the step that produced it is rejected. LevenbergMarquardt builds its Jacobian from the symbolic
+// derivatives, taken once per fit with the constants standing in as parameters, and leaves rows
+// where a partial derivative is not finite (e.g. at a kink) out of each step. NelderMead only
+// evaluates the tree. The fitted constants are never worse than the original ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptimizationMethod {
+    LevenbergMarquardt,
+    NelderMead,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConstantOptimizer {
+    method: OptimizationMethod,
+    max_iterations: usize,
+    tolerance: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Optimized {
+    pub expression: Expression,
+    pub constants: Vec<f64>,
+    // Sums of squared residuals before and after the fit
+    pub initial_loss: f64,
+    pub loss: f64,
+    pub iterations: usize,
+}
+
+impl ConstantOptimizer {
+    pub fn new(method: OptimizationMethod) -> Self {
+        ConstantOptimizer {
+            method,
+            max_iterations: 100,
+            tolerance: 1e-10,
+        }
+    }
+
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    // Stops once an iteration improves the loss by less than this fraction
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn method(&self) -> OptimizationMethod {
+        self.method
+    }
+    pub fn max_iterations(&self) -> usize {
+        self.max_iterations
+    }
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    pub fn optimize(
+        &self,
+        expression: &Expression,
+        context: &ColumnContext,
+        target: &[f64],
+    ) -> Result<Optimized, EvalError> {
+        if target.len() != context.len() {
+            return Err(EvalErrorKind::InvalidInput.into());
+        }
+        // Reports undefined variables and malformed nodes up front
+        expression.evaluate_columns(context)?;
+
+        let rows: Vec<usize> = (expression.warmup()..target.len())
+            .filter(|row| target[*row].is_finite())
+            .collect();
+        if rows.is_empty() {
+            return Err(EvalErrorKind::InvalidInput.into());
+        }
+        let derivatives = match self.method {
+            OptimizationMethod::LevenbergMarquardt => parameter_derivatives(expression),
+            OptimizationMethod::NelderMead => None,
+        };
+        let problem = Problem {
+            expression,
+            context,
+            target,
+            rows,
+            derivatives,
+        };
+
+        let initial = expression.constants();
+        let initial_loss = problem.loss(&initial);
+        let (constants, loss, iterations) = match (initial.is_empty(), self.method) {
+            (true, _) => (initial, initial_loss, 0),
+            (false, OptimizationMethod::LevenbergMarquardt) => {
+                self.levenberg_marquardt(&problem, initial, initial_loss)
+            }
+            (false, OptimizationMethod::NelderMead) => {
+                self.nelder_mead(&problem, initial, initial_loss)
+            }
+        };
+
+        Ok(Optimized {
+            expression: expression.with_constants(&constants)?,
+            constants,
+            initial_loss,
+            loss,
+            iterations,
+        })
+    }
+
+    fn converged(&self, previous: f64, current: f64) -> bool {
+        current == 0.0 || previous - current <= self.tolerance * previous
+    }
+
+    fn levenberg_marquardt(
+        &self,
+        problem: &Problem,
+        mut constants: Vec<f64>,
+        mut loss: f64,
+    ) -> (Vec<f64>, f64, usize) {
+        let mut damping = 1e-3;
+        let mut iterations = 0;
+
+        while iterations < self.max_iterations {
+            iterations += 1;
+            let (Some(residuals), Some(jacobian)) =
+                (problem.residuals(&constants), problem.jacobian(&constants))
+            else {
+                break;
+            };
+
+            // Normal equations (J^T J + damping * diag(J^T J)) step = -J^T r over the rows where
+            // every partial derivative is finite
+            let usable: Vec<usize> = (0..residuals.len())
+                .filter(|row| jacobian.iter().all(|column| column[*row].is_finite()))
+                .collect();
+            if usable.is_empty() {
+                break;
+            }
+            let over_usable =
+                |a: &[f64], b: &[f64]| -> f64 { usable.iter().map(|row| a[*row] * b[*row]).sum() };
+            let size = constants.len();
+            let mut normal = vec![vec![0.0; size]; size];
+            let mut gradient = vec![0.0; size];
+            for i in 0..size {
+                for j in 0..size {
+                    normal[i][j] = over_usable(&jacobian[i], &jacobian[j]);
+                }
+                gradient[i] = -over_usable(&jacobian[i], &residuals);
+            }
+
+            let mut improved = None;
+            while damping < 1e16 {
+                let mut damped = normal.clone();
+                for (i, row) in damped.iter_mut().enumerate() {
+                    row[i] += damping * normal[i][i].max(1e-12);
+                }
+                if let Some(step) = solve(damped, gradient.clone()) {
+                    let candidate: Vec<f64> =
+                        constants.iter().zip(&step).map(|(c, s)| c + s).collect();
+                    let candidate_loss = problem.loss(&candidate);
+                    if candidate_loss < loss {
+                        damping = (damping / 10.0).max(1e-12);
+                        improved = Some((candidate, candidate_loss));
+                        break;
+                    }
+                }
+                damping *= 10.0;
+            }
+
+            let Some((candidate, candidate_loss)) = improved else {
+                break;
+            };
+            let converged = self.converged(loss, candidate_loss);
+            constants = candidate;
+            loss = candidate_loss;
+            if converged {
+                break;
+            }
+        }
+        (constants, loss, iterations)
+    }
+
+    // Standard reflection (1), expansion (2), contraction (0.5) and shrink (0.5) coefficients, the
+    // initial simplex moves each constant by 5% (0.00025 for a zero)
+    fn nelder_mead(
+        &self,
+        problem: &Problem,
+        constants: Vec<f64>,
+        loss: f64,
+    ) -> (Vec<f64>, f64, usize) {
+        let mut simplex = vec![(constants.clone(), loss)];
+        for i in 0..constants.len() {
+            let mut vertex = constants.clone();
+            vertex[i] = if vertex[i] == 0.0 {
+                0.00025
+            } else {
+                vertex[i] * 1.05
+            };
+            let vertex_loss = problem.loss(&vertex);
+            simplex.push((vertex, vertex_loss));
+        }
+
+        let mut iterations = 0;
+        while iterations < self.max_iterations {
+            simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+            let (best, worst) = (simplex[0].1, simplex[simplex.len() - 1].1);
+            if !best.is_finite() || self.converged(worst, best) && worst.is_finite() {
+                break;
+            }
+            iterations += 1;
+
+            let last = simplex.len() - 1;
+            let mut centroid = vec![0.0; constants.len()];
+            for (vertex, _) in &simplex[..last] {
+                for (c, v) in centroid.iter_mut().zip(vertex) {
+                    *c += v / last as f64;
+                }
+            }
+            let towards = |from: &[f64], scale: f64| -> Vec<f64> {
+                centroid
+                    .iter()
+                    .zip(from)
+                    .map(|(c, v)| c + scale * (v - c))
+                    .collect()
+            };
+
+            let reflected = towards(&simplex[last].0, -1.0);
+            let reflected_loss = problem.loss(&reflected);
+            if reflected_loss < best {
+                let expanded = towards(&reflected, 2.0);
+                let expanded_loss = problem.loss(&expanded);
+                simplex[last] = if expanded_loss < reflected_loss {
+                    (expanded, expanded_loss)
+                } else {
+                    (reflected, reflected_loss)
+                };
+                continue;
+            }
+            if reflected_loss < simplex[last - 1].1 {
+                simplex[last] = (reflected, reflected_loss);
+                continue;
+            }
+
+            let (contracted, bound) = if reflected_loss < worst {
+                (towards(&reflected, 0.5), reflected_loss)
+            } else {
+                (towards(&simplex[last].0, 0.5), worst)
+            };
+            let contracted_loss = problem.loss(&contracted);
+            if contracted_loss < bound {
+                simplex[last] = (contracted, contracted_loss);
+                continue;
+            }
+
+            let anchor = simplex[0].0.clone();
+            for (vertex, vertex_loss) in simplex.iter_mut().skip(1) {
+                for (v, a) in vertex.iter_mut().zip(&anchor) {
+                    *v = a + 0.5 * (*v - a);
+                }
+                *vertex_loss = problem.loss(vertex);
+            }
+        }
+
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (constants, loss) = simplex.swap_remove(0);
+        (constants, loss, iterations)
+    }
+}
+
+struct Problem<'a, 'c> {
+    expression: &'a Expression,
+    context: &'a ColumnContext<'c>,
+    target: &'a [f64],
+    rows: Vec<usize>,
+    // d/dc_k of the tree with its constants as parameter variables, see parameter_derivatives
+    derivatives: Option<Vec<Expression>>,
+}
+
+impl Problem<'_, '_> {
+    // None if the tree fails or predicts a non-finite value on a fitted row
+    fn residuals(&self, constants: &[f64]) -> Option<Vec<f64>> {
+        let prediction = self
+            .expression
+            .with_constants(constants)
+            .and_then(|tree| tree.evaluate_columns(self.context))
+            .ok()?;
+        self.rows
+            .iter()
+            .map(|row| Some(prediction[*row] - self.target[*row]).filter(|r| r.is_finite()))
+            .collect()
+    }
+
+    fn loss(&self, constants: &[f64]) -> f64 {
+        self.residuals(constants)
+            .map_or(f64::INFINITY, |residuals| dot(&residuals, &residuals))
+    }
+
+    // One column per constant over the fitted rows, non-finite partial derivatives are kept
+    fn jacobian(&self, constants: &[f64]) -> Option<Vec<Vec<f64>>> {
+        let derivatives = self.derivatives.as_ref()?;
+        let parameters: Vec<Vec<f64>> = constants
+            .iter()
+            .map(|constant| vec![*constant; self.context.len()])
+            .collect();
+        let mut context: ColumnContext = self.context.clone();
+        for (slot, values) in parameters.iter().enumerate() {
+            context.push_column(&parameter(slot), values).ok()?;
+        }
+
+        derivatives
+            .iter()
+            .map(|derivative| {
+                let values = derivative.evaluate_columns(&context).ok()?;
+                Some(self.rows.iter().map(|row| values[*row]).collect())
+            })
+            .collect()
+    }
+}
+
+// Name of the variable that stands in for constant slot `slot`, not one the parser can produce
+fn parameter(slot: usize) -> String {
+    format!("#c{}", slot)
+}
+
+// The partial derivatives of the tree with respect to each of its constants. The constants are
+// replaced by parameter variables first, so the derivatives can be evaluated for any constants
+// without being taken again (simplification would otherwise fold the constants into them).
+fn parameter_derivatives(expression: &Expression) -> Option<Vec<Expression>> {
+    fn replace(expression: &mut Expression, slot: &mut usize) {
+        match expression {
+            Expression::Terminal(TerminalData::Constant(_)) => {
+                *expression = Expression::Terminal(TerminalData::Variable(parameter(*slot)));
+                *slot += 1;
+            }
+            Expression::Terminal(TerminalData::Variable(_)) => {}
+            Expression::Operation(node) => {
+                for operand in node.operands.iter_mut() {
+                    replace(operand, slot);
+                }
+            }
+        }
+    }
+    let mut parameterized = expression.clone();
+    let mut slots = 0;
+    replace(&mut parameterized, &mut slots);
+    (0..slots)
+        .map(|slot| {
+            parameterized
+                .derivative(&DiffTarget::Variable(parameter(slot)))
+                .ok()
+        })
+        .collect()
+}
+
+impl Expression {
+    // Constants in pre-order, the DiffTarget::Constant slots
+    pub fn constants(&self) -> Vec<f64> {
+        fn collect(expression: &Expression, constants: &mut Vec<f64>) {
+            match expression {
+                Expression::Terminal(TerminalData::Constant(value)) => constants.push(*value),
+                Expression::Terminal(TerminalData::Variable(_)) => {}
+                Expression::Operation(node) => {
+                    for operand in &node.operands {
+                        collect(operand, constants);
+                    }
+                }
+            }
+        }
+        let mut constants = Vec::new();
+        collect(self, &mut constants);
+        constants
+    }
+
+    // Copy with the constants replaced in pre-order, `values` needs exactly one value per constant
+    pub fn with_constants(&self, values: &[f64]) -> Result<Expression, EvalError> {
+        fn replace(expression: &mut Expression, values: &mut std::slice::Iter<f64>) -> bool {
+            match expression {
+                Expression::Terminal(TerminalData::Constant(value)) => match values.next() {
+                    Some(next) => {
+                        *value = *next;
+                        true
+                    }
+                    None => false,
+                },
+                Expression::Terminal(TerminalData::Variable(_)) => true,
+                Expression::Operation(node) => node
+                    .operands
+                    .iter_mut()
+                    .all(|operand| replace(operand, values)),
+            }
+        }
+
+        let mut expression = self.clone();
+        let mut values = values.iter();
+        if !replace(&mut expression, &mut values) || values.next().is_some() {
+            return Err(EvalErrorKind::InvalidInput.into());
+        }
+        Ok(expression)
+    }
+}
+
+// Owned input columns and target series, e.g. for the constant optimization in the evolution loop
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FitData {
+    columns: Vec<(String, Vec<f64>)>,
+    target: Vec<f64>,
+}
+
+impl FitData {
+    pub fn new(target: Vec<f64>) -> Self {
+        FitData {
+            columns: Vec::new(),
+            target,
+        }
+    }
+
+    pub fn push_column(&mut self, name: &str, values: Vec<f64>) -> Result<(), EvalError> {
+        if values.len() != self.target.len() {
+            return Err(EvalErrorKind::InvalidInput.into());
+        }
+        self.columns.push((name.to_string(), values));
+        Ok(())
+    }
+
+    pub fn target(&self) -> &[f64] {
+        &self.target
+    }
+
+    pub fn context(&self) -> ColumnContext<'_> {
+        let mut context = ColumnContext::new(self.target.len());
+        for (name, values) in &self.columns {
+            // Lengths are checked in push_column
+            let _ = context.push_column(name, values);
+        }
+        context
+    }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// Gaussian elimination with partial pivoting, None for a singular system
+fn solve(mut matrix: Vec<Vec<f64>>, mut rhs: Vec<f64>) -> Option<Vec<f64>> {
+    let size = rhs.len();
+    for column in 0..size {
+        let pivot = (column..size).max_by(|a, b| {
+            matrix[*a][column]
+                .abs()
+                .total_cmp(&matrix[*b][column].abs())
+        })?;
+        if !matrix[pivot][column].is_normal() {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+
+        for row in column + 1..size {
+            let factor = matrix[row][column] / matrix[column][column];
+            for k in column..size {
+                matrix[row][k] -= factor * matrix[column][k];
+            }
+            rhs[row] -= factor * rhs[column];
+        }
+    }
+
+    let mut solution = vec![0.0; size];
+    for row in (0..size).rev() {
+        let tail: f64 = (row + 1..size).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (rhs[row] - tail) / matrix[row][row];
+    }
+    solution
+        .iter()
+        .all(|value| value.is_finite())
+        .then_some(solution)
+}
@@ -0,0 +1,276 @@
+use super::{
+    vectorized::apply_columns, ColumnContext, EvalError, EvalErrorKind, EvalPolicy, Expression,
+    FunctionData, NanPolicy, TerminalData,
+};
+use crate::data::types::BarPanel;
+use std::collections::HashMap;
+
+//NOTE: Symbols x time input for multi-asset evaluation. Every symbol has its own ColumnContext over
+// the same timestamps, groups label each symbol (e.g. its industry) for IndustryNeutralize.
+// Pointwise and time-series functions run along each symbol's series, cross-sectional functions
+// across the symbols at each timestamp. Cross-sectional statistics skip NaN values, a NaN input
+// stays NaN in the output.
+#[derive(Debug, Clone)]
+pub struct PanelContext<'a> {
+    symbols: Vec<String>,
+    columns: Vec<ColumnContext<'a>>,
+    groups: HashMap<String, Vec<String>>,
+    len: usize,
+    nan_policy: NanPolicy,
+    eval_policy: EvalPolicy,
+}
+
+impl<'a> PanelContext<'a> {
+    pub fn new(len: usize) -> Self {
+        PanelContext {
+            symbols: Vec::new(),
+            columns: Vec::new(),
+            groups: HashMap::new(),
+            len,
+            nan_policy: NanPolicy::default(),
+            eval_policy: EvalPolicy::default(),
+        }
+    }
+
+    pub fn with_nan_policy(mut self, nan_policy: NanPolicy) -> Self {
+        self.nan_policy = nan_policy;
+        self.columns = self
+            .columns
+            .into_iter()
+            .map(|columns| columns.with_nan_policy(nan_policy))
+            .collect();
+        self
+    }
+
+    pub fn with_eval_policy(mut self, eval_policy: EvalPolicy) -> Self {
+        self.eval_policy = eval_policy;
+        self
+    }
+
+    pub fn push_symbol(
+        &mut self,
+        symbol: &str,
+        columns: ColumnContext<'a>,
+    ) -> Result<(), EvalError> {
+        if columns.len() != self.len {
+            return Err(EvalErrorKind::InvalidInput.into());
+        }
+        self.symbols.push(symbol.to_string());
+        self.columns.push(columns.with_nan_policy(self.nan_policy));
+        Ok(())
+    }
+
+    // One label per symbol, in the order the symbols were pushed
+    pub fn push_group(&mut self, name: &str, labels: Vec<String>) -> Result<(), EvalError> {
+        if labels.len() != self.symbols.len() {
+            return Err(EvalErrorKind::InvalidInput.into());
+        }
+        self.groups.insert(name.to_string(), labels);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn eval_policy(&self) -> EvalPolicy {
+        self.eval_policy
+    }
+
+    pub fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    pub fn symbol_columns(&self, symbol: &str) -> Option<&ColumnContext<'a>> {
+        let index = self.symbols.iter().position(|s| s == symbol)?;
+        self.columns.get(index)
+    }
+
+    pub fn try_get_group(&self, name: &str) -> Result<&[String], EvalError> {
+        match self.groups.get(name) {
+            Some(labels) if labels.len() == self.symbols.len() => Ok(labels),
+            Some(_) => Err(EvalErrorKind::InvalidInput.into()),
+            None => Err(EvalErrorKind::UndefinedVariable(name.to_string()).into()),
+        }
+    }
+}
+
+impl<'a> From<&'a BarPanel> for PanelContext<'a> {
+    fn from(panel: &'a BarPanel) -> Self {
+        let mut context = PanelContext::new(panel.len());
+        for (symbol, bars) in panel.symbols.iter().zip(&panel.columns) {
+            context.symbols.push(symbol.clone());
+            context.columns.push(ColumnContext::from(bars));
+        }
+        context
+    }
+}
+
+impl Expression {
+    // Evaluates the expression for every symbol and timestamp, the result is indexed
+    // [symbol][row] in the order of context.symbols()
+    pub fn evaluate_panel(&self, context: &PanelContext) -> Result<Vec<Vec<f64>>, EvalError> {
+        match self {
+            Expression::Terminal(TerminalData::Constant(value)) => {
+                Ok(vec![vec![*value; context.len]; context.symbols.len()])
+            }
+            Expression::Terminal(TerminalData::Variable(name)) => context
+                .columns
+                .iter()
+                .map(|columns| columns.load_column(name))
+                .collect(),
+            Expression::Operation(function_node) => {
+                function_node.check_operands()?;
+                let mut operands = function_node
+                    .operands
+                    .iter()
+                    .enumerate()
+                    .map(|(index, op)| op.evaluate_panel(context).map_err(|e| e.within(index)))
+                    .collect::<Result<Vec<Vec<Vec<f64>>>, EvalError>>()?;
+
+                if function_node.operation.is_cross_sectional() {
+                    return cross_section(
+                        &function_node.operation,
+                        operands.swap_remove(0),
+                        context,
+                    )
+                    .map_err(|e| e.with_operator(&function_node.operation));
+                }
+
+                let mut by_symbol: Vec<Vec<Vec<f64>>> = (0..context.symbols.len())
+                    .map(|_| Vec::with_capacity(operands.len()))
+                    .collect();
+                for operand in operands {
+                    for (symbol, series) in operand.into_iter().enumerate() {
+                        by_symbol[symbol].push(series);
+                    }
+                }
+
+                by_symbol
+                    .into_iter()
+                    .map(|series| {
+                        apply_columns(
+                            &function_node.operation,
+                            series,
+                            context.len,
+                            context.eval_policy,
+                        )
+                        .map_err(|kind| function_node.error(kind))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+fn cross_section(
+    function: &FunctionData,
+    mut values: Vec<Vec<f64>>,
+    context: &PanelContext,
+) -> Result<Vec<Vec<f64>>, EvalError> {
+    let labels = match function {
+        FunctionData::IndustryNeutralize { group } => Some(context.try_get_group(group)?),
+        _ => None,
+    };
+
+    let mut row = Vec::with_capacity(values.len());
+    for t in 0..context.len {
+        row.clear();
+        row.extend(values.iter().map(|series| series[t]));
+
+        let output = match labels {
+            Some(labels) => neutralize(&row, labels),
+            None => match function {
+                FunctionData::Rank => rank(&row),
+                FunctionData::ZScore => zscore(&row),
+                FunctionData::Demean => demean(&row),
+                _ => scale(&row),
+            },
+        };
+        for (series, value) in values.iter_mut().zip(output) {
+            series[t] = value;
+        }
+    }
+    Ok(values)
+}
+
+fn valid(row: &[f64]) -> impl Iterator<Item = f64> + '_ {
+    row.iter().copied().filter(|v| !v.is_nan())
+}
+
+fn mean(row: &[f64]) -> f64 {
+    let (sum, count) = valid(row).fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    sum / count as f64
+}
+
+// Percentile rank, 0 for the smallest and 1 for the largest, ties share the average rank. A lone
+// valid value is in the middle (0.5).
+fn rank(row: &[f64]) -> Vec<f64> {
+    let mut sorted: Vec<f64> = valid(row).collect();
+    sorted.sort_by(f64::total_cmp);
+    let count = sorted.len() as f64;
+
+    row.iter()
+        .map(|value| {
+            if value.is_nan() {
+                return f64::NAN;
+            }
+            if count == 1.0 {
+                return 0.5;
+            }
+            let below = sorted.partition_point(|v| v < value) as f64;
+            let equal = sorted.partition_point(|v| v <= value) as f64 - below;
+            (below + (equal - 1.0) / 2.0) / (count - 1.0)
+        })
+        .collect()
+}
+
+// Sample standard deviation, like ts_std. Without spread (a lone valid value or all values equal)
+// every valid value is at the mean and scores 0.
+fn zscore(row: &[f64]) -> Vec<f64> {
+    let mean = mean(row);
+    let count = valid(row).count() as f64;
+    let deviation: f64 = valid(row).map(|v| (v - mean).powi(2)).sum();
+    if deviation == 0.0 {
+        return row
+            .iter()
+            .map(|value| if value.is_nan() { f64::NAN } else { 0.0 })
+            .collect();
+    }
+    let std = (deviation / (count - 1.0)).sqrt();
+    row.iter().map(|value| (value - mean) / std).collect()
+}
+
+fn demean(row: &[f64]) -> Vec<f64> {
+    let mean = mean(row);
+    row.iter().map(|value| value - mean).collect()
+}
+
+// Scales the row so that its absolute values sum to 1
+fn scale(row: &[f64]) -> Vec<f64> {
+    let total: f64 = valid(row).map(f64::abs).sum();
+    row.iter().map(|value| value / total).collect()
+}
+
+fn neutralize(row: &[f64], labels: &[String]) -> Vec<f64> {
+    let mut groups: HashMap<&str, (f64, usize)> = HashMap::new();
+    for (value, label) in row.iter().zip(labels) {
+        if !value.is_nan() {
+            let entry = groups.entry(label.as_str()).or_insert((0.0, 0));
+            entry.0 += value;
+            entry.1 += 1;
+        }
+    }
+
+    row.iter()
+        .zip(labels)
+        .map(|(value, label)| match groups.get(label.as_str()) {
+            Some((sum, count)) => value - sum / *count as f64,
+            None => f64::NAN,
+        })
+        .collect()
+}
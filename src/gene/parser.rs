@@ -0,0 +1,633 @@
+use super::{Expression, FunctionData, FunctionNode, TerminalData};
+use std::{error::Error, fmt, iter::Peekable, str::Chars, str::FromStr};
+
+//NOTE: Grammar, lowest to highest precedence:
+//
+//   expression     := additive
+//   additive       := multiplicative (("+" | "-") multiplicative)*
+//   multiplicative := unary (("*" | "/") unary)*
+//   unary          := "-" unary | power
+//   power          := primary ("^" unary)?
+//   primary        := number | identifier | identifier "(" arguments? ")" | "(" expression ")"
+//
+// `^` is right associative. Chains of `+` or `*` are collected into a single n-ary Add/Multiply
+// node, a parenthesised operand starts a new node. A `-` directly in front of a literal (a number,
+// `nan` or `inf`) is folded into the constant, on any other operand it becomes `-1 * operand`.
+// `nan` and `inf` are constants, any other bare identifier is a variable. Identifiers that are not plain
+// `[A-Za-z_][A-Za-z0-9_]*`, or that clash with a keyword, can be quoted with backticks.
+//
+// Functions: add(..), mul(..), sub(a, b), div(a, b), pow(a, b), sqrt(x), root(x, degree) where
+// degree is a positive number literal, log, exp, abs, neg, sign, tanh, sigmoid (one argument),
+// min, max, gt, lt, ge, le, eq, ne (two arguments), clip(x, lo, hi) where lo <= hi are number
+// literals and if_then_else(condition, a, b).
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(position: Position, message: impl Into<String>) -> Self {
+        ParseError {
+            line: position.line,
+            column: position.column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.message, self.line, self.column
+        )
+    }
+}
+
+impl Error for ParseError {}
+
+pub fn parse(input: &str) -> Result<Expression, ParseError> {
+    let tokens = Lexer::new(input).tokenize()?;
+    let mut parser = Parser { tokens, index: 0 };
+    let expression = parser.parse_expression()?;
+
+    match parser.peek() {
+        Token::End => Ok(expression),
+        token => Err(ParseError::new(
+            parser.position(),
+            format!("unexpected {}", token),
+        )),
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        parse(input)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier { name: String, quoted: bool },
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    LeftParen,
+    RightParen,
+    Comma,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "number `{}`", value),
+            Token::Identifier { name, .. } => write!(f, "identifier `{}`", name),
+            Token::Plus => write!(f, "`+`"),
+            Token::Minus => write!(f, "`-`"),
+            Token::Star => write!(f, "`*`"),
+            Token::Slash => write!(f, "`/`"),
+            Token::Caret => write!(f, "`^`"),
+            Token::LeftParen => write!(f, "`(`"),
+            Token::RightParen => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Lexer {
+            chars: input.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn tokenize(mut self) -> Result<Vec<(Token, Position)>, ParseError> {
+        let mut tokens = Vec::new();
+
+        while let Some(&c) = self.chars.peek() {
+            let start = self.position();
+            let token = match c {
+                c if c.is_whitespace() => {
+                    self.bump();
+                    continue;
+                }
+                '+' => self.single(Token::Plus),
+                '-' => self.single(Token::Minus),
+                '*' => self.single(Token::Star),
+                '/' => self.single(Token::Slash),
+                '^' => self.single(Token::Caret),
+                '(' => self.single(Token::LeftParen),
+                ')' => self.single(Token::RightParen),
+                ',' => self.single(Token::Comma),
+                '`' => self.quoted_identifier(start)?,
+                c if c.is_ascii_digit() || c == '.' => self.number(start)?,
+                c if c.is_ascii_alphabetic() || c == '_' => self.identifier(),
+                c => {
+                    return Err(ParseError::new(
+                        start,
+                        format!("unexpected character `{}`", c),
+                    ))
+                }
+            };
+            tokens.push((token, start));
+        }
+        tokens.push((Token::End, self.position()));
+
+        Ok(tokens)
+    }
+
+    fn single(&mut self, token: Token) -> Token {
+        self.bump();
+        token
+    }
+
+    fn take_while(&mut self, text: &mut String, predicate: impl Fn(char) -> bool) {
+        while let Some(&c) = self.chars.peek() {
+            if !predicate(c) {
+                break;
+            }
+            text.push(c);
+            self.bump();
+        }
+    }
+
+    fn number(&mut self, start: Position) -> Result<Token, ParseError> {
+        let mut text = String::new();
+        self.take_while(&mut text, |c| c.is_ascii_digit());
+        if self.chars.peek() == Some(&'.') {
+            text.push('.');
+            self.bump();
+            self.take_while(&mut text, |c| c.is_ascii_digit());
+        }
+        if self.exponent_follows() {
+            text.extend(self.bump());
+            if let Some(&sign) = self.chars.peek().filter(|c| **c == '+' || **c == '-') {
+                text.push(sign);
+                self.bump();
+            }
+            self.take_while(&mut text, |c| c.is_ascii_digit());
+        }
+        // A number running into letters, digits or another `.` (`2ex`, `1e`, `1.2.3`) is reported
+        // as a whole rather than split into a number and whatever follows
+        self.take_while(&mut text, |c| {
+            c.is_ascii_alphanumeric() || c == '_' || c == '.'
+        });
+
+        text.parse::<f64>()
+            .map(Token::Number)
+            .map_err(|_| ParseError::new(start, format!("invalid number `{}`", text)))
+    }
+
+    // `e`/`E` followed by digits, optionally signed
+    fn exponent_follows(&self) -> bool {
+        let mut ahead = self.chars.clone();
+        if !matches!(ahead.next(), Some('e' | 'E')) {
+            return false;
+        }
+        if matches!(ahead.peek(), Some('+' | '-')) {
+            ahead.next();
+        }
+        ahead.peek().is_some_and(|c| c.is_ascii_digit())
+    }
+
+    fn identifier(&mut self) -> Token {
+        let mut name = String::new();
+        self.take_while(&mut name, |c| c.is_ascii_alphanumeric() || c == '_');
+        Token::Identifier {
+            name,
+            quoted: false,
+        }
+    }
+
+    fn quoted_identifier(&mut self, start: Position) -> Result<Token, ParseError> {
+        self.bump();
+        let mut name = String::new();
+        self.take_while(&mut name, |c| c != '`');
+        if self.bump() != Some('`') {
+            return Err(ParseError::new(start, "unterminated quoted identifier"));
+        }
+        if name.is_empty() {
+            return Err(ParseError::new(start, "empty quoted identifier"));
+        }
+        Ok(Token::Identifier { name, quoted: true })
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    index: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].0
+    }
+
+    fn peek_nth(&self, n: usize) -> &Token {
+        let index = (self.index + n).min(self.tokens.len() - 1);
+        &self.tokens[index].0
+    }
+
+    fn position(&self) -> Position {
+        self.tokens[self.index].1
+    }
+
+    fn advance(&mut self) -> (Token, Position) {
+        let entry = self.tokens[self.index].clone();
+        if self.index < self.tokens.len() - 1 {
+            self.index += 1;
+        }
+        entry
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        if *self.peek() == expected {
+            self.advance();
+            Ok(())
+        } else {
+            Err(ParseError::new(
+                self.position(),
+                format!("expected {}, found {}", expected, self.peek()),
+            ))
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, ParseError> {
+        self.parse_additive()
+    }
+
+    fn parse_additive(&mut self) -> Result<Expression, ParseError> {
+        let mut lhs = self.parse_multiplicative()?;
+        let mut chained = false;
+
+        loop {
+            match self.peek() {
+                Token::Plus => {
+                    self.advance();
+                    let rhs = self.parse_multiplicative()?;
+                    lhs = chain(FunctionData::Add, lhs, rhs, chained);
+                    chained = true;
+                }
+                Token::Minus => {
+                    self.advance();
+                    let rhs = self.parse_multiplicative()?;
+                    lhs = operation(FunctionData::Subtract, vec![lhs, rhs]);
+                    chained = false;
+                }
+                _ => return Ok(lhs),
+            }
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expression, ParseError> {
+        let mut lhs = self.parse_unary()?;
+        let mut chained = false;
+
+        loop {
+            match self.peek() {
+                Token::Star => {
+                    self.advance();
+                    let rhs = self.parse_unary()?;
+                    lhs = chain(FunctionData::Multiply, lhs, rhs, chained);
+                    chained = true;
+                }
+                Token::Slash => {
+                    self.advance();
+                    let rhs = self.parse_unary()?;
+                    lhs = operation(FunctionData::Divide, vec![lhs, rhs]);
+                    chained = false;
+                }
+                _ => return Ok(lhs),
+            }
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, ParseError> {
+        if *self.peek() != Token::Minus {
+            return self.parse_power();
+        }
+        self.advance();
+
+        if let Some(value) = literal(self.peek()) {
+            if *self.peek_nth(1) != Token::Caret {
+                self.advance();
+                return Ok(Expression::Terminal(TerminalData::Constant(-value)));
+            }
+        }
+
+        let operand = self.parse_unary()?;
+        Ok(operation(
+            FunctionData::Multiply,
+            vec![Expression::Terminal(TerminalData::Constant(-1.0)), operand],
+        ))
+    }
+
+    fn parse_power(&mut self) -> Result<Expression, ParseError> {
+        let base = self.parse_primary()?;
+
+        if *self.peek() == Token::Caret {
+            self.advance();
+            let exponent = self.parse_unary()?;
+            return Ok(operation(FunctionData::Exponent, vec![base, exponent]));
+        }
+        Ok(base)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, ParseError> {
+        let (token, position) = self.advance();
+
+        match token {
+            Token::Identifier {
+                name,
+                quoted: false,
+            } if *self.peek() == Token::LeftParen => self.parse_call(name, position),
+            token if literal(&token).is_some() => Ok(Expression::Terminal(TerminalData::Constant(
+                literal(&token).unwrap_or_default(),
+            ))),
+            Token::Identifier { name, .. } => {
+                Ok(Expression::Terminal(TerminalData::Variable(name)))
+            }
+            Token::LeftParen => {
+                let inner = self.parse_expression()?;
+                self.expect(Token::RightParen)?;
+                Ok(inner)
+            }
+            token => Err(ParseError::new(
+                position,
+                format!("expected an expression, found {}", token),
+            )),
+        }
+    }
+
+    fn parse_call(&mut self, name: String, position: Position) -> Result<Expression, ParseError> {
+        self.expect(Token::LeftParen)?;
+
+        let mut arguments = Vec::new();
+        if *self.peek() != Token::RightParen {
+            loop {
+                arguments.push(self.parse_expression()?);
+                if *self.peek() != Token::Comma {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        self.expect(Token::RightParen)?;
+
+        if let Some(constructor) = time_series_constructor(&name) {
+            let operand_count = if name == "ts_corr" { 2 } else { 1 };
+            check_argument_count(&name, position, &arguments, operand_count + 1)?;
+            let window = match arguments.pop() {
+                Some(Expression::Terminal(TerminalData::Constant(window)))
+                    if window >= 1.0 && window.fract() == 0.0 && window <= u32::MAX as f64 =>
+                {
+                    window as usize
+                }
+                _ => 0,
+            };
+            let function = constructor(window);
+            if window < function.min_window() {
+                return Err(ParseError::new(
+                    position,
+                    format!(
+                        "`{}` window must be an integer literal of at least {}",
+                        name,
+                        function.min_window()
+                    ),
+                ));
+            }
+            return Ok(operation(function, arguments));
+        }
+
+        let expect_arguments =
+            |count: usize| check_argument_count(&name, position, &arguments, count);
+
+        let function = match name.as_str() {
+            "add" => FunctionData::Add,
+            "mul" => FunctionData::Multiply,
+            "sub" => {
+                expect_arguments(2)?;
+                FunctionData::Subtract
+            }
+            "div" => {
+                expect_arguments(2)?;
+                FunctionData::Divide
+            }
+            "pow" => {
+                expect_arguments(2)?;
+                FunctionData::Exponent
+            }
+            "sqrt" => {
+                expect_arguments(1)?;
+                FunctionData::Sqrt
+            }
+            "root" => {
+                check_argument_count(&name, position, &arguments, 2)?;
+                let degree = match arguments.pop() {
+                    Some(Expression::Terminal(TerminalData::Constant(degree))) if degree > 0.0 => {
+                        degree
+                    }
+                    _ => {
+                        return Err(ParseError::new(
+                            position,
+                            "`root` degree must be a positive number literal",
+                        ))
+                    }
+                };
+                FunctionData::Root { degree }
+            }
+            "log" | "exp" | "abs" | "neg" | "sign" | "tanh" | "sigmoid" => {
+                expect_arguments(1)?;
+                match name.as_str() {
+                    "log" => FunctionData::Log,
+                    "exp" => FunctionData::Exp,
+                    "abs" => FunctionData::Abs,
+                    "neg" => FunctionData::Neg,
+                    "sign" => FunctionData::Sign,
+                    "tanh" => FunctionData::Tanh,
+                    _ => FunctionData::Sigmoid,
+                }
+            }
+            "min" | "max" | "gt" | "lt" | "ge" | "le" | "eq" | "ne" => {
+                expect_arguments(2)?;
+                match name.as_str() {
+                    "min" => FunctionData::Min,
+                    "max" => FunctionData::Max,
+                    "gt" => FunctionData::GreaterThan,
+                    "lt" => FunctionData::LessThan,
+                    "ge" => FunctionData::GreaterOrEqual,
+                    "le" => FunctionData::LessOrEqual,
+                    "eq" => FunctionData::Equal,
+                    _ => FunctionData::NotEqual,
+                }
+            }
+            "clip" => {
+                check_argument_count(&name, position, &arguments, 3)?;
+                let bounds = (arguments.pop(), arguments.pop());
+                match bounds {
+                    (
+                        Some(Expression::Terminal(TerminalData::Constant(hi))),
+                        Some(Expression::Terminal(TerminalData::Constant(lo))),
+                    ) if lo <= hi => FunctionData::Clip { lo, hi },
+                    _ => {
+                        return Err(ParseError::new(
+                            position,
+                            "`clip` bounds must be number literals with lo <= hi",
+                        ))
+                    }
+                }
+            }
+            "if_then_else" => {
+                expect_arguments(3)?;
+                FunctionData::IfThenElse
+            }
+            "rank" => {
+                expect_arguments(1)?;
+                FunctionData::Rank
+            }
+            "zscore" => {
+                expect_arguments(1)?;
+                FunctionData::ZScore
+            }
+            "demean" => {
+                expect_arguments(1)?;
+                FunctionData::Demean
+            }
+            "scale" => {
+                expect_arguments(1)?;
+                FunctionData::Scale
+            }
+            "industry_neutralize" => {
+                check_argument_count(&name, position, &arguments, 2)?;
+                let group = match arguments.pop() {
+                    Some(Expression::Terminal(TerminalData::Variable(group))) => group,
+                    _ => {
+                        return Err(ParseError::new(
+                            position,
+                            "`industry_neutralize` group must be a group name",
+                        ))
+                    }
+                };
+                FunctionData::IndustryNeutralize { group }
+            }
+            _ => {
+                return Err(ParseError::new(
+                    position,
+                    format!("unknown function `{}`", name),
+                ))
+            }
+        };
+
+        Ok(operation(function, arguments))
+    }
+}
+
+fn literal(token: &Token) -> Option<f64> {
+    match token {
+        Token::Number(value) => Some(*value),
+        Token::Identifier {
+            name,
+            quoted: false,
+        } => match name.as_str() {
+            "nan" => Some(f64::NAN),
+            "inf" => Some(f64::INFINITY),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn time_series_constructor(name: &str) -> Option<fn(usize) -> FunctionData> {
+    let constructor: fn(usize) -> FunctionData = match name {
+        "delay" => |window| FunctionData::Delay { window },
+        "delta" => |window| FunctionData::Delta { window },
+        "ts_mean" => |window| FunctionData::TsMean { window },
+        "ts_std" => |window| FunctionData::TsStd { window },
+        "ts_min" => |window| FunctionData::TsMin { window },
+        "ts_max" => |window| FunctionData::TsMax { window },
+        "ts_rank" => |window| FunctionData::TsRank { window },
+        "ts_argmax" => |window| FunctionData::TsArgMax { window },
+        "decay" => |window| FunctionData::Decay { window },
+        "ts_corr" => |window| FunctionData::TsCorr { window },
+        _ => return None,
+    };
+    Some(constructor)
+}
+
+fn check_argument_count(
+    name: &str,
+    position: Position,
+    arguments: &[Expression],
+    expected: usize,
+) -> Result<(), ParseError> {
+    if arguments.len() == expected {
+        Ok(())
+    } else {
+        Err(ParseError::new(
+            position,
+            format!(
+                "`{}` expects {} argument(s), found {}",
+                name,
+                expected,
+                arguments.len()
+            ),
+        ))
+    }
+}
+
+fn operation(function: FunctionData, operands: Vec<Expression>) -> Expression {
+    Expression::Operation(FunctionNode::new(function, operands))
+}
+
+fn chain(function: FunctionData, lhs: Expression, rhs: Expression, chained: bool) -> Expression {
+    match lhs {
+        Expression::Operation(mut node) if chained => {
+            node.operands.push(rhs);
+            Expression::Operation(node)
+        }
+        lhs => operation(function, vec![lhs, rhs]),
+    }
+}
@@ -0,0 +1,292 @@
+use super::{EvalPolicy, Expression, FunctionData, FunctionNode, TerminalData};
+
+//NOTE: By default every rewrite is exact, the simplified tree evaluates to the same value as the
+// original for every input, NaN, infinities and signed zeros included. Add/Multiply fold their
+// operands left to right, so a nested chain is only merged when it is the leading operand and only
+// a leading run of constants is folded, regrouping would change rounding and overflow. For the same
+// reason x + 0 is kept (it turns -0 into 0), only x + -0 drops the zero. `with_assume_finite` also
+// allows the rewrites that only hold for finite inputs and ignore the sign of zero: regrouping
+// chains, x + 0 -> x, x * 0 -> 0, x - x -> 0 and root(x, 2) -> sqrt(x) (powf and sqrt differ at -0
+// and -inf, and powf is not always correctly rounded). sqrt(x) ^ 2 is never rewritten, it is NaN
+// for x < 0. Rewrites follow the EvalPolicy the tree will be evaluated under: constants fold with
+// that policy's kernel, Protected keeps nan / 0 and root(x, 1), and Strict does not flatten chains.
+// A subtree is only dropped when evaluating it cannot fail, so errors are kept as well: variables
+// may be unbound (with_assume_finite takes them to be bound), and under Strict every function may
+// raise a domain error. Nodes that fail the operand checks are left as they are so evaluate still
+// reports them, time-series and cross-sectional nodes are kept but their operands are simplified.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Simplifier {
+    assume_finite: bool,
+    eval_policy: EvalPolicy,
+}
+
+impl Simplifier {
+    pub fn new() -> Self {
+        Simplifier::default()
+    }
+
+    pub fn with_assume_finite(mut self, assume_finite: bool) -> Self {
+        self.assume_finite = assume_finite;
+        self
+    }
+
+    pub fn with_eval_policy(mut self, eval_policy: EvalPolicy) -> Self {
+        self.eval_policy = eval_policy;
+        self
+    }
+
+    // Returns the simplified tree and the number of rewrites applied
+    pub fn simplify(&self, expression: &Expression) -> (Expression, usize) {
+        let mut rewrites = 0;
+        let simplified = self.rewrite(expression.clone(), &mut rewrites);
+        (simplified, rewrites)
+    }
+
+    fn rewrite(&self, expression: Expression, rewrites: &mut usize) -> Expression {
+        let Expression::Operation(mut node) = expression else {
+            return expression;
+        };
+        node.operands = node
+            .operands
+            .into_iter()
+            .map(|operand| self.rewrite(operand, rewrites))
+            .collect();
+
+        // Rules only rebuild the root out of simplified operands, keep rewriting the root until none
+        // applies. A root that fails the operand checks (e.g. the lone operand of a chain) is kept.
+        let mut current = Expression::Operation(node);
+        while let Expression::Operation(node) = &current {
+            if node.check_operands().is_err() {
+                break;
+            }
+            match self.step(node) {
+                Some(rewritten) => {
+                    *rewrites += 1;
+                    current = rewritten;
+                }
+                None => break,
+            }
+        }
+        current
+    }
+
+    fn step(&self, node: &FunctionNode) -> Option<Expression> {
+        let operands = node.operands.as_slice();
+        if node.operation.is_time_series() || node.operation.is_cross_sectional() {
+            return None;
+        }
+
+        if let Some(values) = operands.iter().map(constant).collect::<Option<Vec<f64>>>() {
+            // A Strict domain error is kept in the tree
+            return node
+                .operation
+                .apply_policy(self.eval_policy, &values)
+                .ok()
+                .map(number);
+        }
+        let protected = self.eval_policy == EvalPolicy::Protected;
+
+        match &node.operation {
+            FunctionData::Add => self.chain(node, 0.0),
+            FunctionData::Multiply => self.chain(node, 1.0),
+            FunctionData::Subtract => {
+                if operands.iter().any(is_nan) && self.may_drop(operands) {
+                    Some(number(f64::NAN))
+                } else if constant(&operands[1]).map_or(false, |value| {
+                    value == 0.0 && (value.is_sign_positive() || self.assume_finite)
+                }) {
+                    // x - 0 is exactly x, x - -0 is x + 0
+                    Some(operands[0].clone())
+                } else if self.assume_finite
+                    && operands[0] == operands[1]
+                    && self.may_drop(operands)
+                {
+                    Some(number(0.0))
+                } else {
+                    None
+                }
+            }
+            // Protected maps nan / 0 to 1
+            FunctionData::Divide => {
+                if operands.iter().any(is_nan) && !protected && self.may_drop(operands) {
+                    Some(number(f64::NAN))
+                } else if constant(&operands[1]) == Some(1.0) {
+                    Some(operands[0].clone())
+                } else {
+                    None
+                }
+            }
+            // powf(x, 0) and powf(1, y) are 1 even for NaN
+            FunctionData::Exponent => match (constant(&operands[0]), constant(&operands[1])) {
+                (_, Some(exponent)) if exponent == 0.0 && self.may_drop(operands) => {
+                    Some(number(1.0))
+                }
+                (Some(base), _) if base == 1.0 && self.may_drop(operands) => Some(number(1.0)),
+                (_, Some(exponent)) if exponent == 1.0 => Some(operands[0].clone()),
+                _ => None,
+            },
+            // A constant condition picks its branch, a NaN condition gives NaN
+            FunctionData::IfThenElse => match constant(&operands[0]) {
+                Some(condition) if condition.is_nan() && self.may_drop(operands) => {
+                    Some(number(f64::NAN))
+                }
+                Some(condition) if condition > 0.0 && self.may_drop(&operands[2..]) => {
+                    Some(operands[1].clone())
+                }
+                Some(condition) if condition <= 0.0 && self.may_drop(&operands[1..2]) => {
+                    Some(operands[2].clone())
+                }
+                _ => None,
+            },
+            FunctionData::Root { degree } if *degree == 2.0 && self.assume_finite => {
+                Some(operation(FunctionData::Sqrt, operands.to_vec()))
+            }
+            // Protected takes the root of |x|
+            FunctionData::Root { degree } if *degree == 1.0 && !protected => {
+                Some(operands[0].clone())
+            }
+            _ => None,
+        }
+    }
+
+    // Rules that drop operands may only drop subtrees whose evaluation cannot fail
+    fn may_drop(&self, operands: &[Expression]) -> bool {
+        operands.iter().all(|operand| self.infallible(operand))
+    }
+
+    fn infallible(&self, expression: &Expression) -> bool {
+        match expression {
+            Expression::Terminal(TerminalData::Constant(_)) => true,
+            Expression::Terminal(TerminalData::Variable(_)) => self.assume_finite,
+            // evaluate rejects time-series and cross-sectional nodes
+            Expression::Operation(node) => {
+                self.eval_policy != EvalPolicy::Strict
+                    && !node.operation.is_time_series()
+                    && !node.operation.is_cross_sectional()
+                    && node.check_operands().is_ok()
+                    && node.operands.iter().all(|operand| self.infallible(operand))
+            }
+        }
+    }
+
+    // Add/Multiply, `identity` is 0 or 1. Add sums from -0, so add(x) is exactly x
+    fn chain(&self, node: &FunctionNode, identity: f64) -> Option<Expression> {
+        let function = &node.operation;
+        let operands = node.operands.as_slice();
+
+        if operands.iter().any(is_nan) && self.may_drop(operands) {
+            return Some(number(f64::NAN));
+        }
+        if self.assume_finite
+            && self.may_drop(operands)
+            && *function == FunctionData::Multiply
+            && operands
+                .iter()
+                .any(|operand| constant(operand) == Some(0.0))
+        {
+            return Some(number(0.0));
+        }
+
+        let nested = |operand: &Expression| match operand {
+            Expression::Operation(inner) => inner.operation == *function,
+            Expression::Terminal(_) => false,
+        };
+        // Under Strict the nested node checks its own intermediate result for overflow
+        let flatten = if self.eval_policy == EvalPolicy::Strict {
+            false
+        } else if self.assume_finite {
+            operands.iter().any(nested)
+        } else {
+            operands.first().map_or(false, nested)
+        };
+        if flatten {
+            let mut flattened = Vec::with_capacity(operands.len());
+            for (index, operand) in operands.iter().enumerate() {
+                match operand {
+                    Expression::Operation(inner)
+                        if nested(operand) && (index == 0 || self.assume_finite) =>
+                    {
+                        flattened.extend(inner.operands.iter().cloned())
+                    }
+                    _ => flattened.push(operand.clone()),
+                }
+            }
+            return Some(operation(function.clone(), flattened));
+        }
+
+        let (constants, rest): (Vec<&Expression>, Vec<&Expression>) = if self.assume_finite {
+            operands.iter().partition(|operand| is_constant(operand))
+        } else {
+            let run = operands
+                .iter()
+                .take_while(|operand| is_constant(operand))
+                .count();
+            (
+                operands[..run].iter().collect(),
+                operands[run..].iter().collect(),
+            )
+        };
+        if constants.len() >= 2 {
+            let values: Vec<f64> = constants.into_iter().filter_map(constant).collect();
+            if let Ok(value) = function.apply_policy(self.eval_policy, &values) {
+                let mut folded = vec![number(value)];
+                folded.extend(rest.into_iter().cloned());
+                return Some(operation(function.clone(), folded));
+            }
+        }
+
+        // x + 0 is not x for x = -0, x + -0 always is
+        let is_identity = |operand: &Expression| match constant(operand) {
+            Some(value) if value == identity => {
+                identity != 0.0 || value.is_sign_negative() || self.assume_finite
+            }
+            _ => false,
+        };
+        if operands.iter().any(is_identity) {
+            let mut remaining: Vec<Expression> = operands
+                .iter()
+                .filter(|operand| !is_identity(operand))
+                .cloned()
+                .collect();
+            return Some(match remaining.len() {
+                1 => remaining.swap_remove(0),
+                _ => operation(function.clone(), remaining),
+            });
+        }
+
+        match operands {
+            [single] => Some(single.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Expression {
+    // Exact simplification, see Simplifier
+    pub fn simplify(&self) -> (Expression, usize) {
+        Simplifier::new().simplify(self)
+    }
+}
+
+fn constant(expression: &Expression) -> Option<f64> {
+    match expression {
+        Expression::Terminal(TerminalData::Constant(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+fn is_constant(expression: &&Expression) -> bool {
+    constant(expression).is_some()
+}
+
+fn is_nan(expression: &Expression) -> bool {
+    constant(expression).map_or(false, f64::is_nan)
+}
+
+fn number(value: f64) -> Expression {
+    Expression::Terminal(TerminalData::Constant(value))
+}
+
+fn operation(function: FunctionData, operands: Vec<Expression>) -> Expression {
+    Expression::Operation(FunctionNode::new(function, operands))
+}
@@ -0,0 +1,217 @@
+use super::{
+    vectorized::apply_columns, ColumnContext, EvalError, EvalErrorKind, Expression, FunctionData,
+    TerminalData,
+};
+
+//NOTE: Time-series functions look back over the rows of their operands. Row t of a time-series
+// node reads rows t + 1 - span ..= t of each operand, rows before the first full window are NaN
+// (the warm-up). Like the pointwise kernel, a NaN inside the window makes the output row NaN, Delay
+// and Delta only read the two rows they compare.
+
+impl FunctionData {
+    // Windowed kernel shared by all evaluators, `windows` holds the last `span` values of each
+    // operand, oldest first. Pointwise functions are applied to the current row.
+    pub fn apply_window(&self, windows: &[&[f64]]) -> f64 {
+        let Some(values) = windows.first() else {
+            return self.apply(&[]);
+        };
+        let current = values[values.len() - 1];
+
+        match self {
+            FunctionData::Delay { .. } => values[0],
+            FunctionData::Delta { .. } => current - values[0],
+            FunctionData::TsMean { .. } => mean(values),
+            FunctionData::TsStd { .. } => variance(values, mean(values)).sqrt(),
+            FunctionData::TsMin { .. } => extreme(values, f64::min),
+            FunctionData::TsMax { .. } => extreme(values, f64::max),
+            FunctionData::TsRank { .. } => rank(values, current),
+            FunctionData::TsArgMax { .. } => periods_since_max(values),
+            FunctionData::Decay { .. } => linear_decay(values),
+            FunctionData::TsCorr { .. } => correlation(values, windows[1]),
+            pointwise => {
+                let row: Vec<f64> = windows.iter().map(|w| w[w.len() - 1]).collect();
+                pointwise.apply(&row)
+            }
+        }
+    }
+}
+
+impl Expression {
+    // Leading rows of a series evaluation that are NaN because some time-series node has not seen a
+    // full window yet, nested windows add up
+    pub fn warmup(&self) -> usize {
+        match self {
+            Expression::Terminal(_) => 0,
+            Expression::Operation(function_node) => {
+                function_node.operation.warmup()
+                    + function_node
+                        .operands
+                        .iter()
+                        .map(Expression::warmup)
+                        .max()
+                        .unwrap_or(0)
+            }
+        }
+    }
+
+    // Scalar evaluation of one row of `context`, equal to row `row` of `evaluate_columns`.
+    // Time-series nodes evaluate their operands over the preceding rows.
+    pub fn evaluate_at(&self, context: &ColumnContext, row: usize) -> Result<f64, EvalError> {
+        if row >= context.len() {
+            return Err(EvalErrorKind::InvalidInput.into());
+        }
+        Ok(self.evaluate_rows(context, row, row)?[0])
+    }
+
+    //NOTE: rows `first..=last` of `evaluate_columns`. Each operand of a time-series node is
+    // evaluated once, over the node's rows plus its warm-up, so nested windows cost the sum and not
+    // the product of their spans.
+    fn evaluate_rows(
+        &self,
+        context: &ColumnContext,
+        first: usize,
+        last: usize,
+    ) -> Result<Vec<f64>, EvalError> {
+        match self {
+            Expression::Terminal(TerminalData::Constant(value)) => {
+                Ok(vec![*value; last + 1 - first])
+            }
+            Expression::Terminal(TerminalData::Variable(name)) => (first..=last)
+                .map(|row| context.load_value(name, row))
+                .collect(),
+            Expression::Operation(function_node) => {
+                function_node.check_operands()?;
+                let function = &function_node.operation;
+                if function.is_cross_sectional() {
+                    return Err(function_node.error(EvalErrorKind::UnsupportedOperation));
+                }
+
+                let start = first.saturating_sub(function.warmup());
+                let operands = function_node
+                    .operands
+                    .iter()
+                    .enumerate()
+                    .map(|(index, op)| {
+                        op.evaluate_rows(context, start, last)
+                            .map_err(|e| e.within(index))
+                    })
+                    .collect::<Result<Vec<Vec<f64>>, EvalError>>()?;
+
+                if !function.is_time_series() {
+                    return apply_columns(
+                        function,
+                        operands,
+                        last + 1 - first,
+                        context.eval_policy(),
+                    )
+                    .map_err(|kind| function_node.error(kind));
+                }
+
+                // Operand index i holds row start + i
+                let span = function.span();
+                let mut windows: Vec<&[f64]> = Vec::with_capacity(operands.len());
+                Ok((first..=last)
+                    .map(|row| {
+                        if row + 1 < span {
+                            return f64::NAN;
+                        }
+                        windows.clear();
+                        windows.extend(
+                            operands
+                                .iter()
+                                .map(|column| &column[row + 1 - span - start..=row - start]),
+                        );
+                        function.apply_window(&windows)
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+// Columnar counterpart of apply_window, every operand column holds `len` rows
+pub(super) fn apply_rolling(
+    function: &FunctionData,
+    operands: &[Vec<f64>],
+    len: usize,
+) -> Vec<f64> {
+    let span = function.span();
+    let mut windows: Vec<&[f64]> = Vec::with_capacity(operands.len());
+
+    (0..len)
+        .map(|t| {
+            if t + 1 < span {
+                return f64::NAN;
+            }
+            windows.clear();
+            windows.extend(operands.iter().map(|column| &column[t + 1 - span..=t]));
+            function.apply_window(&windows)
+        })
+        .collect()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// Sample variance (n - 1)
+fn variance(values: &[f64], mean: f64) -> f64 {
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() as f64 - 1.0)
+}
+
+// f64::min/max skip NaN, the window has to be checked first
+fn extreme(values: &[f64], pick: fn(f64, f64) -> f64) -> f64 {
+    if values.iter().any(|v| v.is_nan()) {
+        return f64::NAN;
+    }
+    values.iter().copied().reduce(pick).unwrap_or(f64::NAN)
+}
+
+// Percentile of the current value within the window, 0 for the smallest and 1 for the largest,
+// ties share the average rank
+fn rank(values: &[f64], current: f64) -> f64 {
+    if values.iter().any(|v| v.is_nan()) {
+        return f64::NAN;
+    }
+    let below = values.iter().filter(|v| **v < current).count() as f64;
+    let equal = values.iter().filter(|v| **v == current).count() as f64;
+    (below + (equal - 1.0) / 2.0) / (values.len() as f64 - 1.0)
+}
+
+// 0 if the current row holds the window maximum, the most recent maximum wins ties
+fn periods_since_max(values: &[f64]) -> f64 {
+    if values.iter().any(|v| v.is_nan()) {
+        return f64::NAN;
+    }
+    let mut best = values.len() - 1;
+    for (index, value) in values.iter().enumerate().rev() {
+        if *value > values[best] {
+            best = index;
+        }
+    }
+    (values.len() - 1 - best) as f64
+}
+
+// Linearly weighted mean, the current row has weight n and the oldest weight 1
+fn linear_decay(values: &[f64]) -> f64 {
+    let n = values.len() as f64;
+    let weighted: f64 = values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| (index + 1) as f64 * value)
+        .sum();
+    weighted / (n * (n + 1.0) / 2.0)
+}
+
+// Pearson correlation, NaN when either window is constant
+fn correlation(x: &[f64], y: &[f64]) -> f64 {
+    let (x_mean, y_mean) = (mean(x), mean(y));
+    let covariance: f64 = x
+        .iter()
+        .zip(y)
+        .map(|(a, b)| (a - x_mean) * (b - y_mean))
+        .sum();
+    let x_deviation: f64 = x.iter().map(|a| (a - x_mean).powi(2)).sum();
+    let y_deviation: f64 = y.iter().map(|b| (b - y_mean).powi(2)).sum();
+    covariance / (x_deviation * y_deviation).sqrt()
+}
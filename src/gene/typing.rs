@@ -0,0 +1,578 @@
+use super::{Arity, FunctionData, TerminalData};
+use crate::traits::GeneticTree;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, error, fmt};
+
+//NOTE: Value types for strongly typed GP. Price and Volume carry a unit, Float and Ratio are
+// dimensionless (a Ratio is a unit divided by itself, e.g. close / open) and Bool is what the
+// comparisons return and if_then_else expects as its condition. Values of different units never
+// mix, two different dimensionless types combine to Float. Constants are literals without a unit,
+// they take whichever numeric type their position needs, so `close - 1` is a Price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NodeType {
+    Float,
+    Bool,
+    Price,
+    Volume,
+    Ratio,
+}
+
+// Order of preference when a tree could have several types
+const ALL: [NodeType; 5] = [
+    NodeType::Float,
+    NodeType::Ratio,
+    NodeType::Price,
+    NodeType::Volume,
+    NodeType::Bool,
+];
+
+impl NodeType {
+    pub fn is_numeric(&self) -> bool {
+        *self != NodeType::Bool
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        matches!(self, NodeType::Float | NodeType::Ratio)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeType::Float => "float",
+            NodeType::Bool => "bool",
+            NodeType::Price => "price",
+            NodeType::Volume => "volume",
+            NodeType::Ratio => "ratio",
+        }
+    }
+}
+
+impl fmt::Display for NodeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FunctionData {
+    // Type of a node whose operands have the given types, None if they do not fit
+    pub fn output_type(&self, operands: &[NodeType]) -> Option<NodeType> {
+        if !self.arity().accepts(operands.len()) {
+            return None;
+        }
+        if *self == FunctionData::IfThenElse {
+            return match operands {
+                [NodeType::Bool, a, b] => unify(*a, *b),
+                _ => None,
+            };
+        }
+        if operands.iter().any(|operand| !operand.is_numeric()) {
+            return None;
+        }
+
+        match self {
+            FunctionData::Add | FunctionData::Multiply if operands.is_empty() => {
+                Some(NodeType::Float)
+            }
+            FunctionData::Add | FunctionData::Subtract | FunctionData::Min | FunctionData::Max => {
+                fold(operands, unify)
+            }
+            FunctionData::Multiply => fold(operands, product),
+            FunctionData::Divide => quotient(operands[0], operands[1]),
+            FunctionData::GreaterThan
+            | FunctionData::LessThan
+            | FunctionData::GreaterOrEqual
+            | FunctionData::LessOrEqual
+            | FunctionData::Equal
+            | FunctionData::NotEqual => unify(operands[0], operands[1]).map(|_| NodeType::Bool),
+            FunctionData::Exponent
+            | FunctionData::Exp
+            | FunctionData::Tanh
+            | FunctionData::Sigmoid => operands
+                .iter()
+                .all(NodeType::is_dimensionless)
+                .then_some(NodeType::Float),
+            FunctionData::Sqrt
+            | FunctionData::Root { .. }
+            | FunctionData::Log
+            | FunctionData::Sign
+            | FunctionData::TsRank { .. }
+            | FunctionData::TsArgMax { .. }
+            | FunctionData::TsCorr { .. }
+            | FunctionData::Rank
+            | FunctionData::ZScore
+            | FunctionData::Scale => Some(NodeType::Float),
+            FunctionData::Abs
+            | FunctionData::Neg
+            | FunctionData::Clip { .. }
+            | FunctionData::Delay { .. }
+            | FunctionData::Delta { .. }
+            | FunctionData::TsMean { .. }
+            | FunctionData::TsStd { .. }
+            | FunctionData::TsMin { .. }
+            | FunctionData::TsMax { .. }
+            | FunctionData::Decay { .. }
+            | FunctionData::Demean
+            | FunctionData::IndustryNeutralize { .. } => Some(operands[0]),
+            FunctionData::IfThenElse => None,
+        }
+    }
+}
+
+// Operands of add/sub/min/max, the comparisons and the if_then_else branches
+fn unify(a: NodeType, b: NodeType) -> Option<NodeType> {
+    if a == b {
+        Some(a)
+    } else if a.is_dimensionless() && b.is_dimensionless() {
+        Some(NodeType::Float)
+    } else {
+        None
+    }
+}
+
+// At most one factor may carry a unit
+fn product(a: NodeType, b: NodeType) -> Option<NodeType> {
+    match (a.is_dimensionless(), b.is_dimensionless()) {
+        (true, true) => unify(a, b),
+        (true, false) => Some(b),
+        (false, true) => Some(a),
+        (false, false) => None,
+    }
+}
+
+// A unit divided by itself is a Ratio, a unit can be divided by a dimensionless value
+fn quotient(a: NodeType, b: NodeType) -> Option<NodeType> {
+    match (a.is_dimensionless(), b.is_dimensionless()) {
+        (_, true) => product(a, b),
+        (false, false) if a == b => Some(NodeType::Ratio),
+        _ => None,
+    }
+}
+
+fn fold(
+    operands: &[NodeType],
+    combine: fn(NodeType, NodeType) -> Option<NodeType>,
+) -> Option<NodeType> {
+    let (first, rest) = operands.split_first()?;
+    rest.iter()
+        .try_fold(*first, |acc, operand| combine(acc, *operand))
+}
+
+// Set of NodeTypes as a bit mask, a constant can be any numeric type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct TypeSet(u8);
+
+impl TypeSet {
+    pub(super) const EMPTY: TypeSet = TypeSet(0);
+    pub(super) const ANY: TypeSet = TypeSet(0b11111);
+    pub(super) const LITERAL: TypeSet = TypeSet(0b11101);
+
+    pub(super) fn of(node_type: NodeType) -> Self {
+        TypeSet(1 << node_type as u8)
+    }
+
+    pub(super) fn contains(&self, node_type: NodeType) -> bool {
+        self.0 & TypeSet::of(node_type).0 != 0
+    }
+
+    pub(super) fn insert(&mut self, node_type: NodeType) {
+        self.0 |= TypeSet::of(node_type).0;
+    }
+
+    pub(super) fn union(self, other: TypeSet) -> Self {
+        TypeSet(self.0 | other.0)
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub(super) fn iter(self) -> impl Iterator<Item = NodeType> {
+        ALL.into_iter()
+            .filter(move |node_type| self.contains(*node_type))
+    }
+
+    pub(super) fn principal(&self) -> Option<NodeType> {
+        self.iter().next()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeErrorKind {
+    UnknownVariable(String),
+    IncorrectOperandCount { expected: Arity, found: usize },
+    // The operand types, a constant operand is reported as float
+    Mismatch { found: Vec<NodeType> },
+    UnexpectedType { expected: NodeType, found: NodeType },
+}
+
+// Same addressing as EvalError, `path` holds the child indices from the root to the node
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub path: Vec<usize>,
+    pub operator: Option<FunctionData>,
+}
+
+impl fmt::Display for TypeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeErrorKind::UnknownVariable(name) => write!(f, "variable `{}` has no type", name),
+            TypeErrorKind::IncorrectOperandCount { expected, found } => {
+                write!(f, "expected {} operand(s), found {}", expected, found)
+            }
+            TypeErrorKind::Mismatch { found } => {
+                let found: Vec<&str> = found.iter().map(NodeType::name).collect();
+                write!(f, "operand types ({}) do not fit", found.join(", "))
+            }
+            TypeErrorKind::UnexpectedType { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(operator) = &self.operator {
+            write!(f, " in `{}`", operator.name())?;
+        }
+        if !self.path.is_empty() {
+            let path: Vec<String> = self.path.iter().map(|index| index.to_string()).collect();
+            write!(f, " at node path {}", path.join("."))?;
+        } else if self.operator.is_some() {
+            write!(f, " at the root")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for TypeError {}
+
+// Types of the variables a tree may use
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeEnv {
+    variables: HashMap<String, NodeType>,
+}
+
+impl TypeEnv {
+    pub fn new() -> Self {
+        TypeEnv::default()
+    }
+
+    // The columns of ColumnContext::from(&BarColumns)
+    pub fn for_bars() -> Self {
+        let mut env = TypeEnv::new();
+        for name in ["open", "high", "low", "close"] {
+            env.push_variable(name, NodeType::Price);
+        }
+        env.push_variable("volume", NodeType::Volume);
+        env
+    }
+
+    pub fn push_variable(&mut self, name: &str, node_type: NodeType) {
+        self.variables.insert(name.to_string(), node_type);
+    }
+
+    pub fn variable_type(&self, name: &str) -> Option<NodeType> {
+        self.variables.get(name).copied()
+    }
+
+    // Type of the tree, a tree of constants is a float. Every mismatch is reported, a node that
+    // does not type-check is treated as having any type so its parent is still checked.
+    pub fn typecheck<T: GeneticTree>(&self, tree: &T) -> Result<NodeType, Vec<TypeError>> {
+        let mut errors = Vec::new();
+        let inferred = self.infer(tree, &mut Vec::new(), &mut errors);
+        match inferred.types.principal() {
+            Some(node_type) if errors.is_empty() => Ok(node_type),
+            _ => Err(errors),
+        }
+    }
+
+    // Like typecheck, but the tree also has to be usable as `expected`
+    pub fn typecheck_as<T: GeneticTree>(
+        &self,
+        tree: &T,
+        expected: NodeType,
+    ) -> Result<(), Vec<TypeError>> {
+        self.check_as(tree, expected).map(|_| ())
+    }
+
+    // Type of every node, in the order of GeneticTree::subtrees, when the tree is used as
+    // `expected`. Constants get the type their position needs, e.g. price for the 1 in `close - 1`.
+    pub fn resolve<T: GeneticTree>(
+        &self,
+        tree: &T,
+        expected: NodeType,
+    ) -> Result<Vec<NodeType>, Vec<TypeError>> {
+        let inferred = self.check_as(tree, expected)?;
+        let mut types = Vec::new();
+        resolve_node(tree, &inferred, expected, &mut types);
+        Ok(types)
+    }
+
+    // Records the types of `resolve` on the nodes, see GeneticTree::set_node_type
+    pub fn assign<T: GeneticTree>(
+        &self,
+        tree: &mut T,
+        expected: NodeType,
+    ) -> Result<(), Vec<TypeError>> {
+        fn stamp<T: GeneticTree>(node: &mut T, types: &mut impl Iterator<Item = NodeType>) {
+            if let Some(node_type) = types.next() {
+                node.set_node_type(node_type);
+            }
+            for child in node.children_mut() {
+                stamp(child, types);
+            }
+        }
+        let types = self.resolve(tree, expected)?;
+        stamp(tree, &mut types.into_iter());
+        Ok(())
+    }
+
+    fn check_as<T: GeneticTree>(
+        &self,
+        tree: &T,
+        expected: NodeType,
+    ) -> Result<Inferred, Vec<TypeError>> {
+        let mut errors = Vec::new();
+        let inferred = self.infer(tree, &mut Vec::new(), &mut errors);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        match inferred.types.principal() {
+            Some(_) if inferred.types.contains(expected) => Ok(inferred),
+            found => Err(vec![TypeError {
+                kind: TypeErrorKind::UnexpectedType {
+                    expected,
+                    found: found.unwrap_or(NodeType::Float),
+                },
+                path: Vec::new(),
+                operator: tree.function_data().cloned(),
+            }]),
+        }
+    }
+
+    fn infer<T: GeneticTree>(
+        &self,
+        tree: &T,
+        path: &mut Vec<usize>,
+        errors: &mut Vec<TypeError>,
+    ) -> Inferred {
+        let leaf = |types: TypeSet| Inferred {
+            types,
+            operands: Vec::new(),
+        };
+        match tree.terminal_data() {
+            Some(TerminalData::Constant(_)) => return leaf(TypeSet::LITERAL),
+            Some(TerminalData::Variable(name)) => {
+                return match self.variable_type(name) {
+                    Some(node_type) => leaf(TypeSet::of(node_type)),
+                    None => {
+                        errors.push(TypeError {
+                            kind: TypeErrorKind::UnknownVariable(name.clone()),
+                            path: path.clone(),
+                            operator: None,
+                        });
+                        leaf(TypeSet::ANY)
+                    }
+                };
+            }
+            None => {}
+        }
+        let Some(function) = tree.function_data() else {
+            return leaf(TypeSet::ANY);
+        };
+
+        let operands: Vec<Inferred> = tree
+            .children()
+            .into_iter()
+            .enumerate()
+            .map(|(index, child)| {
+                path.push(index);
+                let inferred = self.infer(child, path, errors);
+                path.pop();
+                inferred
+            })
+            .collect();
+        let sets: Vec<TypeSet> = operands.iter().map(|operand| operand.types).collect();
+
+        let mut error = |kind: TypeErrorKind| {
+            errors.push(TypeError {
+                kind,
+                path: path.clone(),
+                operator: Some(function.clone()),
+            });
+            TypeSet::ANY
+        };
+        let expected = function.arity();
+        let types = if !expected.accepts(sets.len()) {
+            error(TypeErrorKind::IncorrectOperandCount {
+                expected,
+                found: sets.len(),
+            })
+        } else {
+            let types = output_types(function, &sets);
+            if types.is_empty() {
+                let found = sets
+                    .iter()
+                    .map(|types| types.principal().unwrap_or(NodeType::Float))
+                    .collect();
+                error(TypeErrorKind::Mismatch { found })
+            } else {
+                types
+            }
+        };
+        Inferred { types, operands }
+    }
+}
+
+// Types a node can have, with those of its operands
+struct Inferred {
+    types: TypeSet,
+    operands: Vec<Inferred>,
+}
+
+// `tree` type-checked, so every type in an Inferred set has operand types that produce it
+fn resolve_node<T: GeneticTree>(
+    tree: &T,
+    inferred: &Inferred,
+    node_type: NodeType,
+    types: &mut Vec<NodeType>,
+) {
+    types.push(node_type);
+    let Some(function) = tree.function_data() else {
+        return;
+    };
+    let sets: Vec<TypeSet> = inferred
+        .operands
+        .iter()
+        .map(|operand| operand.types)
+        .collect();
+    let operand_types = operand_types(function, &sets, node_type, &mut |_| 0)
+        .expect("a type-checked node has operands of its type");
+    for ((child, operand), operand_type) in tree
+        .children()
+        .into_iter()
+        .zip(&inferred.operands)
+        .zip(operand_types)
+    {
+        resolve_node(child, operand, operand_type, types);
+    }
+}
+
+// Every type the node can have for some choice of its operands' types. Add and Multiply are
+// folded pairwise so long chains stay cheap.
+pub(super) fn output_types(function: &FunctionData, operands: &[TypeSet]) -> TypeSet {
+    if function.arity() == Arity::Variadic {
+        return match prefixes(function, operands).last() {
+            Some(types) => *types,
+            None => TypeSet::LITERAL,
+        };
+    }
+
+    let mut types = TypeSet::EMPTY;
+    let mut tuple = Vec::with_capacity(operands.len());
+    combinations(operands, &mut tuple, &mut |tuple| {
+        if let Some(output) = function.output_type(tuple) {
+            types.insert(output);
+        }
+    });
+    types
+}
+
+//NOTE: Operand types from `operands` that give `output`, `pick(n)` chooses one of n candidates
+// (0 for the preferred one). A variadic function is taken apart from its last operand: the
+// operand and the type of the fold before it have to combine to the output, so only pairs of
+// types are ever tried.
+pub(super) fn operand_types(
+    function: &FunctionData,
+    operands: &[TypeSet],
+    output: NodeType,
+    pick: &mut impl FnMut(usize) -> usize,
+) -> Option<Vec<NodeType>> {
+    if function.arity() != Arity::Variadic {
+        let mut tuples = Vec::new();
+        combinations(operands, &mut Vec::new(), &mut |tuple| {
+            if function.output_type(tuple) == Some(output) {
+                tuples.push(tuple.to_vec());
+            }
+        });
+        return match tuples.len() {
+            0 => None,
+            count => Some(tuples.swap_remove(pick(count))),
+        };
+    }
+
+    let prefixes = prefixes(function, operands);
+    let mut types = vec![output; operands.len()];
+    let mut wanted = output;
+    for position in (1..operands.len()).rev() {
+        let mut pairs = Vec::new();
+        for before in prefixes[position - 1].iter() {
+            for operand in operands[position].iter() {
+                if function.output_type(&[before, operand]) == Some(wanted) {
+                    pairs.push((before, operand));
+                }
+            }
+        }
+        if pairs.is_empty() {
+            return None;
+        }
+        let (before, operand) = pairs[pick(pairs.len())];
+        types[position] = operand;
+        wanted = before;
+    }
+    if let Some(first) = operands.first() {
+        let firsts: Vec<NodeType> = first
+            .iter()
+            .filter(|operand| function.output_type(&[*operand]) == Some(wanted))
+            .collect();
+        if firsts.is_empty() {
+            return None;
+        }
+        types[0] = firsts[pick(firsts.len())];
+    }
+    Some(types)
+}
+
+// prefixes[k] holds the types of the variadic function over the first k + 1 operands
+fn prefixes(function: &FunctionData, operands: &[TypeSet]) -> Vec<TypeSet> {
+    let Some((first, rest)) = operands.split_first() else {
+        return Vec::new();
+    };
+    let start = first.iter().fold(TypeSet::EMPTY, |mut types, operand| {
+        if let Some(output) = function.output_type(&[operand]) {
+            types.insert(output);
+        }
+        types
+    });
+    let mut prefixes = vec![start];
+    for operand in rest {
+        let mut types = TypeSet::EMPTY;
+        for a in prefixes[prefixes.len() - 1].iter() {
+            for b in operand.iter() {
+                if let Some(output) = function.output_type(&[a, b]) {
+                    types.insert(output);
+                }
+            }
+        }
+        prefixes.push(types);
+    }
+    prefixes
+}
+
+fn combinations(
+    operands: &[TypeSet],
+    tuple: &mut Vec<NodeType>,
+    visit: &mut impl FnMut(&[NodeType]),
+) {
+    let Some((first, rest)) = operands.split_first() else {
+        visit(tuple);
+        return;
+    };
+    for node_type in first.iter() {
+        tuple.push(node_type);
+        combinations(rest, tuple, visit);
+        tuple.pop();
+    }
+}
@@ -0,0 +1,195 @@
+use super::{
+    timeseries::apply_rolling, EvalError, EvalErrorKind, EvalPolicy, Expression, FunctionData,
+    TerminalData,
+};
+use crate::data::types::BarColumns;
+use std::collections::HashMap;
+
+//NOTE: How NaN values in the input columns are treated. Propagate keeps IEEE semantics, a NaN in
+// any operand of a row makes that row of the signal NaN, it is never an error.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NanPolicy {
+    #[default]
+    Propagate,
+    // Replace NaN input values with the given value before evaluating
+    Fill(f64),
+    // Fail with InvalidInput if a column used by the expression holds a NaN, evaluate_at only
+    // checks the values it reads
+    Reject,
+}
+
+// Named, equal length input columns, e.g. from BarColumns::context
+#[derive(Debug, Clone)]
+pub struct ColumnContext<'a> {
+    columns: HashMap<String, &'a [f64]>,
+    len: usize,
+    nan_policy: NanPolicy,
+    eval_policy: EvalPolicy,
+}
+
+impl<'a> ColumnContext<'a> {
+    pub fn new(len: usize) -> Self {
+        ColumnContext {
+            columns: HashMap::new(),
+            len,
+            nan_policy: NanPolicy::default(),
+            eval_policy: EvalPolicy::default(),
+        }
+    }
+
+    pub fn with_nan_policy(mut self, nan_policy: NanPolicy) -> Self {
+        self.nan_policy = nan_policy;
+        self
+    }
+
+    pub fn with_eval_policy(mut self, eval_policy: EvalPolicy) -> Self {
+        self.eval_policy = eval_policy;
+        self
+    }
+
+    pub fn push_column(&mut self, name: &str, values: &'a [f64]) -> Result<(), EvalError> {
+        if values.len() != self.len {
+            return Err(EvalErrorKind::InvalidInput.into());
+        }
+        self.columns.insert(name.to_string(), values);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn nan_policy(&self) -> NanPolicy {
+        self.nan_policy
+    }
+
+    pub fn eval_policy(&self) -> EvalPolicy {
+        self.eval_policy
+    }
+
+    pub fn try_get_column(&self, name: &str) -> Result<&'a [f64], EvalError> {
+        self.columns
+            .get(name)
+            .copied()
+            .ok_or_else(|| EvalErrorKind::UndefinedVariable(name.to_string()).into())
+    }
+
+    pub(super) fn load_column(&self, name: &str) -> Result<Vec<f64>, EvalError> {
+        let column = self.try_get_column(name)?;
+
+        match self.nan_policy {
+            NanPolicy::Propagate => Ok(column.to_vec()),
+            NanPolicy::Fill(fill) => Ok(column
+                .iter()
+                .map(|value| if value.is_nan() { fill } else { *value })
+                .collect()),
+            NanPolicy::Reject if column.iter().any(|value| value.is_nan()) => {
+                Err(EvalErrorKind::InvalidInput.into())
+            }
+            NanPolicy::Reject => Ok(column.to_vec()),
+        }
+    }
+
+    pub(super) fn load_value(&self, name: &str, row: usize) -> Result<f64, EvalError> {
+        let value = self.try_get_column(name)?[row];
+
+        match self.nan_policy {
+            NanPolicy::Fill(fill) if value.is_nan() => Ok(fill),
+            NanPolicy::Reject if value.is_nan() => Err(EvalErrorKind::InvalidInput.into()),
+            _ => Ok(value),
+        }
+    }
+}
+
+impl<'a> From<&'a BarColumns> for ColumnContext<'a> {
+    fn from(bars: &'a BarColumns) -> Self {
+        let mut context = ColumnContext::new(bars.len());
+        for (name, column) in [
+            ("open", &bars.open),
+            ("high", &bars.high),
+            ("low", &bars.low),
+            ("close", &bars.close),
+            ("volume", &bars.volume),
+        ] {
+            context.columns.insert(name.to_string(), column.as_slice());
+        }
+        context
+    }
+}
+
+impl Expression {
+    // Evaluates the expression for every row at once, row i of the result is what `evaluate`
+    // returns for a Context holding row i of each column
+    pub fn evaluate_columns(&self, context: &ColumnContext) -> Result<Vec<f64>, EvalError> {
+        match self {
+            Expression::Terminal(TerminalData::Constant(value)) => Ok(vec![*value; context.len]),
+            Expression::Terminal(TerminalData::Variable(name)) => context.load_column(name),
+            Expression::Operation(function_node) => {
+                function_node.check_operands()?;
+                if function_node.operation.is_cross_sectional() {
+                    return Err(function_node.error(EvalErrorKind::UnsupportedOperation));
+                }
+                let operands = function_node
+                    .operands
+                    .iter()
+                    .enumerate()
+                    .map(|(index, op)| op.evaluate_columns(context).map_err(|e| e.within(index)))
+                    .collect::<Result<Vec<Vec<f64>>, EvalError>>()?;
+
+                apply_columns(
+                    &function_node.operation,
+                    operands,
+                    context.len,
+                    context.eval_policy,
+                )
+                .map_err(|kind| function_node.error(kind))
+            }
+        }
+    }
+}
+
+//NOTE: unary and binary nodes are computed in place in the first operand's buffer, only wider
+// nodes need a row buffer
+pub(super) fn apply_columns(
+    function: &FunctionData,
+    mut operands: Vec<Vec<f64>>,
+    len: usize,
+    policy: EvalPolicy,
+) -> Result<Vec<f64>, EvalErrorKind> {
+    if function.is_time_series() {
+        return Ok(apply_rolling(function, &operands, len));
+    }
+
+    match operands.len() {
+        0 => Ok(vec![function.apply_policy(policy, &[])?; len]),
+        1 => {
+            let mut column = operands.swap_remove(0);
+            for value in column.iter_mut() {
+                *value = function.apply_policy(policy, &[*value])?;
+            }
+            Ok(column)
+        }
+        2 => {
+            let rhs = operands.swap_remove(1);
+            let mut lhs = operands.swap_remove(0);
+            for (l, r) in lhs.iter_mut().zip(&rhs) {
+                *l = function.apply_policy(policy, &[*l, *r])?;
+            }
+            Ok(lhs)
+        }
+        width => {
+            let mut row = Vec::with_capacity(width);
+            (0..len)
+                .map(|i| {
+                    row.clear();
+                    row.extend(operands.iter().map(|column| column[i]));
+                    function.apply_policy(policy, &row)
+                })
+                .collect()
+        }
+    }
+}
@@ -1,5 +1,6 @@
 #![allow(warnings)]
 pub mod data;
+pub mod evolution;
 pub mod features;
 pub mod gene;
 pub mod traits;
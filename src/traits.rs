@@ -1,15 +1,29 @@
 use crate::{
-    data::types::{Granularity, NormalizedTypes},
-    features::ExecutionContext,
+    data::{
+        db::{PgPool, WriteReport},
+        types::{Granularity, NormalizedTypes},
+    },
+    evolution::Fitness,
+    features::{DataContext, ExecutionContext},
+    gene::{hash, EvalPolicy, Expression, FunctionData, NodeType, TerminalData},
 };
 use async_trait::async_trait;
-use diesel::PgConnection;
 use eyre::Result;
-use std::{error::Error, fs};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fs,
+};
 
+// Produces a feature series, one value per bar of the context in timestamp order
 pub trait Executable {
-    fn execute(&self, context: &ExecutionContext) -> f64;
+    fn execute(&self, context: &ExecutionContext) -> Result<Vec<f64>>;
+}
+// Timestamp a record is keyed and ordered on
+pub trait Timestamped {
+    fn timestamp(&self) -> i64;
 }
+
 pub trait LocalDataMethods {
     type Output;
 
@@ -37,16 +51,22 @@ pub trait IODataMethods {
         first_ts: i64,
         last_ts: i64,
     ) -> Result<Vec<Self::Item>, std::io::Error>;
-    async fn from_db_all_entries(
-        &self,
-        conn: &PgConnection,
-    ) -> Result<Vec<Self::Item>, diesel::result::Error>;
+    // Rows of this dataset in timestamp order, see data::db
+    async fn from_db_all_entries(&self, pool: &PgPool) -> Result<Vec<Self::Item>>;
     async fn db_ts_window(
         &self,
-        conn: &PgConnection,
+        pool: &PgPool,
         first_ts: i64,
         last_ts: i64,
-    ) -> Result<Vec<Self::Item>, diesel::result::Error>;
+    ) -> Result<Vec<Self::Item>>;
+}
+
+//NOTE: Write side of IODataMethods, see data::db::WriteReport for how existing rows are treated
+#[async_trait]
+pub trait DbWriteMethods: IODataMethods {
+    async fn db_write(&self, pool: &PgPool, items: Vec<Self::Item>) -> Result<WriteReport>;
+    // Writes every entry held by the dataset
+    async fn db_write_all(&self, pool: &PgPool) -> Result<WriteReport>;
 }
 
 pub trait DataUpdate {
@@ -54,3 +74,126 @@ pub trait DataUpdate {
 
     fn update(&mut self, data: Self::NewData);
 }
+
+//NOTE: Shared by the gene tree types so generation and the genetic operators are written once.
+// Annotation is whatever a node carries besides its GeneType, () for Expression and the
+// Operation for Gene.
+pub trait GeneticTree: Clone + Sized {
+    type Annotation: Clone;
+
+    fn terminal(annotation: &Self::Annotation, data: TerminalData) -> Self;
+    fn function(annotation: &Self::Annotation, func: FunctionData, children: Vec<Self>) -> Self;
+
+    fn annotation(&self) -> Self::Annotation;
+    fn terminal_data(&self) -> Option<&TerminalData>;
+    fn terminal_data_mut(&mut self) -> Option<&mut TerminalData>;
+    fn function_data(&self) -> Option<&FunctionData>;
+    fn function_data_mut(&mut self) -> Option<&mut FunctionData>;
+    fn children(&self) -> Vec<&Self>;
+    fn children_mut(&mut self) -> Vec<&mut Self>;
+
+    // Trees that record the NodeType of their nodes (Gene) keep it, see TypeEnv::assign
+    fn set_node_type(&mut self, _node_type: NodeType) {}
+
+    fn node_count(&self) -> usize {
+        1 + self
+            .children()
+            .into_iter()
+            .map(|child| child.node_count())
+            .sum::<usize>()
+    }
+
+    // Edges on the longest root to leaf path, a lone terminal has depth 0
+    fn depth(&self) -> usize {
+        self.children()
+            .into_iter()
+            .map(|child| child.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Names of the variables the tree reads
+    fn variables(&self) -> BTreeSet<String> {
+        let mut variables = BTreeSet::new();
+        for (_, node) in self.subtrees() {
+            if let Some(TerminalData::Variable(name)) = node.terminal_data() {
+                variables.insert(name.clone());
+            }
+        }
+        variables
+    }
+
+    fn constant_count(&self) -> usize {
+        self.subtrees()
+            .into_iter()
+            .filter(|(_, node)| matches!(node.terminal_data(), Some(TerminalData::Constant(_))))
+            .count()
+    }
+
+    // FunctionData::name -> number of nodes applying it
+    fn operator_counts(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for (_, node) in self.subtrees() {
+            if let Some(function) = node.function_data() {
+                *counts.entry(function.name()).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    // Every subtree in pre-order with its path, the child indices leading from the root as in
+    // EvalError::path (the root is the empty path)
+    fn subtrees(&self) -> Vec<(Vec<usize>, &Self)> {
+        fn walk<'a, T: GeneticTree>(
+            node: &'a T,
+            path: &mut Vec<usize>,
+            subtrees: &mut Vec<(Vec<usize>, &'a T)>,
+        ) {
+            subtrees.push((path.clone(), node));
+            for (index, child) in node.children().into_iter().enumerate() {
+                path.push(index);
+                walk(child, path, subtrees);
+                path.pop();
+            }
+        }
+        let mut subtrees = Vec::new();
+        walk(self, &mut Vec::new(), &mut subtrees);
+        subtrees
+    }
+
+    fn get_subtree(&self, path: &[usize]) -> Option<&Self> {
+        match path.split_first() {
+            None => Some(self),
+            Some((index, rest)) => self.children().into_iter().nth(*index)?.get_subtree(rest),
+        }
+    }
+
+    fn get_subtree_mut(&mut self, path: &[usize]) -> Option<&mut Self> {
+        match path.split_first() {
+            None => Some(self),
+            Some((index, rest)) => self
+                .children_mut()
+                .into_iter()
+                .nth(*index)?
+                .get_subtree_mut(rest),
+        }
+    }
+
+    // Puts `subtree` at `path` and returns the subtree it replaced, None (and the tree is left as
+    // it was) if the path does not exist
+    fn replace_subtree(&mut self, path: &[usize], subtree: Self) -> Option<Self> {
+        self.get_subtree_mut(path)
+            .map(|node| std::mem::replace(node, subtree))
+    }
+
+    // Equal for trees that only differ in the operand order of Add/Multiply, see gene::hash
+    fn structural_hash(&self) -> u64 {
+        hash::structural_hash(self)
+    }
+}
+
+//NOTE: `policy` is the EvalPolicy the alpha is evolved under and recorded with, the expression
+// has to be evaluated with it (e.g. through Context::with_eval_policy)
+pub trait FitnessFunction {
+    fn evaluate(&self, expression: &Expression, policy: EvalPolicy, data: &DataContext) -> Fitness;
+}
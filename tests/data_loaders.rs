@@ -0,0 +1,513 @@
+//NOTE: Fixtures live in tests/fixtures. bars.csv holds ten one-minute bars from ts 1000 to 1540,
+// ticks.csv eight BTCUSD ticks with runs of equal timestamps at 1000 and 1010 plus one ETHUSD tick.
+// book_wide.csv and book_long.csv are order books in the layouts of data::book_csv, book_long.csv
+// also holds an ETHUSD snapshot.
+use alpha_encoding_ast::{
+    data::{book_csv::BookCsvFormat, stream::CsvStream, types::*},
+    traits::{DataUpdate, IODataMethods, LocalDataMethods},
+};
+use std::io::ErrorKind;
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+// Files written by a test, removed again by the test
+fn scratch(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("alpha_encoding_{}_{}", std::process::id(), name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+// (level, qty, count) per quote
+fn levels(quotes: &[Quotes]) -> Vec<(f64, f64, Option<u64>)> {
+    quotes.iter().map(|q| (q.level, q.qty, q.count)).collect()
+}
+
+fn bar_ts(bars: &[Bar]) -> Vec<i64> {
+    bars.iter().map(Bar::ts).collect()
+}
+
+fn tick_ids(ticks: &[NormalizedTicks]) -> Vec<i64> {
+    ticks.iter().map(|tick| tick.server_id).collect()
+}
+
+fn book(ts: i64, bid: f64) -> NormalizedBook {
+    NormalizedBook {
+        symbol: "BTCUSD".to_string(),
+        depth: 1,
+        bids: vec![Quotes {
+            level: bid,
+            qty: 1.0,
+            count: None,
+        }],
+        asks: vec![Quotes {
+            level: bid + 1.0,
+            qty: 1.0,
+            count: Some(2),
+        }],
+        ts,
+    }
+}
+
+async fn loaded_bars() -> BarDataSet {
+    let mut dataset = BarDataSet::new("BTCUSD".to_string(), BarGranularity::OneMinute);
+    let bars = dataset
+        .from_file_full_dataset(&fixture("bars.csv"))
+        .await
+        .unwrap();
+    dataset.update(bars);
+    dataset
+}
+
+async fn loaded_ticks() -> TickDataSet {
+    let mut dataset = TickDataSet::new("BTCUSD".to_string());
+    let ticks = dataset
+        .from_file_full_dataset(&fixture("ticks.csv"))
+        .await
+        .unwrap();
+    dataset.update(ticks);
+    dataset
+}
+
+#[tokio::test]
+async fn bar_file_loaders_honour_inclusive_bounds() {
+    let dataset = BarDataSet::new("BTCUSD".to_string(), BarGranularity::OneMinute);
+    let path = fixture("bars.csv");
+
+    let all = dataset.from_file_full_dataset(&path).await.unwrap();
+    assert_eq!(all.len(), 10);
+    assert_eq!(all[0].open(), 99.5);
+    assert_eq!(all[9].volume(), 100.0);
+
+    let cases = [
+        ((1060, 1180), vec![1060, 1120, 1180]),
+        ((1061, 1179), vec![1120]),
+        ((1000, 1000), vec![1000]),
+        ((1540, 9999), vec![1540]),
+        ((0, 999), vec![]),
+        ((1541, 9999), vec![]),
+        ((1180, 1060), vec![]),
+    ];
+    for ((first, last), expected) in cases {
+        let window = dataset
+            .from_file_by_ts_window(&path, first, last)
+            .await
+            .unwrap();
+        assert_eq!(bar_ts(&window), expected, "window {}..={}", first, last);
+    }
+
+    let lookback = dataset.from_file_by_ts_lookback(&path, 1480).await.unwrap();
+    assert_eq!(bar_ts(&lookback), vec![1480, 1540]);
+    let lookback = dataset.from_file_by_ts_lookback(&path, 0).await.unwrap();
+    assert_eq!(lookback.len(), 10);
+    let lookback = dataset.from_file_by_ts_lookback(&path, 1541).await.unwrap();
+    assert!(lookback.is_empty());
+}
+
+#[tokio::test]
+async fn bar_file_loaders_report_bad_input() {
+    let dataset = BarDataSet::new("BTCUSD".to_string(), BarGranularity::OneMinute);
+
+    let missing = dataset
+        .from_file_full_dataset(&fixture("missing.csv"))
+        .await
+        .unwrap_err();
+    assert_eq!(missing.kind(), ErrorKind::NotFound);
+
+    let malformed = dataset
+        .from_file_full_dataset(&fixture("bars_malformed.csv"))
+        .await
+        .unwrap_err();
+    assert_eq!(malformed.kind(), ErrorKind::InvalidData);
+
+    // Records before the malformed row are yielded, the error ends the stream
+    let mut stream = CsvStream::<Bar>::open(fixture("bars_malformed.csv"));
+    assert_eq!(stream.next().await.unwrap().unwrap().ts(), 1000);
+    let error = stream.next().await.unwrap().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn bar_streams_stop_past_the_window() {
+    let bounded = CsvStream::<Bar>::window(fixture("bars.csv"), 1060, 1180);
+    assert_eq!(
+        bar_ts(&bounded.try_collect().await.unwrap()),
+        vec![1060, 1120, 1180]
+    );
+
+    // Rows after the first bar past the window are never parsed
+    let path = scratch("bars_trailing_garbage.csv");
+    std::fs::write(
+        &path,
+        "o,h,l,c,v,ts\n1,1,1,1,1,1000\n1,1,1,1,1,1060\n1,1,1,1,1,1120\nnot,a,bar\n",
+    )
+    .unwrap();
+    let bounded = CsvStream::<Bar>::window(&path, 1000, 1060)
+        .try_collect()
+        .await;
+    let unbounded = CsvStream::<Bar>::open(&path).try_collect().await;
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(bar_ts(&bounded.unwrap()), vec![1000, 1060]);
+    assert_eq!(unbounded.unwrap_err().kind(), ErrorKind::InvalidData);
+
+    // Only a bounded number of records is read ahead, dropping the stream stops the reader
+    let path = scratch("bars_many.csv");
+    let rows: String = (0..10_000)
+        .map(|ts| format!("1,1,1,1,1,{}\n", ts))
+        .collect();
+    std::fs::write(&path, format!("o,h,l,c,v,ts\n{}", rows)).unwrap();
+    let mut stream = CsvStream::<Bar>::open(&path);
+    assert_eq!(stream.next().await.unwrap().unwrap().ts(), 0);
+    drop(stream);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn bar_streams_reject_unsorted_files() {
+    let path = scratch("bars_unsorted.csv");
+    std::fs::write(
+        &path,
+        "o,h,l,c,v,ts\n1,1,1,1,1,1000\n1,1,1,1,1,1120\n1,1,1,1,1,1060\n1,1,1,1,1,1180\n",
+    )
+    .unwrap();
+
+    let mut stream = CsvStream::<Bar>::open(&path);
+    assert_eq!(stream.next().await.unwrap().unwrap().ts(), 1000);
+    assert_eq!(stream.next().await.unwrap().unwrap().ts(), 1120);
+    let error = stream.next().await.unwrap().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert!(stream.next().await.is_none());
+
+    // Also when the out-of-order bar falls outside the window
+    let windowed = CsvStream::<Bar>::window(&path, 1100, 1200)
+        .try_collect()
+        .await;
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(windowed.unwrap_err().kind(), ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn bar_updates_and_local_lookups() {
+    let mut dataset = loaded_bars().await;
+    assert_eq!(dataset.columns().len(), 10);
+
+    let lookback = dataset.get_timestamp_lookback(1420).unwrap();
+    assert_eq!(bar_ts(&lookback), vec![1420, 1480, 1540]);
+    assert!(dataset.get_timestamp_lookback(1541).unwrap().is_empty());
+
+    let window = dataset.get_timestamp_window(1000, 1060).unwrap();
+    assert_eq!(bar_ts(&window), vec![1000, 1060]);
+    let window = dataset.get_timestamp_window(1001, 1059).unwrap();
+    assert!(window.is_empty());
+    let window = dataset.get_timestamp_window(1060, 1000).unwrap();
+    assert!(window.is_empty());
+
+    // A bar at an existing timestamp replaces it, a new one is added
+    dataset.update(vec![
+        Bar::new(1060, 1.0, 1.0, 1.0, 1.0, 1.0),
+        Bar::new(1600, 2.0, 2.0, 2.0, 2.0, 2.0),
+    ]);
+    let window = dataset.get_timestamp_window(1060, 1600).unwrap();
+    assert_eq!(window.len(), 10);
+    assert_eq!(window[0].close(), 1.0);
+    assert_eq!(window[9].ts(), 1600);
+}
+
+#[tokio::test]
+async fn tick_file_loaders_keep_equal_timestamps_together() {
+    let dataset = TickDataSet::new("BTCUSD".to_string());
+    let path = fixture("ticks.csv");
+
+    let all = dataset.from_file_full_dataset(&path).await.unwrap();
+    assert_eq!(tick_ids(&all), (1..=8).collect::<Vec<_>>());
+    assert_eq!(all[1].side, Side::Sell);
+
+    let window = dataset
+        .from_file_by_ts_window(&path, 1000, 1010)
+        .await
+        .unwrap();
+    assert_eq!(tick_ids(&window), vec![1, 2, 3, 4, 5, 6]);
+    let window = dataset
+        .from_file_by_ts_window(&path, 1001, 1019)
+        .await
+        .unwrap();
+    assert_eq!(tick_ids(&window), vec![3, 4, 5, 6]);
+
+    let lookback = dataset.from_file_by_ts_lookback(&path, 1010).await.unwrap();
+    assert_eq!(tick_ids(&lookback), vec![4, 5, 6, 7, 8]);
+
+    // Other symbols are dropped, from files and from updates alike
+    let eth = TickDataSet::new("ETHUSD".to_string());
+    let eth_ticks = eth.from_file_full_dataset(&path).await.unwrap();
+    assert_eq!(tick_ids(&eth_ticks), vec![9]);
+    let mut dataset = dataset;
+    dataset.update(eth_ticks);
+    assert!(dataset.is_empty());
+    dataset.update(all);
+    assert_eq!(dataset.len(), 8);
+}
+
+#[tokio::test]
+async fn tick_searches_on_boundaries() {
+    let mut dataset = loaded_ticks().await;
+    assert_eq!(dataset.len(), 8);
+    assert_eq!(dataset.first_timestamp(), Some(1000));
+    assert_eq!(dataset.last_timestamp(), Some(1030));
+    assert_eq!(dataset.back_timestamp(), Some(1030));
+
+    // Every tick of the run at 1010 is included
+    let lookback = dataset.get_data_by_timestamp_lookback(1010).unwrap();
+    assert_eq!(tick_ids(&lookback), vec![4, 5, 6, 7, 8]);
+    let lookback = dataset.get_data_by_timestamp_lookback(1011).unwrap();
+    assert_eq!(tick_ids(&lookback), vec![7, 8]);
+    assert!(dataset
+        .get_data_by_timestamp_lookback(1031)
+        .unwrap()
+        .is_empty());
+
+    let window = dataset.get_data_by_timestamp_window(1000, 1005).unwrap();
+    assert_eq!(tick_ids(&window), vec![1, 2, 3]);
+    let window = dataset.get_data_by_timestamp_window(1006, 1009).unwrap();
+    assert!(window.is_empty());
+
+    assert_eq!(dataset.find_nearest_ts_index(999), 0);
+    assert_eq!(dataset.find_nearest_ts_index(1010), 3);
+    assert_eq!(dataset.find_nearest_ts_index(1011), 6);
+    assert_eq!(dataset.find_nearest_ts_index(1031), 8);
+
+    let index = dataset.binary_search_timestamp_index(1010).unwrap();
+    assert_eq!(dataset.get(index).unwrap().tx_ts, 1010);
+    assert!(dataset.binary_search_timestamp_index(1011).is_err());
+
+    // Late ticks are merged in timestamp order
+    let late = dataset.get(0).map(|tick| NormalizedTicks {
+        server_id: 9,
+        tx_ts: 1015,
+        ..tick
+    });
+    dataset.update(late.into_iter().collect());
+    let window = dataset.get_data_by_timestamp_window(1010, 1020).unwrap();
+    assert_eq!(tick_ids(&window), vec![4, 5, 6, 9, 7]);
+
+    dataset.truncate(2);
+    assert_eq!(dataset.last_timestamp(), Some(1000));
+}
+
+#[test]
+fn book_updates_and_local_lookups() {
+    let mut dataset = BookDataSet::new("BTCUSD".to_string());
+    dataset.update(vec![book(10, 99.0), book(20, 98.0), book(30, 97.0)]);
+
+    let lookback = dataset.get_timestamp_lookback(20).unwrap();
+    assert_eq!(
+        lookback.iter().map(|b| b.ts).collect::<Vec<_>>(),
+        vec![20, 30]
+    );
+    let window = dataset.get_timestamp_window(11, 29).unwrap();
+    assert_eq!(window.len(), 1);
+    assert_eq!(window[0].bids[0].level, 98.0);
+
+    dataset.update(vec![book(20, 50.0)]);
+    let window = dataset.get_timestamp_window(20, 20).unwrap();
+    assert_eq!(window[0].bids[0].level, 50.0);
+
+    // Another symbol's snapshot doesn't replace this one's
+    dataset.update(vec![NormalizedBook {
+        symbol: "ETHUSD".to_string(),
+        ..book(20, 3000.0)
+    }]);
+    let window = dataset.get_timestamp_window(20, 20).unwrap();
+    assert_eq!(window[0].symbol, "BTCUSD");
+    assert_eq!(window[0].bids[0].level, 50.0);
+    assert!(dataset.get_timestamp_window(31, 40).unwrap().is_empty());
+    assert!(dataset.get_timestamp_window(30, 10).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn book_wide_file_honours_depth_and_counts() {
+    let dataset = BookDataSet::new("BTCUSD".to_string());
+    assert_eq!(dataset.format(), BookCsvFormat::Wide);
+    let path = fixture("book_wide.csv");
+
+    let books = dataset.from_file_full_dataset(&path).await.unwrap();
+    assert_eq!(books.len(), 3);
+    assert_eq!(books[0].symbol, "BTCUSD");
+    assert_eq!(books[0].depth, 3);
+    assert_eq!(
+        levels(&books[0].bids),
+        vec![
+            (99.5, 1.0, Some(3)),
+            (99.0, 2.0, None),
+            (98.5, 4.0, Some(1))
+        ]
+    );
+    // No ask count columns
+    assert_eq!(
+        levels(&books[0].asks),
+        vec![(100.0, 1.5, None), (100.5, 2.5, None), (101.0, 3.0, None)]
+    );
+    // Levels past depth are ignored
+    assert_eq!(books[1].depth, 2);
+    assert_eq!(books[1].bids.len(), 2);
+    assert_eq!(books[1].asks.len(), 2);
+    // A thin book keeps its depth
+    assert_eq!(books[2].depth, 3);
+    assert_eq!(levels(&books[2].bids), vec![(99.7, 1.0, Some(1))]);
+    assert_eq!(levels(&books[2].asks), vec![(100.2, 1.0, None)]);
+
+    let window = dataset
+        .from_file_by_ts_window(&path, 1001, 1120)
+        .await
+        .unwrap();
+    assert_eq!(
+        window.iter().map(|b| b.ts).collect::<Vec<_>>(),
+        vec![1060, 1120]
+    );
+    let lookback = dataset.from_file_by_ts_lookback(&path, 1120).await.unwrap();
+    assert_eq!(lookback.len(), 1);
+}
+
+#[tokio::test]
+async fn book_long_file_groups_levels_into_snapshots() {
+    let dataset = BookDataSet::new("BTCUSD".to_string()).with_format(BookCsvFormat::Long);
+    let path = fixture("book_long.csv");
+
+    let books = dataset.from_file_full_dataset(&path).await.unwrap();
+    let keys: Vec<(&str, i64, u16)> = books
+        .iter()
+        .map(|b| (b.symbol.as_str(), b.ts, b.depth))
+        .collect();
+    // The ETHUSD snapshot between them is dropped
+    assert_eq!(keys, vec![("BTCUSD", 1000, 2), ("BTCUSD", 1060, 2)]);
+    // bid level 2 is past depth
+    assert_eq!(
+        levels(&books[0].bids),
+        vec![(99.5, 1.0, Some(3)), (99.0, 2.0, None)]
+    );
+    assert_eq!(
+        levels(&books[0].asks),
+        vec![(100.0, 1.5, None), (100.5, 2.5, Some(2))]
+    );
+
+    let eth = BookDataSet::new("ETHUSD".to_string()).with_format(BookCsvFormat::Long);
+    let eth_books = eth.from_file_full_dataset(&path).await.unwrap();
+    assert_eq!(eth_books.len(), 1);
+    assert!(eth_books[0].bids.is_empty());
+    assert_eq!(levels(&eth_books[0].asks), vec![(3000.0, 10.0, None)]);
+
+    let window = dataset
+        .from_file_by_ts_window(&path, 1000, 1000)
+        .await
+        .unwrap();
+    assert_eq!(window.len(), 1);
+    let lookback = dataset.from_file_by_ts_lookback(&path, 1001).await.unwrap();
+    assert_eq!(levels(&lookback[0].bids), vec![(99.6, 1.0, Some(1))]);
+}
+
+#[tokio::test]
+async fn book_files_round_trip_between_formats() {
+    let wide = BookDataSet::new("BTCUSD".to_string());
+    let long = BookDataSet::new("BTCUSD".to_string()).with_format(BookCsvFormat::Long);
+    let mut books = wide
+        .from_file_full_dataset(&fixture("book_wide.csv"))
+        .await
+        .unwrap();
+    // Quotes past depth are not written
+    books[1].bids.push(Quotes {
+        level: 1.0,
+        qty: 1.0,
+        count: None,
+    });
+    let expected = wide
+        .from_file_full_dataset(&fixture("book_wide.csv"))
+        .await
+        .unwrap();
+
+    // Snapshots of other symbols are written but not loaded
+    books.insert(
+        1,
+        NormalizedBook {
+            symbol: "ETHUSD".to_string(),
+            ..book(1000, 3000.0)
+        },
+    );
+
+    for dataset in [wide, long] {
+        let path = scratch(&format!("book_{:?}.csv", dataset.format()));
+        dataset.file_write(&path, books.clone()).await.unwrap();
+        let loaded = dataset.from_file_full_dataset(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", expected));
+    }
+
+    // Only the asks carry a count, both sides get count columns
+    let mut dataset = BookDataSet::new("BTCUSD".to_string());
+    dataset.update(vec![book(10, 99.0), book(20, 98.0)]);
+    dataset.update(vec![NormalizedBook {
+        asks: Vec::new(),
+        ..book(30, 97.0)
+    }]);
+    let path = scratch("book_no_counts.csv");
+    dataset.file_write_all(&path).await.unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert!(written.starts_with("symbol,ts,depth,bid_px_0,bid_qty_0,bid_count_0,"));
+    let loaded = dataset.from_file_full_dataset(&path).await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        format!("{:?}", loaded),
+        format!("{:?}", dataset.get_timestamp_lookback(0).unwrap())
+    );
+
+    let bids_only = vec![NormalizedBook {
+        asks: Vec::new(),
+        ..book(40, 96.0)
+    }];
+    dataset.file_write(&path, bids_only).await.unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        written,
+        "symbol,ts,depth,bid_px_0,bid_qty_0,ask_px_0,ask_qty_0\nBTCUSD,40,1,96,1,,\n"
+    );
+}
+
+#[tokio::test]
+async fn book_files_report_bad_layouts() {
+    let cases = [
+        (
+            BookCsvFormat::Wide,
+            "symbol,ts,depth,bid_px_0,bid_qty_0\nBTCUSD,1,2,1,1\n",
+        ),
+        (BookCsvFormat::Wide, "symbol,ts,bid_px_0\nBTCUSD,1,1\n"),
+        (
+            BookCsvFormat::Long,
+            "symbol,ts,side,level,px,qty\nBTCUSD,1,bid,1,1,1\n",
+        ),
+        (
+            BookCsvFormat::Long,
+            "symbol,ts,side,level,px,qty\nBTCUSD,1,mid,0,1,1\n",
+        ),
+        (
+            BookCsvFormat::Long,
+            "symbol,ts,depth,side,level,px,qty\nBTCUSD,1,2,bid,0,1,1\nBTCUSD,1,3,ask,0,1,1\n",
+        ),
+    ];
+    for (index, (format, contents)) in cases.into_iter().enumerate() {
+        let path = scratch(&format!("book_bad_{}.csv", index));
+        std::fs::write(&path, contents).unwrap();
+        let result = BookDataSet::new("BTCUSD".to_string())
+            .with_format(format)
+            .from_file_full_dataset(&path)
+            .await;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            result.unwrap_err().kind(),
+            ErrorKind::InvalidData,
+            "case {}",
+            index
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use alpha_encoding_ast::{
+    gene::{
+        ColumnContext, Context, DiffTarget, Expression, FunctionData, FunctionNode, TerminalData,
+    },
+    traits::GeneticTree,
+};
+use proptest::prelude::*;
+
+const STEP: f64 = 1e-5;
+
+fn node(function: FunctionData, operands: Vec<Expression>) -> Expression {
+    Expression::Operation(FunctionNode::new(function, operands))
+}
+
+fn number(value: f64) -> Expression {
+    Expression::Terminal(TerminalData::Constant(value))
+}
+
+// 2 + tanh(e) and 1 + sigmoid(e) keep divisors, logs, roots and bases away from zero
+fn shifted(function: FunctionData, shift: f64, operand: Expression) -> Expression {
+    node(
+        FunctionData::Add,
+        vec![number(shift), node(function, vec![operand])],
+    )
+}
+
+// Trees that are smooth everywhere in x, y and their constants
+fn smooth() -> impl Strategy<Value = Expression> {
+    let terminal = prop_oneof![
+        (-2.0..2.0f64).prop_map(TerminalData::Constant),
+        prop::sample::select(vec!["x", "y"]).prop_map(|name| TerminalData::Variable(name.into())),
+    ]
+    .prop_map(Expression::Terminal);
+
+    terminal.prop_recursive(4, 32, 3, |inner| {
+        prop_oneof![
+            (
+                prop::sample::select(vec![FunctionData::Add, FunctionData::Multiply]),
+                prop::collection::vec(inner.clone(), 2..4)
+            )
+                .prop_map(|(function, ops)| node(function, ops)),
+            prop::collection::vec(inner.clone(), 2)
+                .prop_map(|ops| node(FunctionData::Subtract, ops)),
+            (
+                prop::sample::select(vec![
+                    FunctionData::Tanh,
+                    FunctionData::Sigmoid,
+                    FunctionData::Neg,
+                ]),
+                inner.clone()
+            )
+                .prop_map(|(function, op)| node(function, vec![op])),
+            (inner.clone(), inner.clone()).prop_map(|(a, b)| node(
+                FunctionData::Divide,
+                vec![a, shifted(FunctionData::Tanh, 2.0, b)]
+            )),
+            inner
+                .clone()
+                .prop_map(|a| node(FunctionData::Exp, vec![node(FunctionData::Tanh, vec![a])])),
+            (
+                prop::sample::select(vec![
+                    FunctionData::Log,
+                    FunctionData::Sqrt,
+                    FunctionData::Root { degree: 3.0 },
+                ]),
+                inner.clone()
+            )
+                .prop_map(|(function, a)| node(
+                    function,
+                    vec![shifted(FunctionData::Sigmoid, 1.0, a)]
+                )),
+            (inner.clone(), inner).prop_map(|(a, b)| node(
+                FunctionData::Exponent,
+                vec![
+                    shifted(FunctionData::Tanh, 2.0, a),
+                    node(FunctionData::Tanh, vec![b])
+                ]
+            )),
+        ]
+    })
+}
+
+fn value(expr: &Expression, x: f64, y: f64) -> f64 {
+    let mut context = Context::new();
+    context.push_kv("x", x);
+    context.push_kv("y", y);
+    expr.evaluate(&context).unwrap()
+}
+
+// The tree with its `slot`-th constant (in pre-order) moved by `step`
+fn moved(expr: &Expression, slot: usize, step: f64) -> Expression {
+    let path = expr
+        .subtrees()
+        .into_iter()
+        .filter(|(_, node)| matches!(node.terminal_data(), Some(TerminalData::Constant(_))))
+        .nth(slot)
+        .map(|(path, _)| path)
+        .unwrap();
+    let mut moved = expr.clone();
+    if let Some(TerminalData::Constant(value)) = moved
+        .get_subtree_mut(&path)
+        .and_then(|node| node.terminal_data_mut())
+    {
+        *value += step;
+    }
+    moved
+}
+
+// Central difference along `target` at (x, y)
+fn finite_difference(expr: &Expression, target: &DiffTarget, x: f64, y: f64) -> f64 {
+    let (high, low) = match target {
+        DiffTarget::Variable(name) if name == "x" => {
+            (value(expr, x + STEP, y), value(expr, x - STEP, y))
+        }
+        DiffTarget::Variable(_) => (value(expr, x, y + STEP), value(expr, x, y - STEP)),
+        DiffTarget::Constant(slot) => (
+            value(&moved(expr, *slot, STEP), x, y),
+            value(&moved(expr, *slot, -STEP), x, y),
+        ),
+    };
+    (high - low) / (2.0 * STEP)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn derivatives_match_central_differences(
+        expr in smooth(),
+        x in -2.0..2.0f64,
+        y in -2.0..2.0f64,
+        slot in any::<prop::sample::Index>(),
+    ) {
+        let mut targets = vec![
+            DiffTarget::Variable("x".to_string()),
+            DiffTarget::Variable("y".to_string()),
+        ];
+        let constants = expr.constant_count();
+        if constants > 0 {
+            targets.push(DiffTarget::Constant(slot.index(constants)));
+        }
+
+        for target in targets {
+            let derivative = expr.derivative(&target).unwrap();
+            let exact = value(&derivative, x, y);
+            let numeric = finite_difference(&expr, &target, x, y);
+            prop_assert!(
+                (exact - numeric).abs() <= 1e-5 * exact.abs().max(1.0),
+                "d/d{:?} of {} at x = {}, y = {}: {} = {} vs {}",
+                target, expr, x, y, derivative, exact, numeric
+            );
+        }
+    }
+}
+
+#[test]
+fn unknown_targets() {
+    let expr: Expression = "x * 2 + 3".parse().unwrap();
+    assert_eq!(
+        expr.derivative(&DiffTarget::Variable("y".to_string()))
+            .unwrap()
+            .to_string(),
+        "0"
+    );
+    assert_eq!(
+        expr.derivative(&DiffTarget::Constant(1))
+            .unwrap()
+            .to_string(),
+        "1"
+    );
+    assert!(expr.derivative(&DiffTarget::Constant(2)).is_err());
+}
+
+#[test]
+fn ts_min_and_ts_max_ignore_the_lags_they_do_not_pick() {
+    // d sqrt(x) / dx is inf at x = 0, two rows before the maximum
+    let x = [0.0, 4.0, 1.0, 9.0];
+    let mut context = ColumnContext::new(x.len());
+    context.push_column("x", &x).unwrap();
+    let target = DiffTarget::Variable("x".to_string());
+
+    let expr: Expression = "ts_max(sqrt(x), 3)".parse().unwrap();
+    let values = expr
+        .derivative(&target)
+        .unwrap()
+        .evaluate_columns(&context)
+        .unwrap();
+    assert!(values[..2].iter().all(|v| v.is_nan()));
+    assert_eq!(values[2..], [0.25, 1.0 / 6.0]);
+
+    let expr: Expression = "ts_min(sqrt(x + 1), 3)".parse().unwrap();
+    let values = expr
+        .derivative(&target)
+        .unwrap()
+        .evaluate_columns(&context)
+        .unwrap();
+    assert_eq!(values[2..], [0.5, 1.0 / (2.0 * 2.0f64.sqrt())]);
+}
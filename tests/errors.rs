@@ -0,0 +1,184 @@
+use alpha_encoding_ast::{
+    gene::{
+        Arity, ColumnContext, Context, EvalError, EvalErrorKind, EvalPolicy, Expression,
+        FunctionData, FunctionNode, PanelContext, Program, TerminalData,
+    },
+    traits::GeneticTree,
+};
+
+fn context(eval_policy: EvalPolicy) -> Context {
+    let mut context = Context::new().with_eval_policy(eval_policy);
+    for (name, value) in [("a", 1.0), ("b", 4.0), ("c", 0.0)] {
+        context.push_kv(name, value);
+    }
+    context
+}
+
+// The error's path leads to the node that failed, its operator is that node's function
+fn assert_points_at(expr: &Expression, error: &EvalError, failing: &str) {
+    let node = expr
+        .get_subtree(&error.path)
+        .unwrap_or_else(|| panic!("{:?} is not a node of {}", error.path, expr));
+    assert_eq!(node.to_string(), failing, "{:?} in {}", error, expr);
+    assert_eq!(error.operator.as_ref(), node.function_data());
+}
+
+#[test]
+fn paths_point_at_the_failing_node() {
+    let cases = [
+        (
+            "a + sqrt(b - log(c))",
+            EvalPolicy::Strict,
+            EvalErrorKind::NonPositiveLog,
+            "log(c)",
+            vec![1, 0, 1],
+        ),
+        (
+            "a * (b + zz)",
+            EvalPolicy::Ieee,
+            EvalErrorKind::UndefinedVariable("zz".to_string()),
+            "zz",
+            vec![1, 1],
+        ),
+        (
+            "b / c",
+            EvalPolicy::Strict,
+            EvalErrorKind::DivisionByZero,
+            "b / c",
+            vec![],
+        ),
+        (
+            "max(a, if_then_else(a, sqrt(c - b), b))",
+            EvalPolicy::Strict,
+            EvalErrorKind::NegativeSqrt,
+            "sqrt(c - b)",
+            vec![1, 1],
+        ),
+        (
+            "a - delay(b, 1)",
+            EvalPolicy::Ieee,
+            EvalErrorKind::UnsupportedOperation,
+            "delay(b, 1)",
+            vec![1],
+        ),
+    ];
+    for (input, policy, kind, failing, path) in cases {
+        let expr: Expression = input.parse().unwrap();
+        let error = expr.evaluate(&context(policy)).unwrap_err();
+        assert_eq!(error.kind, kind, "{}", input);
+        assert_eq!(error.path, path, "{}", input);
+        assert_points_at(&expr, &error, failing);
+    }
+
+    // A node checks its operand count before it evaluates its operands
+    let leaf = |name: &str| Expression::Terminal(TerminalData::Variable(name.to_string()));
+    let expr = Expression::Operation(FunctionNode::new(
+        FunctionData::Add,
+        vec![
+            leaf("a"),
+            Expression::Operation(FunctionNode::new(
+                FunctionData::Sqrt,
+                vec![leaf("zz"), leaf("b")],
+            )),
+        ],
+    ));
+    let error = expr.evaluate(&context(EvalPolicy::Ieee)).unwrap_err();
+    assert_eq!(
+        error.kind,
+        EvalErrorKind::IncorrectOperandCount {
+            expected: Arity::Exact(1),
+            found: 2
+        }
+    );
+    assert_eq!(error.path, vec![1]);
+    assert_eq!(error.operator, Some(FunctionData::Sqrt));
+}
+
+#[test]
+fn columns_and_panels_report_the_same_paths() {
+    let a = [1.0, 2.0, 3.0];
+    let c = [1.0, 0.0, 2.0];
+    let mut columns = ColumnContext::new(3).with_eval_policy(EvalPolicy::Strict);
+    columns.push_column("a", &a).unwrap();
+    columns.push_column("c", &c).unwrap();
+
+    let expr: Expression = "a + ts_mean(a / c, 2)".parse().unwrap();
+    for error in [
+        expr.evaluate_columns(&columns).unwrap_err(),
+        expr.evaluate_at(&columns, 2).unwrap_err(),
+    ] {
+        assert_eq!(error.kind, EvalErrorKind::DivisionByZero);
+        assert_eq!(error.path, vec![1, 0]);
+        assert_points_at(&expr, &error, "a / c");
+    }
+    // Row 0 does not read row 1
+    assert!(expr.evaluate_at(&columns, 0).is_ok());
+
+    let expr: Expression = "a * rank(c)".parse().unwrap();
+    let error = expr.evaluate_columns(&columns).unwrap_err();
+    assert_eq!(error.kind, EvalErrorKind::UnsupportedOperation);
+    assert_points_at(&expr, &error, "rank(c)");
+
+    let mut panel = PanelContext::new(3);
+    panel.push_symbol("s", columns).unwrap();
+    let expr: Expression = "a - industry_neutralize(c, sector)".parse().unwrap();
+    let error = expr.evaluate_panel(&panel).unwrap_err();
+    assert_eq!(
+        error.kind,
+        EvalErrorKind::UndefinedVariable("sector".to_string())
+    );
+    assert_points_at(&expr, &error, "industry_neutralize(c, sector)");
+}
+
+#[test]
+fn display_names_the_operator_and_path() {
+    let expr: Expression = "a + sqrt(b - log(c))".parse().unwrap();
+    let error = expr.evaluate(&context(EvalPolicy::Strict)).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "logarithm of a non-positive number in `log` at node path 1.0.1"
+    );
+
+    let expr: Expression = "b / c".parse().unwrap();
+    let error = expr.evaluate(&context(EvalPolicy::Strict)).unwrap_err();
+    assert_eq!(error.to_string(), "division by zero in `div` at the root");
+
+    let expr: Expression = "zz".parse().unwrap();
+    let error = expr.evaluate(&context(EvalPolicy::Strict)).unwrap_err();
+    assert_eq!(error.to_string(), "undefined variable `zz`");
+}
+
+#[test]
+fn programs_report_the_node_path_and_follow_the_context_policy() {
+    let expr: Expression = "a + b / c".parse().unwrap();
+    let program = Program::compile(&expr)
+        .unwrap()
+        .with_eval_policy(EvalPolicy::Strict);
+    let context = context(EvalPolicy::Strict);
+    let error = program.run_context(&context).unwrap_err();
+    assert_eq!(error, expr.evaluate(&context).unwrap_err());
+    assert_eq!(error.path, vec![1]);
+    assert_eq!(
+        error.to_string(),
+        "division by zero in `div` at node path 1"
+    );
+    assert_eq!(program.run(&[1.0, 4.0, 0.0]).unwrap_err(), error);
+
+    let a = [1.0, 2.0];
+    let c = [1.0, 0.0];
+    let mut columns = ColumnContext::new(2).with_eval_policy(EvalPolicy::Strict);
+    columns.push_column("a", &a).unwrap();
+    columns.push_column("b", &a).unwrap();
+    columns.push_column("c", &c).unwrap();
+    assert_eq!(program.run_columns(&columns).unwrap_err(), error);
+
+    // The context's policy wins over the program's
+    let ieee = Program::compile(&expr).unwrap();
+    assert_eq!(ieee.eval_policy(), EvalPolicy::Ieee);
+    assert_eq!(ieee.run_context(&context).unwrap_err(), error);
+    assert_eq!(ieee.run_columns(&columns).unwrap_err(), error);
+    assert_eq!(ieee.run(&[1.0, 4.0, 0.0]).unwrap(), f64::INFINITY);
+    // A protected quotient is 1
+    let protected = self::context(EvalPolicy::Protected);
+    assert_eq!(program.run_context(&protected).unwrap(), 2.0);
+}
@@ -0,0 +1,211 @@
+use alpha_encoding_ast::gene::{
+    ColumnContext, Context, EvalError, EvalErrorKind, EvalPolicy, Expression, FunctionData,
+    FunctionNode, NanPolicy, Program, TerminalData,
+};
+use proptest::prelude::*;
+
+// `d` is never bound
+const VARIABLES: [&str; 4] = ["a", "b", "c", "d"];
+const POLICIES: [EvalPolicy; 3] = [EvalPolicy::Ieee, EvalPolicy::Protected, EvalPolicy::Strict];
+
+fn value() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        4 => -5.0..5.0f64,
+        1 => Just(0.0),
+        1 => Just(-1.0),
+        1 => Just(f64::NAN),
+        1 => Just(f64::INFINITY),
+        1 => Just(f64::NEG_INFINITY),
+    ]
+}
+
+fn terminal() -> impl Strategy<Value = Expression> {
+    prop_oneof![
+        value().prop_map(TerminalData::Constant),
+        prop::sample::select(VARIABLES.to_vec())
+            .prop_map(|name| TerminalData::Variable(name.to_string())),
+    ]
+    .prop_map(Expression::Terminal)
+}
+
+// Trees of pointwise functions, the ones `evaluate` supports
+fn pointwise() -> impl Strategy<Value = Expression> {
+    terminal().prop_recursive(4, 32, 3, |inner| {
+        let node = |function: FunctionData, operands: Vec<Expression>| {
+            Expression::Operation(FunctionNode::new(function, operands))
+        };
+        prop_oneof![
+            (
+                prop::sample::select(vec![FunctionData::Add, FunctionData::Multiply]),
+                prop::collection::vec(inner.clone(), 0..4)
+            )
+                .prop_map(move |(function, ops)| node(function, ops)),
+            (
+                prop::sample::select(vec![
+                    FunctionData::Subtract,
+                    FunctionData::Divide,
+                    FunctionData::Exponent,
+                    FunctionData::Min,
+                    FunctionData::Max,
+                    FunctionData::GreaterThan,
+                    FunctionData::LessThan,
+                    FunctionData::GreaterOrEqual,
+                    FunctionData::LessOrEqual,
+                    FunctionData::Equal,
+                    FunctionData::NotEqual,
+                ]),
+                prop::collection::vec(inner.clone(), 2)
+            )
+                .prop_map(move |(function, ops)| node(function, ops)),
+            (
+                prop::sample::select(vec![
+                    FunctionData::Sqrt,
+                    FunctionData::Root { degree: 2.0 },
+                    FunctionData::Root { degree: 3.0 },
+                    FunctionData::Log,
+                    FunctionData::Exp,
+                    FunctionData::Abs,
+                    FunctionData::Neg,
+                    FunctionData::Sign,
+                    FunctionData::Tanh,
+                    FunctionData::Sigmoid,
+                    FunctionData::Clip { lo: -1.0, hi: 2.0 },
+                ]),
+                inner.clone()
+            )
+                .prop_map(move |(function, op)| node(function, vec![op])),
+            prop::collection::vec(inner, 3)
+                .prop_map(move |ops| node(FunctionData::IfThenElse, ops)),
+        ]
+    })
+}
+
+// Equal length columns for a, b and c
+fn columns() -> impl Strategy<Value = Vec<Vec<f64>>> {
+    (1..8usize).prop_flat_map(|len| prop::collection::vec(prop::collection::vec(value(), len), 3))
+}
+
+fn nan_policy() -> impl Strategy<Value = NanPolicy> {
+    prop_oneof![
+        Just(NanPolicy::Propagate),
+        value().prop_map(NanPolicy::Fill),
+        Just(NanPolicy::Reject),
+    ]
+}
+
+fn same_value(a: f64, b: f64) -> bool {
+    a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan())
+}
+
+// Values for a, b and c, `d` stays unbound
+fn row() -> impl Strategy<Value = Vec<f64>> {
+    prop::collection::vec(value(), 3)
+}
+
+// `evaluate` on row `row`, with the NaN policy applied to the values put in the Context
+fn evaluate_row(
+    expr: &Expression,
+    columns: &[Vec<f64>],
+    row: usize,
+    nan_policy: NanPolicy,
+    eval_policy: EvalPolicy,
+) -> Result<f64, EvalError> {
+    let mut context = Context::new().with_eval_policy(eval_policy);
+    let mut rejected = Vec::new();
+    for (name, column) in VARIABLES.iter().zip(columns) {
+        match (nan_policy, column[row]) {
+            (NanPolicy::Fill(fill), value) if value.is_nan() => context.push_kv(name, fill),
+            (NanPolicy::Reject, value) if value.is_nan() => rejected.push(name.to_string()),
+            (_, value) => context.push_kv(name, value),
+        }
+    }
+    // A rejected value is left out of the Context, reading it is the InvalidInput of the columns
+    expr.evaluate(&context).map_err(|mut error| {
+        if let EvalErrorKind::UndefinedVariable(name) = &error.kind {
+            if rejected.contains(name) {
+                error.kind = EvalErrorKind::InvalidInput;
+            }
+        }
+        error
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    // The columns fail iff some row fails, and then with the error of one of the failing rows:
+    // evaluate_columns finishes each operand on every row before it moves to the next one
+    #[test]
+    fn columns_match_row_by_row_evaluation(
+        expr in pointwise(),
+        columns in columns(),
+        nan_policy in nan_policy(),
+    ) {
+        let len = columns[0].len();
+        for eval_policy in POLICIES {
+            let mut context = ColumnContext::new(len)
+                .with_nan_policy(nan_policy)
+                .with_eval_policy(eval_policy);
+            for (name, column) in VARIABLES.iter().zip(&columns) {
+                context.push_column(name, column).unwrap();
+            }
+            let rows: Vec<Result<f64, EvalError>> = (0..len)
+                .map(|row| evaluate_row(&expr, &columns, row, nan_policy, eval_policy))
+                .collect();
+
+            match expr.evaluate_columns(&context) {
+                Ok(values) => {
+                    prop_assert_eq!(values.len(), len);
+                    for (row, (value, expected)) in values.iter().zip(&rows).enumerate() {
+                        let expected = expected.clone();
+                        prop_assert!(
+                            matches!(expected, Ok(expected) if same_value(*value, expected)),
+                            "{} under {:?}/{:?}, row {}: {} vs {:?}",
+                            expr, nan_policy, eval_policy, row, value, expected
+                        );
+                    }
+                }
+                Err(error) => prop_assert!(
+                    rows.contains(&Err(error.clone())),
+                    "{} under {:?}/{:?}: {:?} vs {:?}",
+                    expr, nan_policy, eval_policy, error, rows
+                ),
+            }
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    // A program looks all its slots up before it runs, so an unbound variable is reported ahead
+    // of errors the tree walk meets first. Other errors point at the same node.
+    #[test]
+    fn programs_match_tree_evaluation(expr in pointwise(), values in row()) {
+        for eval_policy in POLICIES {
+            let mut context = Context::new().with_eval_policy(eval_policy);
+            for (name, value) in VARIABLES.iter().zip(&values) {
+                context.push_kv(name, *value);
+            }
+            let program = Program::compile(&expr).unwrap().with_eval_policy(eval_policy);
+
+            match (expr.evaluate(&context), program.run_context(&context)) {
+                (Ok(expected), Ok(value)) => prop_assert!(
+                    same_value(value, expected),
+                    "{} under {:?}: {} vs {}",
+                    expr, eval_policy, value, expected
+                ),
+                (Err(expected), Err(error)) => {
+                    if !matches!(error.kind, EvalErrorKind::UndefinedVariable(_)) {
+                        prop_assert_eq!(error, expected, "{}", expr);
+                    }
+                }
+                (expected, result) => prop_assert!(
+                    false,
+                    "{} under {:?}: {:?} vs {:?}",
+                    expr, eval_policy, result, expected
+                ),
+            }
+        }
+    }
+}
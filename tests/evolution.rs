@@ -0,0 +1,512 @@
+use alpha_encoding_ast::{
+    data::types::{BarDataSet, BarGranularity, NormalizedTypes},
+    evolution::*,
+    features::{DataContext, DataSource},
+    gene::{
+        generator::{TerminalSpec, TreeGenerator},
+        operators::GeneticOperators,
+        ColumnContext, ConstantOptimizer, EvalPolicy, Expression, FitData, FunctionData,
+        OptimizationMethod,
+    },
+    traits::FitnessFunction,
+};
+use std::{cell::Cell, collections::HashMap};
+
+fn data() -> DataContext {
+    let bars = || {
+        NormalizedTypes::Bar(BarDataSet::new(
+            "BTCUSD".to_string(),
+            BarGranularity::OneMinute,
+        ))
+    };
+    DataContext::new(bars(), DataSource::Historical(bars()))
+}
+
+fn operators() -> GeneticOperators {
+    let generator = TreeGenerator::new(
+        vec![
+            TerminalSpec::Variable("x".to_string()),
+            TerminalSpec::EphemeralConstant {
+                min: -2.0,
+                max: 2.0,
+            },
+        ],
+        vec![
+            FunctionData::Add,
+            FunctionData::Multiply,
+            FunctionData::Subtract,
+        ],
+    )
+    .unwrap()
+    .with_depth(1, 4)
+    .unwrap();
+    GeneticOperators::new(generator).with_limits(8, 40)
+}
+
+const XS: [f64; 8] = [-2.0, -1.5, -1.0, -0.5, 0.0, 0.5, 1.0, 2.0];
+
+// Negative mean squared error against x^2 + 1
+fn fit_quadratic(expression: &Expression, policy: EvalPolicy, _: &DataContext) -> f64 {
+    let mut context = ColumnContext::new(XS.len()).with_eval_policy(policy);
+    context.push_column("x", &XS).unwrap();
+    let Ok(values) = expression.evaluate_columns(&context) else {
+        return f64::NAN;
+    };
+    let error: f64 = values
+        .iter()
+        .zip(XS)
+        .map(|(value, x)| (value - (x * x + 1.0)).powi(2))
+        .sum();
+    -error / XS.len() as f64
+}
+
+fn config(population_size: usize, selection: Selection) -> EvolutionConfig {
+    EvolutionConfig {
+        population_size,
+        selection,
+        max_generations: 8,
+        seed: 42,
+        ..EvolutionConfig::default()
+    }
+}
+
+// Fitness looked up by the printed expression, unknown trees are invalid
+struct Table(HashMap<String, Fitness>);
+
+impl FitnessFunction for Table {
+    fn evaluate(&self, expression: &Expression, _: EvalPolicy, _: &DataContext) -> Fitness {
+        self.0
+            .get(&expression.to_string())
+            .cloned()
+            .unwrap_or_else(|| Fitness::scalar(f64::NAN))
+    }
+}
+
+// Selection only: offspring are unchanged copies of the selected parents
+fn selections(selection: Selection, table: &[(&str, Fitness)], draws: usize) -> Vec<String> {
+    let config = EvolutionConfig {
+        population_size: draws,
+        elitism: 0,
+        crossover_rate: 0.0,
+        mutation_rate: 0.0,
+        selection,
+        cache_fitness: false,
+        ..EvolutionConfig::default()
+    };
+    let fitness = Table(
+        table
+            .iter()
+            .map(|(name, fitness)| (name.to_string(), fitness.clone()))
+            .collect(),
+    );
+    let mut evolver = Evolver::new(config, operators(), fitness).unwrap();
+    let data = data();
+    let individuals = table
+        .iter()
+        .map(|(name, _)| evolver.evaluate(name.parse().unwrap(), &data))
+        .collect();
+
+    let next = evolver.step(&Population::new(individuals, 0), &data);
+    next.individuals()
+        .iter()
+        .map(|individual| individual.expression.to_string())
+        .collect()
+}
+
+#[test]
+fn same_seed_gives_the_same_run() {
+    let run = |seed: u64| {
+        let seeded = EvolutionConfig {
+            seed,
+            ..config(60, Selection::Tournament { size: 4 })
+        };
+        let mut evolver = Evolver::new(seeded, operators(), fit_quadratic).unwrap();
+        let result = evolver.run(&data()).unwrap();
+        (
+            result.best.expression.to_string(),
+            format!("{:?}", result.history),
+        )
+    };
+    let first = run(42);
+    assert_eq!(first, run(42));
+    assert_ne!(first, run(43));
+}
+
+#[test]
+fn evolution_improves_and_keeps_the_elites() {
+    let mut evolver = Evolver::new(
+        config(100, Selection::Tournament { size: 5 }),
+        operators(),
+        fit_quadratic,
+    )
+    .unwrap();
+    let result = evolver.run(&data()).unwrap();
+
+    assert_eq!(result.stop_reason, StopReason::MaxGenerations);
+    assert_eq!(result.history.len(), 9);
+    assert_eq!(result.population.generation(), 8);
+    assert_eq!(result.population.len(), 100);
+    // With elitism the best score never drops
+    for pair in result.history.windows(2) {
+        assert!(pair[1].best_fitness >= pair[0].best_fitness);
+    }
+    assert!(result.history[8].best_fitness > result.history[0].best_fitness);
+}
+
+#[test]
+fn runs_stop_on_target_or_stagnation() {
+    let reached = EvolutionConfig {
+        target_fitness: Some(f64::NEG_INFINITY),
+        ..config(20, Selection::Roulette)
+    };
+    let mut evolver = Evolver::new(reached, operators(), fit_quadratic).unwrap();
+    let result = evolver.run(&data()).unwrap();
+    assert_eq!(result.stop_reason, StopReason::TargetFitness);
+    assert_eq!(result.history.len(), 1);
+
+    let stagnating = EvolutionConfig {
+        stagnation_limit: Some(2),
+        max_generations: 1000,
+        ..config(20, Selection::Lexicase)
+    };
+    let constant = |_: &Expression, _: EvalPolicy, _: &DataContext| 1.0;
+    let mut evolver = Evolver::new(stagnating, operators(), constant).unwrap();
+    let result = evolver.run(&data()).unwrap();
+    assert_eq!(result.stop_reason, StopReason::Stagnation);
+    assert_eq!(result.history.len(), 3);
+}
+
+#[test]
+fn degenerate_sizes_work() {
+    for (population_size, selection) in [
+        (30, Selection::Tournament { size: 1 }),
+        (1, Selection::Tournament { size: 3 }),
+        (1, Selection::Lexicase),
+        (1, Selection::Roulette),
+    ] {
+        let small = EvolutionConfig {
+            elitism: population_size.min(2),
+            ..config(population_size, selection)
+        };
+        let mut evolver = Evolver::new(small, operators(), fit_quadratic).unwrap();
+        let result = evolver.run(&data()).unwrap();
+        assert_eq!(result.population.len(), population_size);
+        assert_eq!(result.history.len(), 9);
+    }
+
+    let mut evolver =
+        Evolver::new(config(5, Selection::Roulette), operators(), fit_quadratic).unwrap();
+    assert!(evolver
+        .run_from(Population::new(Vec::new(), 0), &data())
+        .is_err());
+}
+
+#[test]
+fn lexicase_selects_on_cases() {
+    // `c` has the best score but is never the best on a case
+    let table = [
+        ("a", Fitness::new(1.0, vec![1.0, 0.0])),
+        ("b", Fitness::new(1.0, vec![0.0, 1.0])),
+        ("c", Fitness::new(5.0, vec![0.5, 0.5])),
+        ("d", Fitness::new(0.0, vec![0.0, 0.0])),
+    ];
+    let selected = selections(Selection::Lexicase, &table, 200);
+    assert!(selected.iter().all(|name| name == "a" || name == "b"));
+    assert!(selected.iter().any(|name| name == "a"));
+    assert!(selected.iter().any(|name| name == "b"));
+
+    // Without cases it picks the best score
+    let table = [
+        ("a", Fitness::scalar(1.0)),
+        ("b", Fitness::scalar(3.0)),
+        ("c", Fitness::scalar(f64::NAN)),
+    ];
+    let selected = selections(Selection::Lexicase, &table, 50);
+    assert!(selected.iter().all(|name| name == "b"));
+}
+
+#[test]
+fn tournament_and_roulette_prefer_fitter_individuals() {
+    let table = [
+        ("a", Fitness::scalar(0.0)),
+        ("b", Fitness::scalar(1.0)),
+        ("c", Fitness::scalar(10.0)),
+        ("d", Fitness::scalar(f64::NAN)),
+    ];
+    let count = |selected: &[String], name: &str| selected.iter().filter(|s| *s == name).count();
+
+    let selected = selections(Selection::Tournament { size: 4 }, &table, 400);
+    assert!(count(&selected, "c") > count(&selected, "b"));
+    // A size 1 tournament is a uniform draw
+    let selected = selections(Selection::Tournament { size: 1 }, &table, 400);
+    assert!(table.iter().all(|(name, _)| count(&selected, name) > 0));
+
+    // Invalid individuals get no share of the wheel, the lowest valid score a small one
+    let selected = selections(Selection::Roulette, &table, 400);
+    assert_eq!(count(&selected, "d"), 0);
+    assert!(count(&selected, "a") > 0);
+    assert!(count(&selected, "c") > count(&selected, "b"));
+    assert!(count(&selected, "b") > count(&selected, "a"));
+}
+
+#[test]
+fn infinite_scores_rank_below_finite_ones() {
+    let table = [
+        ("a", Fitness::scalar(0.0)),
+        ("b", Fitness::scalar(1.0)),
+        ("c", Fitness::scalar(f64::INFINITY)),
+    ];
+    let count = |selected: &[String], name: &str| selected.iter().filter(|s| *s == name).count();
+    // A tournament over the whole table draws every individual most of the time
+    let selected = selections(Selection::Tournament { size: 12 }, &table, 200);
+    assert_eq!(count(&selected, "c"), 0);
+    let selected = selections(Selection::Roulette, &table, 200);
+    assert_eq!(count(&selected, "c"), 0);
+
+    // Best, elites and the target ignore the infinite score
+    let infinite = |expression: &Expression, policy: EvalPolicy, data: &DataContext| {
+        if expression.to_string() == "x" {
+            f64::INFINITY
+        } else {
+            fit_quadratic(expression, policy, data)
+        }
+    };
+    let kept = EvolutionConfig {
+        elitism: 1,
+        crossover_rate: 0.0,
+        mutation_rate: 0.0,
+        max_generations: 2,
+        target_fitness: Some(0.0),
+        ..config(2, Selection::Tournament { size: 1 })
+    };
+    let mut evolver = Evolver::new(kept, operators(), infinite).unwrap();
+    let data = data();
+    let individuals = ["x", "x * x"]
+        .iter()
+        .map(|input| evolver.evaluate(input.parse().unwrap(), &data))
+        .collect();
+    let population = Population::new(individuals, 0);
+    assert_eq!(population.best().unwrap().expression.to_string(), "x * x");
+
+    let result = evolver.run_from(population, &data).unwrap();
+    assert_eq!(result.stop_reason, StopReason::MaxGenerations);
+    assert_eq!(result.best.expression.to_string(), "x * x");
+    assert_eq!(result.history[0].best_fitness, -1.0);
+}
+
+#[test]
+fn constants_are_fitted_every_few_generations() {
+    // Negative mean squared error against 3x
+    let fit_line = |expression: &Expression, policy: EvalPolicy, _: &DataContext| -> f64 {
+        let mut context = ColumnContext::new(XS.len()).with_eval_policy(policy);
+        context.push_column("x", &XS).unwrap();
+        let Ok(values) = expression.evaluate_columns(&context) else {
+            return f64::NAN;
+        };
+        let error: f64 = values
+            .iter()
+            .zip(XS)
+            .map(|(value, x)| (value - 3.0 * x).powi(2))
+            .sum();
+        -error / XS.len() as f64
+    };
+    let mut fit_data = FitData::new(XS.iter().map(|x| 3.0 * x).collect());
+    fit_data.push_column("x", XS.to_vec()).unwrap();
+
+    // The lone individual is an elite, only the schedule changes it
+    let config = EvolutionConfig {
+        elitism: 1,
+        crossover_rate: 0.0,
+        mutation_rate: 0.0,
+        max_generations: 7,
+        constant_optimization: Some(ConstantSchedule {
+            every: 3,
+            elites: 1,
+            optimizer: ConstantOptimizer::new(OptimizationMethod::NelderMead)
+                .with_max_iterations(1),
+        }),
+        ..config(1, Selection::Tournament { size: 1 })
+    };
+    let data = data();
+    let mut evolver = Evolver::new(config, operators(), fit_line)
+        .unwrap()
+        .with_fit_data(fit_data);
+    let start = evolver.evaluate("1 * x".parse().unwrap(), &data);
+    let result = evolver
+        .run_from(Population::new(vec![start], 0), &data)
+        .unwrap();
+
+    let history = &result.history;
+    let changed: Vec<usize> = (1..history.len())
+        .filter(|generation| {
+            history[*generation].best_fitness != history[generation - 1].best_fitness
+        })
+        .collect();
+    assert_eq!(changed, vec![3, 6]);
+    assert!(result.best.expression.constants()[0] > 1.0);
+}
+
+#[test]
+fn cache_matches_trees_up_to_binary_operand_order() {
+    let tree = |input: &str| -> Expression { input.parse().unwrap() };
+    let mut cache = FitnessCache::new();
+    cache.insert(&tree("x + 1"), Fitness::scalar(1.0));
+    cache.insert(&tree("x - 1"), Fitness::scalar(2.0));
+    cache.insert(&tree("(x + 2) * 3"), Fitness::scalar(3.0));
+    cache.insert(&tree("x + 1 + 2"), Fitness::scalar(4.0));
+    assert_eq!(cache.len(), 4);
+
+    assert_eq!(cache.get(&tree("1 + x")), Some(&Fitness::scalar(1.0)));
+    assert_eq!(cache.get(&tree("3 * (2 + x)")), Some(&Fitness::scalar(3.0)));
+    assert_eq!(cache.get(&tree("x + 1 + 2")), Some(&Fitness::scalar(4.0)));
+    // Subtraction does not commute, a reordered chain can round differently
+    assert_eq!(cache.get(&tree("1 - x")), None);
+    assert_eq!(cache.get(&tree("x + 2 + 1")), None);
+    assert_eq!(cache.get(&tree("x + 1.5")), None);
+    assert_eq!((cache.hits(), cache.misses()), (3, 3));
+
+    // Inserting an equal tree replaces its entry
+    cache.insert(&tree("1 + x"), Fitness::scalar(5.0));
+    assert_eq!(cache.len(), 4);
+    assert_eq!(cache.get(&tree("x + 1")), Some(&Fitness::scalar(5.0)));
+
+    cache.clear();
+    assert!(cache.is_empty());
+    assert_eq!((cache.hits(), cache.misses()), (0, 0));
+}
+
+#[test]
+fn evolver_scores_each_distinct_tree_once_per_run() {
+    let calls = Cell::new(0);
+    let counting = |expression: &Expression, policy: EvalPolicy, data: &DataContext| {
+        calls.set(calls.get() + 1);
+        fit_quadratic(expression, policy, data)
+    };
+    // Offspring are copies of the one individual
+    let copies = EvolutionConfig {
+        elitism: 0,
+        crossover_rate: 0.0,
+        mutation_rate: 0.0,
+        max_generations: 1,
+        ..config(4, Selection::Tournament { size: 1 })
+    };
+    let mut evolver = Evolver::new(copies, operators(), counting).unwrap();
+    let data = data();
+
+    let a = evolver.evaluate("x * x + 1".parse().unwrap(), &data);
+    let b = evolver.evaluate("1 + x * x".parse().unwrap(), &data);
+    assert_eq!(a.fitness, b.fitness);
+    evolver.evaluate("x * x - 1".parse().unwrap(), &data);
+    evolver.evaluate("1 - x * x".parse().unwrap(), &data);
+    assert_eq!(calls.get(), 3);
+    assert_eq!((evolver.cache().hits(), evolver.cache().misses()), (1, 3));
+
+    // The run starts with an empty cache and scores the copies once
+    let result = evolver
+        .run_from(Population::new(vec![a; 4], 0), &data)
+        .unwrap();
+    assert_eq!(result.population.len(), 4);
+    assert_eq!(calls.get(), 4);
+    assert_eq!((evolver.cache().hits(), evolver.cache().misses()), (3, 1));
+}
+
+#[test]
+fn validate_rejects_bad_configs() {
+    let valid = EvolutionConfig::default();
+    assert!(valid.validate().is_ok());
+
+    let schedule = ConstantSchedule {
+        every: 1,
+        elites: 1,
+        optimizer: ConstantOptimizer::new(OptimizationMethod::LevenbergMarquardt),
+    };
+    let invalid = [
+        EvolutionConfig {
+            population_size: 0,
+            elitism: 0,
+            ..valid.clone()
+        },
+        EvolutionConfig {
+            elitism: 501,
+            ..valid.clone()
+        },
+        EvolutionConfig {
+            crossover_rate: 1.2,
+            mutation_rate: 0.0,
+            ..valid.clone()
+        },
+        EvolutionConfig {
+            mutation_rate: -0.1,
+            ..valid.clone()
+        },
+        EvolutionConfig {
+            crossover_rate: 0.6,
+            mutation_rate: 0.5,
+            ..valid.clone()
+        },
+        EvolutionConfig {
+            mutation_weights: MutationWeights {
+                point: -1.0,
+                ..MutationWeights::default()
+            },
+            ..valid.clone()
+        },
+        EvolutionConfig {
+            mutation_weights: MutationWeights {
+                point: 0.0,
+                subtree: 0.0,
+                hoist: 0.0,
+                shrink: 0.0,
+                constant: 0.0,
+            },
+            ..valid.clone()
+        },
+        EvolutionConfig {
+            selection: Selection::Tournament { size: 0 },
+            ..valid.clone()
+        },
+        EvolutionConfig {
+            constant_optimization: Some(ConstantSchedule {
+                every: 0,
+                ..schedule
+            }),
+            ..valid.clone()
+        },
+        EvolutionConfig {
+            constant_optimization: Some(ConstantSchedule {
+                elites: 501,
+                ..schedule
+            }),
+            ..valid.clone()
+        },
+        EvolutionConfig {
+            constant_optimization: Some(ConstantSchedule {
+                optimizer: schedule.optimizer.with_tolerance(f64::NAN),
+                ..schedule
+            }),
+            ..valid.clone()
+        },
+    ];
+    for (index, config) in invalid.into_iter().enumerate() {
+        assert!(config.validate().is_err(), "config {}", index);
+        assert!(
+            Evolver::new(config, operators(), fit_quadratic).is_err(),
+            "config {}",
+            index
+        );
+    }
+
+    // Zero weights are fine as long as nothing mutates
+    let copy_only = EvolutionConfig {
+        mutation_rate: 0.0,
+        mutation_weights: MutationWeights {
+            point: 0.0,
+            subtree: 0.0,
+            hoist: 0.0,
+            shrink: 0.0,
+            constant: 0.0,
+        },
+        ..valid
+    };
+    assert!(copy_only.validate().is_ok());
+}
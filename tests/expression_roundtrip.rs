@@ -0,0 +1,239 @@
+use alpha_encoding_ast::{
+    features::{ArbTypes, CrossOverComponents, MomentumTypes, Operation, MA},
+    gene::{Expression, FunctionData, FunctionNode, Gene, NodeType, TerminalData},
+    traits::GeneticTree,
+};
+use proptest::prelude::*;
+
+fn terminal() -> impl Strategy<Value = Expression> {
+    prop_oneof![
+        any::<f64>().prop_map(TerminalData::Constant),
+        (-10.0..10.0f64).prop_map(TerminalData::Constant),
+        "[a-z_][a-z0-9_]{0,6}".prop_map(TerminalData::Variable),
+        "[a-z][a-z .]{0,6}".prop_map(TerminalData::Variable),
+    ]
+    .prop_map(Expression::Terminal)
+}
+
+// Clip bounds, infinite ones included
+fn bound() -> impl Strategy<Value = f64> {
+    prop_oneof![any::<f64>(), Just(f64::INFINITY), Just(f64::NEG_INFINITY),]
+}
+
+fn expression() -> impl Strategy<Value = Expression> {
+    terminal().prop_recursive(5, 48, 4, |inner| {
+        let node = |function: FunctionData, operands: Vec<Expression>| {
+            Expression::Operation(FunctionNode::new(function, operands))
+        };
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..4)
+                .prop_map(move |ops| node(FunctionData::Add, ops)),
+            prop::collection::vec(inner.clone(), 0..4)
+                .prop_map(move |ops| node(FunctionData::Multiply, ops)),
+            prop::collection::vec(inner.clone(), 2)
+                .prop_map(move |ops| node(FunctionData::Subtract, ops)),
+            prop::collection::vec(inner.clone(), 2)
+                .prop_map(move |ops| node(FunctionData::Divide, ops)),
+            prop::collection::vec(inner.clone(), 2)
+                .prop_map(move |ops| node(FunctionData::Exponent, ops)),
+            inner
+                .clone()
+                .prop_map(move |op| node(FunctionData::Sqrt, vec![op])),
+            (
+                inner.clone(),
+                prop_oneof![0.01..100.0f64, Just(f64::INFINITY)]
+            )
+                .prop_map(move |(op, degree)| node(FunctionData::Root { degree }, vec![op])),
+            (
+                prop::sample::select(vec![
+                    FunctionData::Log,
+                    FunctionData::Exp,
+                    FunctionData::Abs,
+                    FunctionData::Neg,
+                    FunctionData::Sign,
+                    FunctionData::Tanh,
+                    FunctionData::Sigmoid,
+                ]),
+                inner.clone()
+            )
+                .prop_map(move |(function, op)| node(function, vec![op])),
+            (
+                prop::sample::select(vec![
+                    FunctionData::Min,
+                    FunctionData::Max,
+                    FunctionData::GreaterThan,
+                    FunctionData::LessOrEqual,
+                    FunctionData::NotEqual,
+                ]),
+                prop::collection::vec(inner.clone(), 2)
+            )
+                .prop_map(move |(function, ops)| node(function, ops)),
+            (inner.clone(), bound(), bound())
+                .prop_filter("lo <= hi", |(_, lo, hi)| lo <= hi)
+                .prop_map(move |(op, lo, hi)| node(FunctionData::Clip { lo, hi }, vec![op])),
+            prop::collection::vec(inner.clone(), 3)
+                .prop_map(move |ops| node(FunctionData::IfThenElse, ops)),
+            (inner.clone(), 1..30usize)
+                .prop_map(move |(op, window)| node(FunctionData::Delay { window }, vec![op])),
+            (inner.clone(), 2..30usize)
+                .prop_map(move |(op, window)| node(FunctionData::TsStd { window }, vec![op])),
+            (inner.clone(), 1..30usize)
+                .prop_map(move |(op, window)| node(FunctionData::Decay { window }, vec![op])),
+            (prop::collection::vec(inner.clone(), 2), 2..30usize)
+                .prop_map(move |(ops, window)| node(FunctionData::TsCorr { window }, ops)),
+            inner
+                .clone()
+                .prop_map(move |op| node(FunctionData::Rank, vec![op])),
+            (inner, "[a-z][a-z .]{0,6}").prop_map(move |(op, group)| node(
+                FunctionData::IndustryNeutralize { group },
+                vec![op]
+            )),
+        ]
+    })
+}
+
+fn operation() -> impl Strategy<Value = Operation> {
+    prop_oneof![
+        Just(Operation::Custom),
+        Just(Operation::Pairs),
+        (1..50usize).prop_map(|period| Operation::MovingAverage(MA::simple(period))),
+        (1..20usize, 20..50usize).prop_map(|(fast, slow)| Operation::CrossOver(
+            CrossOverComponents::new(MA::exponential(fast), MA::simple(slow))
+        )),
+        (1..30usize)
+            .prop_map(|lookback| Operation::Momentum(MomentumTypes::TimeSeries { lookback })),
+        Just(Operation::Arb(ArbTypes::Funding)),
+    ]
+}
+
+type Annotation = (Operation, Option<usize>, Option<NodeType>);
+
+fn annotation() -> impl Strategy<Value = Annotation> {
+    (
+        operation(),
+        prop::option::of(0..8usize),
+        prop::option::of(prop::sample::select(vec![
+            NodeType::Float,
+            NodeType::Bool,
+            NodeType::Price,
+            NodeType::Volume,
+            NodeType::Ratio,
+        ])),
+    )
+}
+
+// The expression as a gene whose nodes take the annotations in pre-order
+fn annotate(expr: &Expression, annotations: &mut impl Iterator<Item = Annotation>) -> Gene {
+    let (op, context, node_type) = annotations.next().unwrap();
+    let mut gene = match (expr.function_data(), expr.terminal_data()) {
+        (Some(function), _) => {
+            let children = expr
+                .children()
+                .into_iter()
+                .map(|operand| annotate(operand, annotations))
+                .collect();
+            Gene::function(&op, function.clone(), children)
+        }
+        (_, data) => Gene::terminal(&op, data.unwrap().clone()),
+    }
+    .with_context(context);
+    if let Some(node_type) = node_type {
+        gene.set_node_type(node_type);
+    }
+    gene
+}
+
+fn gene() -> impl Strategy<Value = Gene> {
+    (expression(), prop::collection::vec(annotation(), 64))
+        .prop_map(|(expr, annotations)| annotate(&expr, &mut annotations.into_iter().cycle()))
+}
+
+// Debug output spells out every node and prints NaN as `NaN`, so unlike `==` it treats two trees
+// holding NaN constants as equal.
+fn same_tree(a: &Expression, b: &Expression) -> bool {
+    format!("{:?}", a) == format!("{:?}", b)
+}
+
+proptest! {
+    #[test]
+    fn print_parse_print_is_stable(expr in expression()) {
+        let printed = expr.to_string();
+        let reparsed: Expression = printed.parse().unwrap();
+        prop_assert_eq!(reparsed.to_string(), printed);
+    }
+
+    #[test]
+    fn parse_inverts_print(expr in expression()) {
+        let reparsed: Expression = expr.to_string().parse().unwrap();
+        prop_assert!(same_tree(&reparsed, &expr), "{} reparsed as {:?}", expr, reparsed);
+    }
+
+    #[test]
+    fn json_roundtrip_is_lossless(expr in expression()) {
+        let json = serde_json::to_string(&expr).unwrap();
+        let restored: Expression = serde_json::from_str(&json).unwrap();
+        prop_assert!(same_tree(&restored, &expr), "{} restored as {:?}", json, restored);
+    }
+
+    #[test]
+    fn expression_gene_expression_keeps_the_tree(expr in expression()) {
+        let gene = Gene::from(expr.clone());
+        let restored = Expression::from(&gene);
+        prop_assert!(same_tree(&restored, &expr), "{:?} restored as {:?}", expr, restored);
+    }
+
+    #[test]
+    fn gene_expression_gene_keeps_the_annotations(gene in gene()) {
+        let (expr, annotations) = gene.to_annotated();
+        prop_assert_eq!(annotations.len(), gene.node_count());
+        let restored = Gene::from_annotated(Operation::Custom, &expr, &annotations);
+        prop_assert_eq!(format!("{:?}", restored), format!("{:?}", gene));
+    }
+}
+
+#[test]
+fn prints_minimal_parentheses() {
+    let cases = [
+        (
+            "sqrt(close / open) - 2.5 * volume^0.5",
+            "sqrt(close / open) - 2.5 * volume ^ 0.5",
+        ),
+        ("(a + b) + c", "(a + b) + c"),
+        ("a + (b - c)", "a + (b - c)"),
+        ("a - b - c", "a - b - c"),
+        ("a - (b - c)", "a - (b - c)"),
+        ("a / (b * c)", "a / (b * c)"),
+        ("(-2) ^ x ^ y", "(-2) ^ x ^ y"),
+        ("(a ^ b) ^ c", "(a ^ b) ^ c"),
+        ("-x", "-1 * x"),
+        ("root(high - low, 3)", "root(high - low, 3)"),
+        (
+            "ts_corr(close, delay(volume,5), 10)",
+            "ts_corr(close, delay(volume, 5), 10)",
+        ),
+        (
+            "industry_neutralize(rank(delta(close, 5)), `gics sector`)",
+            "industry_neutralize(rank(delta(close, 5)), `gics sector`)",
+        ),
+        ("`close price` + -inf", "`close price` + -inf"),
+        (
+            "if_then_else(gt(close,open), clip(log(volume), -1, 1e20), neg(sign(x)))",
+            "if_then_else(gt(close, open), clip(log(volume), -1, 1e20), neg(sign(x)))",
+        ),
+    ];
+
+    for (input, expected) in cases {
+        let expr: Expression = input.parse().unwrap();
+        assert_eq!(expr.to_string(), expected);
+    }
+}
+
+#[test]
+fn infinite_parameters_survive_json() {
+    for input in ["clip(x, 0, inf)", "clip(x, -inf, inf)", "root(x, inf)"] {
+        let expr: Expression = input.parse().unwrap();
+        let json = serde_json::to_string(&expr).unwrap();
+        let restored: Expression = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.to_string(), input, "{}", json);
+    }
+}
@@ -0,0 +1,114 @@
+use alpha_encoding_ast::{
+    data::types::{Bar, BarDataSet, BarGranularity, NormalizedTypes},
+    features::{
+        CrossOverComponents, DataContext, DataSource, ExecutionContext, ExecutionParameters,
+        FeatureColumns, MomentumTypes, Operation, MA,
+    },
+    gene::Expression,
+    traits::Executable,
+};
+
+const CLOSE: [f64; 8] = [1.0, 2.0, 3.0, 4.0, 5.0, 4.0, 3.0, 2.0];
+const NAN: f64 = f64::NAN;
+
+fn context() -> ExecutionContext {
+    let bars = || {
+        let mut dataset = BarDataSet::new("BTCUSD".to_string(), BarGranularity::OneMinute);
+        for (ts, close) in CLOSE.iter().enumerate() {
+            let ts = ts as i64;
+            dataset
+                .data
+                .insert(ts, Bar::new(ts, *close, *close, *close, *close, 1.0));
+        }
+        NormalizedTypes::Bar(dataset)
+    };
+    let data = DataContext::new(bars(), DataSource::Historical(bars()));
+    ExecutionContext::new(data, ExecutionParameters)
+}
+
+// Equal up to rounding, NaN only where NaN is expected
+fn assert_series(actual: &[f64], expected: &[f64]) {
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected) {
+        let same = (a.is_nan() && e.is_nan()) || (a - e).abs() <= 1e-12;
+        assert!(same, "{:?} vs {:?}", actual, expected);
+    }
+}
+
+#[test]
+fn moving_averages() {
+    let context = context();
+    let sma = Operation::MovingAverage(MA::simple(3));
+    assert_series(
+        &sma.execute(&context).unwrap(),
+        &[NAN, NAN, 2.0, 3.0, 4.0, 13.0 / 3.0, 4.0, 3.0],
+    );
+
+    // Seeded with the mean of the first 3 closes, then weight 1/2
+    let ema = Operation::MovingAverage(MA::exponential(3));
+    assert_series(
+        &ema.execute(&context).unwrap(),
+        &[NAN, NAN, 2.0, 3.0, 4.0, 4.0, 3.5, 2.75],
+    );
+
+    assert!(Operation::MovingAverage(MA::simple(0))
+        .execute(&context)
+        .is_err());
+}
+
+#[test]
+fn crossover_signal() {
+    // ema_2 is 1.5, 2.5, 3.5, 4.5, 4.17, 3.39, 2.46 from the second bar, sma_4 2.5, 3.5, 4, 4, 3.5
+    let crossover =
+        Operation::CrossOver(CrossOverComponents::new(MA::exponential(2), MA::simple(4)));
+    assert_eq!(crossover.variable_name(), "crossover_ema_2_sma_4");
+    assert_series(
+        &crossover.execute(&context()).unwrap(),
+        &[NAN, NAN, NAN, 1.0, 1.0, 1.0, -1.0, -1.0],
+    );
+}
+
+#[test]
+fn momentum_warms_up_over_its_lookback() {
+    let momentum = Operation::Momentum(MomentumTypes::TimeSeries { lookback: 2 });
+    assert_series(
+        &momentum.execute(&context()).unwrap(),
+        &[NAN, NAN, 2.0, 1.0, 2.0 / 3.0, 0.0, -0.4, -0.5],
+    );
+
+    let context = context();
+    assert!(
+        Operation::Momentum(MomentumTypes::TimeSeries { lookback: 0 })
+            .execute(&context)
+            .is_err()
+    );
+    assert!(Operation::Momentum(MomentumTypes::CrossSectional)
+        .execute(&context)
+        .is_err());
+}
+
+#[test]
+fn feature_columns_sit_next_to_the_bars() {
+    let context = context();
+    let operations = [
+        Operation::MovingAverage(MA::simple(3)),
+        Operation::Momentum(MomentumTypes::TimeSeries { lookback: 2 }),
+    ];
+    let features = FeatureColumns::new(&context, &operations).unwrap();
+    assert_eq!(features.feature("sma_3").unwrap()[2], 2.0);
+    assert!(features.feature("ema_3").is_none());
+
+    let expr: Expression = "close - sma_3 + ts_momentum_2".parse().unwrap();
+    let values = expr.evaluate_columns(&features.context().unwrap()).unwrap();
+    assert_series(
+        &values,
+        &[NAN, NAN, 3.0, 2.0, 5.0 / 3.0, -1.0 / 3.0, -1.4, -1.5],
+    );
+
+    // Names have to be unique
+    let twice = [
+        Operation::MovingAverage(MA::simple(3)),
+        Operation::MovingAverage(MA::simple(3)),
+    ];
+    assert!(FeatureColumns::new(&context, &twice).is_err());
+}
@@ -0,0 +1,177 @@
+use alpha_encoding_ast::{
+    gene::{
+        generator::{GenerationMethod, TerminalSpec, TreeGenerator},
+        Arity, Expression, FunctionData,
+    },
+    traits::GeneticTree,
+};
+
+fn generator(min_depth: usize, max_depth: usize) -> TreeGenerator {
+    TreeGenerator::new(
+        vec![
+            TerminalSpec::Variable("close".to_string()),
+            TerminalSpec::Variable("volume".to_string()),
+            TerminalSpec::Constant(2.0),
+            TerminalSpec::EphemeralConstant {
+                min: -1.0,
+                max: 1.0,
+            },
+        ],
+        vec![
+            FunctionData::Add,
+            FunctionData::Multiply,
+            FunctionData::Subtract,
+            FunctionData::Sqrt,
+            FunctionData::Clip { lo: -1.0, hi: 1.0 },
+            FunctionData::IfThenElse,
+            FunctionData::TsMean { window: 5 },
+        ],
+    )
+    .unwrap()
+    .with_depth(min_depth, max_depth)
+    .unwrap()
+    .with_max_variadic_arity(4)
+    .unwrap()
+}
+
+// Depths of the terminals of `tree`
+fn leaf_depths(tree: &Expression) -> Vec<usize> {
+    tree.subtrees()
+        .into_iter()
+        .filter(|(_, node)| node.terminal_data().is_some())
+        .map(|(path, _)| path.len())
+        .collect()
+}
+
+fn assert_arities(tree: &Expression, max_variadic_arity: usize) {
+    for (path, node) in tree.subtrees() {
+        let Some(function) = node.function_data() else {
+            continue;
+        };
+        let operands = node.children().len();
+        match function.arity() {
+            Arity::Exact(count) => assert_eq!(operands, count, "{} at {:?}", tree, path),
+            Arity::Variadic => assert!(
+                (2..=max_variadic_arity).contains(&operands),
+                "{} at {:?}",
+                tree,
+                path
+            ),
+        }
+    }
+}
+
+#[test]
+fn grow_stays_within_depth_bounds() {
+    let generator = generator(2, 5);
+    let mut rng = TreeGenerator::seeded_rng(1);
+    let mut deepest = 0;
+    for _ in 0..300 {
+        let tree: Expression = generator.grow(&(), &mut rng).unwrap();
+        let depth = tree.depth();
+        assert!((2..=5).contains(&depth), "depth {} of {}", depth, tree);
+        assert_arities(&tree, 4);
+        deepest = deepest.max(depth);
+    }
+    assert_eq!(deepest, 5);
+
+    // Requested depths are clamped to min_depth..=max_depth
+    for requested in [0, 3, 9] {
+        let tree: Expression = generator
+            .generate(GenerationMethod::Grow, requested, &(), &mut rng)
+            .unwrap();
+        assert!(tree.depth() >= 2 && tree.depth() <= requested.clamp(2, 5));
+    }
+}
+
+#[test]
+fn full_trees_reach_the_limit_on_every_branch() {
+    let generator = generator(1, 4);
+    let mut rng = TreeGenerator::seeded_rng(2);
+    for _ in 0..100 {
+        let tree: Expression = generator.full(&(), &mut rng).unwrap();
+        assert!(
+            leaf_depths(&tree).iter().all(|depth| *depth == 4),
+            "{}",
+            tree
+        );
+        assert_arities(&tree, 4);
+
+        let tree: Expression = generator
+            .generate(GenerationMethod::Full, 2, &(), &mut rng)
+            .unwrap();
+        assert!(
+            leaf_depths(&tree).iter().all(|depth| *depth == 2),
+            "{}",
+            tree
+        );
+    }
+
+    // A max_depth of 0 only builds terminals
+    let terminals = TreeGenerator::new(vec![TerminalSpec::Constant(1.0)], vec![FunctionData::Add])
+        .unwrap()
+        .with_depth(0, 0)
+        .unwrap();
+    let tree: Expression = terminals.full(&(), &mut rng).unwrap();
+    assert_eq!(tree.depth(), 0);
+}
+
+#[test]
+fn ramped_half_and_half_covers_every_depth() {
+    let generator = generator(2, 5);
+    let mut rng = TreeGenerator::seeded_rng(3);
+    let trees: Vec<Expression> = generator.ramped_half_and_half(40, &(), &mut rng).unwrap();
+    assert_eq!(trees.len(), 40);
+
+    for (index, tree) in trees.iter().enumerate() {
+        let limit = 2 + (index / 2) % 4;
+        if index % 2 == 1 {
+            // Full
+            assert!(
+                leaf_depths(tree).iter().all(|depth| *depth == limit),
+                "{}",
+                tree
+            );
+        } else {
+            assert!((2..=limit).contains(&tree.depth()), "{}", tree);
+        }
+        assert_arities(tree, 4);
+    }
+}
+
+#[test]
+fn seeded_generation_is_reproducible() {
+    let generator = generator(1, 6);
+    let trees = |seed: u64| -> Vec<String> {
+        let mut rng = TreeGenerator::seeded_rng(seed);
+        generator
+            .ramped_half_and_half::<Expression, _>(30, &(), &mut rng)
+            .unwrap()
+            .iter()
+            .map(|tree| tree.to_string())
+            .collect()
+    };
+    assert_eq!(trees(7), trees(7));
+    assert_ne!(trees(7), trees(8));
+}
+
+#[test]
+fn rejects_invalid_settings() {
+    let terminals = || vec![TerminalSpec::Constant(1.0)];
+    assert!(TreeGenerator::new(Vec::new(), vec![FunctionData::Add]).is_err());
+    assert!(TreeGenerator::new(
+        vec![TerminalSpec::EphemeralConstant { min: 1.0, max: 1.0 }],
+        vec![FunctionData::Add]
+    )
+    .is_err());
+    assert!(
+        TreeGenerator::new(terminals(), vec![FunctionData::Clip { lo: 1.0, hi: 0.0 }]).is_err()
+    );
+    assert!(TreeGenerator::new(terminals(), vec![FunctionData::TsStd { window: 1 }]).is_err());
+
+    let generator = TreeGenerator::new(terminals(), vec![FunctionData::Add]).unwrap();
+    assert!(generator.clone().with_depth(3, 2).is_err());
+    assert!(generator.clone().with_max_variadic_arity(1).is_err());
+    // The default min_depth needs functions
+    assert!(TreeGenerator::new(terminals(), Vec::new()).is_err());
+}
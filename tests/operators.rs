@@ -0,0 +1,154 @@
+use alpha_encoding_ast::{
+    gene::{
+        generator::{TerminalSpec, TreeGenerator},
+        operators::GeneticOperators,
+        Expression, FunctionData,
+    },
+    traits::GeneticTree,
+};
+use rand::rngs::StdRng;
+
+fn operators(max_depth: usize, max_size: usize) -> GeneticOperators {
+    let generator = TreeGenerator::new(
+        vec![
+            TerminalSpec::Variable("close".to_string()),
+            TerminalSpec::Variable("open".to_string()),
+            TerminalSpec::EphemeralConstant {
+                min: -5.0,
+                max: 5.0,
+            },
+        ],
+        vec![
+            FunctionData::Add,
+            FunctionData::Multiply,
+            FunctionData::Subtract,
+            FunctionData::Divide,
+            FunctionData::Sqrt,
+            FunctionData::Log,
+            FunctionData::IfThenElse,
+        ],
+    )
+    .unwrap()
+    .with_depth(1, 5)
+    .unwrap()
+    .with_max_variadic_arity(3)
+    .unwrap();
+    GeneticOperators::new(generator).with_limits(max_depth, max_size)
+}
+
+// Random trees the operators admit
+fn parents(operators: &GeneticOperators, count: usize, rng: &mut StdRng) -> Vec<Expression> {
+    let mut parents = Vec::new();
+    while parents.len() < count {
+        let tree: Expression = operators.generator().grow(&(), rng).unwrap();
+        if operators.admits(&tree) {
+            parents.push(tree);
+        }
+    }
+    parents
+}
+
+#[test]
+fn crossover_respects_depth_and_size_limits() {
+    let operators = operators(5, 24);
+    let mut rng = TreeGenerator::seeded_rng(11);
+    let parents = parents(&operators, 60, &mut rng);
+    let mut changed = 0;
+
+    for pair in parents.chunks(2) {
+        for _ in 0..10 {
+            let (first, second) = operators.subtree_crossover(&pair[0], &pair[1], &mut rng);
+            assert!(operators.within_limits(&first), "{}", first);
+            assert!(operators.within_limits(&second), "{}", second);
+            // Nodes are only exchanged
+            assert_eq!(
+                first.node_count() + second.node_count(),
+                pair[0].node_count() + pair[1].node_count()
+            );
+            if first != pair[0] {
+                changed += 1;
+            }
+        }
+    }
+    assert!(changed > 0);
+}
+
+#[test]
+fn mutations_respect_depth_and_size_limits() {
+    let operators = operators(6, 20);
+    let mut rng = TreeGenerator::seeded_rng(12);
+
+    for parent in parents(&operators, 100, &mut rng) {
+        let subtree = operators.subtree_mutation(&parent, &mut rng);
+        assert!(operators.within_limits(&subtree), "{}", subtree);
+
+        let point = operators.point_mutation(&parent, &mut rng);
+        assert_eq!(point.node_count(), parent.node_count());
+        assert_eq!(point.depth(), parent.depth());
+
+        let hoisted = operators.hoist_mutation(&parent, &mut rng);
+        assert!(hoisted.node_count() <= parent.node_count());
+        if parent.depth() > 0 {
+            assert!(hoisted.node_count() < parent.node_count());
+        }
+
+        let shrunk = operators.shrink_mutation(&parent, &mut rng);
+        assert!(shrunk.node_count() <= parent.node_count());
+        assert!(operators.within_limits(&shrunk));
+
+        // At most one constant moves, the shape stays
+        let perturbed = operators.constant_perturbation(&parent, &mut rng);
+        assert_eq!(perturbed.node_count(), parent.node_count());
+        let moved = perturbed
+            .constants()
+            .iter()
+            .zip(parent.constants())
+            .filter(|(a, b)| **a != *b)
+            .count();
+        assert!(moved <= 1);
+    }
+}
+
+#[test]
+fn tight_limits_return_the_parents() {
+    let operators = operators(1, 3);
+    let a: Expression = "close + open".parse().unwrap();
+    let b: Expression = "sqrt(close)".parse().unwrap();
+    let mut rng = TreeGenerator::seeded_rng(13);
+
+    for _ in 0..50 {
+        let (first, second) = operators.subtree_crossover(&a, &b, &mut rng);
+        assert!(operators.within_limits(&first) && operators.within_limits(&second));
+        let mutated = operators.subtree_mutation(&a, &mut rng);
+        assert!(operators.within_limits(&mutated), "{}", mutated);
+    }
+    // Nothing to hoist or shrink in a terminal
+    let terminal: Expression = "close".parse().unwrap();
+    assert_eq!(operators.hoist_mutation(&terminal, &mut rng), terminal);
+    assert_eq!(operators.shrink_mutation(&terminal, &mut rng), terminal);
+    assert_eq!(
+        operators.constant_perturbation(&terminal, &mut rng),
+        terminal
+    );
+}
+
+#[test]
+fn seeded_operators_are_reproducible() {
+    let operators = operators(6, 30);
+    let run = |seed: u64| -> Vec<String> {
+        let mut rng = TreeGenerator::seeded_rng(seed);
+        let parents = parents(&operators, 10, &mut rng);
+        let mut offspring = Vec::new();
+        for pair in parents.chunks(2) {
+            let (first, second) = operators.subtree_crossover(&pair[0], &pair[1], &mut rng);
+            offspring.push(first);
+            offspring.push(second);
+            offspring.push(operators.subtree_mutation(&pair[0], &mut rng));
+            offspring.push(operators.point_mutation(&pair[1], &mut rng));
+            offspring.push(operators.constant_perturbation(&pair[0], &mut rng));
+        }
+        offspring.iter().map(|tree| tree.to_string()).collect()
+    };
+    assert_eq!(run(5), run(5));
+    assert_ne!(run(5), run(6));
+}
@@ -0,0 +1,100 @@
+use alpha_encoding_ast::gene::{ColumnContext, ConstantOptimizer, Expression, OptimizationMethod};
+
+const METHODS: [OptimizationMethod; 2] = [
+    OptimizationMethod::LevenbergMarquardt,
+    OptimizationMethod::NelderMead,
+];
+
+fn xs() -> Vec<f64> {
+    (0..21).map(|i| i as f64 * 0.25).collect()
+}
+
+fn fit(
+    method: OptimizationMethod,
+    input: &str,
+    xs: &[f64],
+    target: impl Fn(f64) -> f64,
+) -> (Vec<f64>, f64) {
+    let expr: Expression = input.parse().unwrap();
+    let mut context = ColumnContext::new(xs.len());
+    context.push_column("x", xs).unwrap();
+    let target: Vec<f64> = xs.iter().map(|x| target(*x)).collect();
+    let optimized = ConstantOptimizer::new(method)
+        .with_max_iterations(2000)
+        .with_tolerance(1e-15)
+        .optimize(&expr, &context, &target)
+        .unwrap();
+    assert!(optimized.loss <= optimized.initial_loss);
+    assert_eq!(optimized.expression.constants(), optimized.constants);
+    (optimized.constants, optimized.loss)
+}
+
+fn assert_close(actual: &[f64], expected: &[f64], tolerance: f64) {
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected) {
+        assert!((a - e).abs() <= tolerance, "{:?} vs {:?}", actual, expected);
+    }
+}
+
+#[test]
+fn recovers_the_constants_of_a_line() {
+    for method in METHODS {
+        let (constants, loss) = fit(method, "0.5 * x + 0.1", &xs(), |x| 3.0 * x - 2.0);
+        assert_close(&constants, &[3.0, -2.0], 1e-6);
+        assert!(loss < 1e-12, "{:?}: {}", method, loss);
+    }
+}
+
+#[test]
+fn recovers_nonlinear_constants() {
+    let (constants, loss) = fit(
+        OptimizationMethod::LevenbergMarquardt,
+        "1 * exp(0.1 * x)",
+        &xs(),
+        |x| 2.0 * (0.3 * x).exp(),
+    );
+    assert_close(&constants, &[2.0, 0.3], 1e-8);
+    assert!(loss < 1e-16);
+
+    let (constants, _) = fit(
+        OptimizationMethod::NelderMead,
+        "1 * exp(0.1 * x)",
+        &xs(),
+        |x| 2.0 * (0.3 * x).exp(),
+    );
+    assert_close(&constants, &[2.0, 0.3], 1e-5);
+}
+
+#[test]
+fn rows_with_non_finite_partials_are_left_out_of_the_step() {
+    // d/dc sqrt(c x) is 0 / 0 at x = 0, the prediction itself is finite
+    let (constants, loss) = fit(
+        OptimizationMethod::LevenbergMarquardt,
+        "sqrt(1 * x)",
+        &xs(),
+        |x| (4.0 * x).sqrt(),
+    );
+    assert_close(&constants, &[4.0], 1e-8);
+    assert!(loss < 1e-16);
+}
+
+#[test]
+fn trees_without_constants_and_bad_input() {
+    let xs = xs();
+    let mut context = ColumnContext::new(xs.len());
+    context.push_column("x", &xs).unwrap();
+    let optimizer = ConstantOptimizer::new(OptimizationMethod::LevenbergMarquardt);
+
+    let expr: Expression = "x * x".parse().unwrap();
+    let optimized = optimizer.optimize(&expr, &context, &xs).unwrap();
+    assert!(optimized.constants.is_empty());
+    assert_eq!(optimized.iterations, 0);
+    assert_eq!(optimized.loss, optimized.initial_loss);
+
+    // The target has to cover every row, unknown variables are reported
+    assert!(optimizer.optimize(&expr, &context, &xs[1..]).is_err());
+    let unknown: Expression = "2 * y".parse().unwrap();
+    assert!(optimizer.optimize(&unknown, &context, &xs).is_err());
+    let nan = vec![f64::NAN; xs.len()];
+    assert!(optimizer.optimize(&expr, &context, &nan).is_err());
+}
@@ -0,0 +1,150 @@
+use alpha_encoding_ast::gene::{ColumnContext, EvalErrorKind, Expression, PanelContext};
+
+// One timestamp, `x` holds one value per symbol
+fn cross_section(expr: &str, values: &[f64], groups: Option<&[&str]>) -> Vec<f64> {
+    let mut context = PanelContext::new(1);
+    for (index, value) in values.iter().enumerate() {
+        let mut columns = ColumnContext::new(1);
+        columns
+            .push_column("x", std::slice::from_ref(value))
+            .unwrap();
+        context
+            .push_symbol(&format!("s{}", index), columns)
+            .unwrap();
+    }
+    if let Some(labels) = groups {
+        let labels = labels.iter().map(|label| label.to_string()).collect();
+        context.push_group("sector", labels).unwrap();
+    }
+    let expr: Expression = expr.parse().unwrap();
+    expr.evaluate_panel(&context)
+        .unwrap()
+        .into_iter()
+        .map(|series| series[0])
+        .collect()
+}
+
+fn assert_values(actual: &[f64], expected: &[f64]) {
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected) {
+        assert!(
+            (a.is_nan() && e.is_nan()) || (a - e).abs() < 1e-12,
+            "{:?} vs {:?}",
+            actual,
+            expected
+        );
+    }
+}
+
+#[test]
+fn rank_shares_ties_and_skips_nan() {
+    let nan = f64::NAN;
+    assert_values(
+        &cross_section("rank(x)", &[3.0, 1.0, 2.0], None),
+        &[1.0, 0.0, 0.5],
+    );
+    // The two 2s share ranks 1 and 2 of 0..=3
+    assert_values(
+        &cross_section("rank(x)", &[2.0, 5.0, 2.0, 1.0], None),
+        &[0.5, 1.0, 0.5, 0.0],
+    );
+    assert_values(
+        &cross_section("rank(x)", &[4.0, 4.0, 4.0], None),
+        &[0.5, 0.5, 0.5],
+    );
+    assert_values(
+        &cross_section("rank(x)", &[nan, 3.0, nan, 1.0], None),
+        &[nan, 1.0, nan, 0.0],
+    );
+    // A lone valid symbol sits in the middle
+    assert_values(&cross_section("rank(x)", &[7.0], None), &[0.5]);
+    assert_values(
+        &cross_section("rank(x)", &[nan, -2.0, nan], None),
+        &[nan, 0.5, nan],
+    );
+    assert_values(&cross_section("rank(x)", &[nan, nan], None), &[nan, nan]);
+}
+
+#[test]
+fn zscore_demean_and_scale_skip_nan() {
+    let nan = f64::NAN;
+    // mean 2, sample deviation 1
+    assert_values(
+        &cross_section("zscore(x)", &[1.0, nan, 2.0, 3.0], None),
+        &[-1.0, nan, 0.0, 1.0],
+    );
+    // No spread: every valid value is at the mean
+    assert_values(&cross_section("zscore(x)", &[7.0], None), &[0.0]);
+    assert_values(
+        &cross_section("zscore(x)", &[5.0, nan, 5.0], None),
+        &[0.0, nan, 0.0],
+    );
+
+    assert_values(
+        &cross_section("demean(x)", &[1.0, nan, 5.0], None),
+        &[-2.0, nan, 2.0],
+    );
+    assert_values(
+        &cross_section("scale(x)", &[1.0, -3.0, nan], None),
+        &[0.25, -0.75, nan],
+    );
+}
+
+#[test]
+fn industry_neutralize_demeans_each_group() {
+    let nan = f64::NAN;
+    let labels = ["tech", "energy", "tech", "energy", "utilities"];
+    assert_values(
+        &cross_section(
+            "industry_neutralize(x, sector)",
+            &[1.0, 10.0, 3.0, 20.0, 4.0],
+            Some(&labels),
+        ),
+        &[-1.0, -5.0, 1.0, 5.0, 0.0],
+    );
+    // NaN members are skipped, a group without valid values stays NaN
+    assert_values(
+        &cross_section(
+            "industry_neutralize(x, sector)",
+            &[1.0, nan, nan, 20.0, nan],
+            Some(&labels),
+        ),
+        &[0.0, nan, nan, 0.0, nan],
+    );
+
+    // Unknown group names are undefined
+    let mut context = PanelContext::new(1);
+    let mut columns = ColumnContext::new(1);
+    columns.push_column("x", &[1.0]).unwrap();
+    context.push_symbol("a", columns).unwrap();
+    let expr: Expression = "industry_neutralize(x, sector)".parse().unwrap();
+    let error = expr.evaluate_panel(&context).unwrap_err();
+    assert_eq!(
+        error.kind,
+        EvalErrorKind::UndefinedVariable("sector".to_string())
+    );
+    assert!(context.push_group("sector", Vec::new()).is_err());
+}
+
+#[test]
+fn cross_sections_run_per_timestamp() {
+    let a = [1.0, 4.0, 2.0];
+    let b = [2.0, 3.0, 2.0];
+    let mut context = PanelContext::new(3);
+    for (symbol, values) in [("a", &a), ("b", &b)] {
+        let mut columns = ColumnContext::new(3);
+        columns.push_column("x", values).unwrap();
+        context.push_symbol(symbol, columns).unwrap();
+    }
+
+    let expr: Expression = "rank(x)".parse().unwrap();
+    let ranks = expr.evaluate_panel(&context).unwrap();
+    assert_values(&ranks[0], &[0.0, 1.0, 0.5]);
+    assert_values(&ranks[1], &[1.0, 0.0, 0.5]);
+
+    // Time-series operands run along each symbol first
+    let expr: Expression = "rank(delta(x, 1))".parse().unwrap();
+    let ranks = expr.evaluate_panel(&context).unwrap();
+    assert_values(&ranks[0], &[f64::NAN, 1.0, 0.0]);
+    assert_values(&ranks[1], &[f64::NAN, 0.0, 1.0]);
+}
@@ -0,0 +1,142 @@
+use alpha_encoding_ast::gene::{
+    parser::parse, Expression, FunctionData, FunctionNode, TerminalData,
+};
+
+fn constant(value: f64) -> Expression {
+    Expression::Terminal(TerminalData::Constant(value))
+}
+
+fn variable(name: &str) -> Expression {
+    Expression::Terminal(TerminalData::Variable(name.to_string()))
+}
+
+fn node(function: FunctionData, operands: Vec<Expression>) -> Expression {
+    Expression::Operation(FunctionNode::new(function, operands))
+}
+
+// (line, column, message) of the error `input` fails with
+fn error(input: &str) -> (usize, usize, String) {
+    let error = parse(input).unwrap_err();
+    (error.line, error.column, error.message)
+}
+
+#[test]
+fn errors_point_at_line_and_column() {
+    let cases = [
+        ("a + ", 1, 5, "expected an expression, found end of input"),
+        ("a +\n  * b", 2, 3, "expected an expression, found `*`"),
+        ("close $ open", 1, 7, "unexpected character `$`"),
+        ("(a + b", 1, 7, "expected `)`, found end of input"),
+        ("a b", 1, 3, "unexpected identifier `b`"),
+        ("\n\n  foo(x)", 3, 3, "unknown function `foo`"),
+        ("sub(a)", 1, 1, "`sub` expects 2 argument(s), found 1"),
+        ("x + `unclosed", 1, 5, "unterminated quoted identifier"),
+        ("``", 1, 1, "empty quoted identifier"),
+        (
+            "ts_mean(x, 0)",
+            1,
+            1,
+            "`ts_mean` window must be an integer literal of at least 1",
+        ),
+        (
+            "a *\n clip(x, 2, 1)",
+            2,
+            2,
+            "`clip` bounds must be number literals with lo <= hi",
+        ),
+    ];
+    for (input, line, column, message) in cases {
+        assert_eq!(
+            error(input),
+            (line, column, message.to_string()),
+            "input {:?}",
+            input
+        );
+    }
+
+    let display = parse("a +\n  * b").unwrap_err().to_string();
+    assert_eq!(
+        display,
+        "expected an expression, found `*` at line 2, column 3"
+    );
+}
+
+#[test]
+fn unary_minus_folds_into_literals_only() {
+    assert_eq!(parse("-2").unwrap(), constant(-2.0));
+    assert_eq!(parse("-inf").unwrap(), constant(f64::NEG_INFINITY));
+    assert_eq!(parse("a - -1.5").unwrap().to_string(), "a - -1.5");
+    assert_eq!(
+        parse("a - -1.5").unwrap(),
+        node(FunctionData::Subtract, vec![variable("a"), constant(-1.5)])
+    );
+
+    let negated = |operand| node(FunctionData::Multiply, vec![constant(-1.0), operand]);
+    assert_eq!(parse("-x").unwrap(), negated(variable("x")));
+    assert_eq!(parse("--2").unwrap(), negated(constant(-2.0)));
+    // The minus applies to the power, not to the base
+    assert_eq!(
+        parse("-2 ^ x").unwrap(),
+        negated(node(
+            FunctionData::Exponent,
+            vec![constant(2.0), variable("x")]
+        ))
+    );
+    assert_eq!(
+        parse("2 ^ -3").unwrap(),
+        node(FunctionData::Exponent, vec![constant(2.0), constant(-3.0)])
+    );
+}
+
+#[test]
+fn backtick_identifiers_are_variables() {
+    assert_eq!(
+        parse("`close price` * `nan`").unwrap(),
+        node(
+            FunctionData::Multiply,
+            vec![variable("close price"), variable("nan")]
+        )
+    );
+    // A quoted name is never a call or a keyword
+    assert_eq!(parse("`inf`").unwrap(), variable("inf"));
+    assert_eq!(parse("nan").unwrap().to_string(), "nan");
+    assert_eq!(error("`sqrt`(x)"), (1, 7, "unexpected `(`".to_string()));
+    assert_eq!(
+        parse("industry_neutralize(x, `gics sector`)").unwrap(),
+        node(
+            FunctionData::IndustryNeutralize {
+                group: "gics sector".to_string()
+            },
+            vec![variable("x")]
+        )
+    );
+}
+
+#[test]
+fn numbers_are_lexed_whole() {
+    assert_eq!(parse("1e3").unwrap(), constant(1000.0));
+    assert_eq!(parse("2.5E-2").unwrap(), constant(0.025));
+    assert_eq!(parse(".5").unwrap(), constant(0.5));
+    assert_eq!(
+        parse("2e+1*x").unwrap(),
+        node(FunctionData::Multiply, vec![constant(20.0), variable("x")])
+    );
+
+    let cases = [
+        ("2ex", 1, 1, "invalid number `2ex`"),
+        ("x + 1e", 1, 5, "invalid number `1e`"),
+        ("1e+", 1, 1, "invalid number `1e`"),
+        ("3e5x", 1, 1, "invalid number `3e5x`"),
+        ("1.2.3", 1, 1, "invalid number `1.2.3`"),
+        ("4_000", 1, 1, "invalid number `4_000`"),
+        ("a *\n  .", 2, 3, "invalid number `.`"),
+    ];
+    for (input, line, column, message) in cases {
+        assert_eq!(
+            error(input),
+            (line, column, message.to_string()),
+            "input {:?}",
+            input
+        );
+    }
+}
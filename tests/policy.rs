@@ -0,0 +1,145 @@
+use alpha_encoding_ast::gene::{
+    ColumnContext, Context, EvalErrorKind, EvalPolicy, Expression, PanelContext, Program,
+};
+
+fn evaluate(input: &str, x: f64, policy: EvalPolicy) -> Result<f64, EvalErrorKind> {
+    let expr: Expression = input.parse().unwrap();
+    let mut context = Context::new().with_eval_policy(policy);
+    context.push_kv("x", x);
+    let value = expr.evaluate(&context).map_err(|error| error.kind);
+
+    // Every evaluator applies the policy the same way
+    let mut columns = ColumnContext::new(1).with_eval_policy(policy);
+    let values = [x];
+    columns.push_column("x", &values).unwrap();
+    let column = expr
+        .evaluate_columns(&columns)
+        .map(|values| values[0])
+        .map_err(|error| error.kind);
+    let program = Program::compile(&expr).unwrap().with_eval_policy(policy);
+    let run = program.run_context(&context).map_err(|error| error.kind);
+    for other in [&column, &run] {
+        assert_eq!(format!("{:?}", other), format!("{:?}", value), "{}", input);
+    }
+    value
+}
+
+// (expression, x, Ieee, Protected, Strict)
+type Case = (&'static str, f64, f64, f64, Result<f64, EvalErrorKind>);
+
+fn check(cases: &[Case]) {
+    for (input, x, ieee, protected, strict) in cases.iter().cloned() {
+        let same = |a: f64, b: f64| a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan());
+        let value = evaluate(input, x, EvalPolicy::Ieee).unwrap();
+        assert!(same(value, ieee), "{} Ieee: {}", input, value);
+        let value = evaluate(input, x, EvalPolicy::Protected).unwrap();
+        assert!(same(value, protected), "{} Protected: {}", input, value);
+        assert_eq!(
+            format!("{:?}", evaluate(input, x, EvalPolicy::Strict)),
+            format!("{:?}", strict),
+            "{} Strict",
+            input
+        );
+    }
+}
+
+#[test]
+fn division_by_zero() {
+    use EvalErrorKind::DivisionByZero;
+    check(&[
+        ("1 / x", 0.0, f64::INFINITY, 1.0, Err(DivisionByZero)),
+        ("-1 / x", 0.0, f64::NEG_INFINITY, 1.0, Err(DivisionByZero)),
+        ("x / x", 0.0, f64::NAN, 1.0, Err(DivisionByZero)),
+        ("1 / x", -0.0, f64::NEG_INFINITY, 1.0, Err(DivisionByZero)),
+        ("1 / x", 4.0, 0.25, 0.25, Ok(0.25)),
+        // A protected quotient is used further up
+        ("2 + 3 / x", 0.0, f64::INFINITY, 3.0, Err(DivisionByZero)),
+    ]);
+}
+
+#[test]
+fn roots_of_negative_numbers() {
+    use EvalErrorKind::NegativeSqrt;
+    check(&[
+        ("sqrt(x)", -4.0, f64::NAN, 2.0, Err(NegativeSqrt)),
+        ("root(x, 4)", -16.0, f64::NAN, 2.0, Err(NegativeSqrt)),
+        ("sqrt(x)", 9.0, 3.0, 3.0, Ok(3.0)),
+        // sqrt(-0) is -0, not a domain error
+        ("sqrt(x)", -0.0, -0.0, 0.0, Ok(-0.0)),
+    ]);
+}
+
+#[test]
+fn logarithms_of_non_positive_numbers() {
+    use EvalErrorKind::NonPositiveLog;
+    check(&[
+        ("log(x)", 0.0, f64::NEG_INFINITY, 0.0, Err(NonPositiveLog)),
+        ("log(x)", -1.0, f64::NAN, 0.0, Err(NonPositiveLog)),
+        (
+            "log(x)",
+            -std::f64::consts::E,
+            f64::NAN,
+            1.0,
+            Err(NonPositiveLog),
+        ),
+        ("log(x)", 1.0, 0.0, 0.0, Ok(0.0)),
+    ]);
+}
+
+#[test]
+fn strict_passes_non_finite_operands_through() {
+    use EvalErrorKind::NonFinite;
+    check(&[
+        ("sqrt(x)", f64::NAN, f64::NAN, f64::NAN, Ok(f64::NAN)),
+        ("1 / x", f64::INFINITY, 0.0, 0.0, Ok(0.0)),
+        (
+            "log(x)",
+            f64::NEG_INFINITY,
+            f64::NAN,
+            f64::INFINITY,
+            Ok(f64::NAN),
+        ),
+        // Overflow from finite operands is an error
+        (
+            "exp(x)",
+            1000.0,
+            f64::INFINITY,
+            f64::INFINITY,
+            Err(NonFinite),
+        ),
+    ]);
+}
+
+#[test]
+fn windows_and_cross_sections_follow_ieee() {
+    let x = [1.0, 2.0, 3.0, 4.0];
+    let zero = [0.0; 4];
+    for policy in [EvalPolicy::Ieee, EvalPolicy::Protected, EvalPolicy::Strict] {
+        let mut columns = ColumnContext::new(4).with_eval_policy(policy);
+        columns.push_column("x", &x).unwrap();
+        columns.push_column("zero", &zero).unwrap();
+
+        // A constant window has no correlation, the warm-up row is NaN
+        let expr: Expression = "ts_corr(x, zero, 2) + 1".parse().unwrap();
+        let values = expr.evaluate_columns(&columns).unwrap();
+        assert!(values.iter().all(|v| v.is_nan()), "{:?}", policy);
+
+        // The pointwise operand of the window still follows the policy
+        let expr: Expression = "ts_mean(x / zero, 2)".parse().unwrap();
+        let values = expr.evaluate_columns(&columns);
+        match policy {
+            EvalPolicy::Ieee => assert_eq!(values.unwrap()[1..], [f64::INFINITY; 3]),
+            EvalPolicy::Protected => assert_eq!(values.unwrap()[1..], [1.0; 3]),
+            EvalPolicy::Strict => {
+                assert_eq!(values.unwrap_err().kind, EvalErrorKind::DivisionByZero)
+            }
+        }
+
+        // One symbol, no spread
+        let mut panel = PanelContext::new(4).with_eval_policy(policy);
+        panel.push_symbol("s", columns).unwrap();
+        let expr: Expression = "scale(zero)".parse().unwrap();
+        let values = expr.evaluate_panel(&panel).unwrap();
+        assert!(values[0].iter().all(|v| v.is_nan()), "{:?}", policy);
+    }
+}
@@ -0,0 +1,195 @@
+use alpha_encoding_ast::gene::{
+    Context, EvalError, EvalPolicy, Expression, FunctionData, FunctionNode, Simplifier,
+    TerminalData,
+};
+use proptest::prelude::*;
+
+// `d` is never bound
+const VARIABLES: [&str; 4] = ["a", "b", "c", "d"];
+const POLICIES: [EvalPolicy; 3] = [EvalPolicy::Ieee, EvalPolicy::Protected, EvalPolicy::Strict];
+
+fn simplified(input: &str, simplifier: Simplifier) -> String {
+    let expr: Expression = input.parse().unwrap();
+    simplifier.simplify(&expr).0.to_string()
+}
+
+fn value() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        4 => -3.0..3.0f64,
+        1 => prop::sample::select(vec![0.0, -0.0, 1.0, -1.0, 2.0, 0.5]),
+        1 => Just(f64::NAN),
+        1 => prop::sample::select(vec![f64::INFINITY, f64::NEG_INFINITY, f64::MAX]),
+    ]
+}
+
+fn terminal() -> impl Strategy<Value = Expression> {
+    prop_oneof![
+        2 => value().prop_map(TerminalData::Constant),
+        1 => prop::sample::select(VARIABLES.to_vec())
+            .prop_map(|name| TerminalData::Variable(name.to_string())),
+    ]
+    .prop_map(Expression::Terminal)
+}
+
+// Mostly pointwise trees, with a few nodes evaluate rejects (time-series, wrong operand counts)
+fn expression() -> impl Strategy<Value = Expression> {
+    terminal().prop_recursive(4, 32, 4, |inner| {
+        let node = |function: FunctionData, operands: Vec<Expression>| {
+            Expression::Operation(FunctionNode::new(function, operands))
+        };
+        prop_oneof![
+            4 => (
+                prop::sample::select(vec![FunctionData::Add, FunctionData::Multiply]),
+                prop::collection::vec(inner.clone(), 0..4)
+            )
+                .prop_map(move |(function, ops)| node(function, ops)),
+            4 => (
+                prop::sample::select(vec![
+                    FunctionData::Subtract,
+                    FunctionData::Divide,
+                    FunctionData::Exponent,
+                    FunctionData::Min,
+                    FunctionData::GreaterThan,
+                    FunctionData::Equal,
+                ]),
+                prop::collection::vec(inner.clone(), 2)
+            )
+                .prop_map(move |(function, ops)| node(function, ops)),
+            3 => (
+                prop::sample::select(vec![
+                    FunctionData::Sqrt,
+                    FunctionData::Root { degree: 1.0 },
+                    FunctionData::Root { degree: 2.0 },
+                    FunctionData::Root { degree: 3.0 },
+                    FunctionData::Log,
+                    FunctionData::Neg,
+                    FunctionData::Abs,
+                ]),
+                inner.clone()
+            )
+                .prop_map(move |(function, op)| node(function, vec![op])),
+            2 => prop::collection::vec(inner.clone(), 3)
+                .prop_map(move |ops| node(FunctionData::IfThenElse, ops)),
+            1 => inner
+                .clone()
+                .prop_map(move |op| node(FunctionData::Delay { window: 1 }, vec![op])),
+            1 => prop::collection::vec(inner, 1..4)
+                .prop_map(move |ops| node(FunctionData::Subtract, ops)),
+        ]
+    })
+}
+
+fn context() -> impl Strategy<Value = Vec<f64>> {
+    prop::collection::vec(value(), 3)
+}
+
+// Bit-equal values, any NaN matches any NaN, errors of the same kind
+fn same_result(a: &Result<f64, EvalError>, b: &Result<f64, EvalError>) -> bool {
+    match (a, b) {
+        (Ok(a), Ok(b)) => a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()),
+        (Err(a), Err(b)) => a.kind == b.kind,
+        _ => false,
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1024))]
+
+    #[test]
+    fn simplification_preserves_values_and_errors(
+        expr in expression(),
+        contexts in prop::collection::vec(context(), 4),
+    ) {
+        for policy in POLICIES {
+            let (simple, _) = Simplifier::new().with_eval_policy(policy).simplify(&expr);
+            for values in &contexts {
+                let mut context = Context::new().with_eval_policy(policy);
+                for (name, value) in VARIABLES.iter().zip(values) {
+                    context.push_kv(name, *value);
+                }
+                let before = expr.evaluate(&context);
+                let after = simple.evaluate(&context);
+                prop_assert!(
+                    same_result(&before, &after),
+                    "{} -> {} under {:?} with {:?}: {:?} vs {:?}",
+                    expr, simple, policy, values, before, after
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn folds_constants_and_identities() {
+    let exact = Simplifier::new();
+    let cases = [
+        ("x * 1", "x"),
+        ("x + -0", "x"),
+        ("x + 0", "x + 0"),
+        ("2 + 3 + x", "5 + x"),
+        ("x + 2 + 3", "x + 2 + 3"),
+        ("x / 1", "x"),
+        ("pow(x, 1)", "x"),
+        ("root(x, 1)", "x"),
+        ("if_then_else(1, x, 2)", "x"),
+        ("sqrt(4) * x", "2 * x"),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(simplified(input, exact), expected, "{}", input);
+    }
+
+    let (_, rewrites) = exact.simplify(&"(1 + 2) * x * 1".parse().unwrap());
+    assert!(rewrites >= 2);
+}
+
+#[test]
+fn keeps_subtrees_that_could_fail() {
+    let exact = Simplifier::new();
+    // An unbound `x` is an error, not NaN or 1
+    for input in [
+        "x - nan",
+        "nan * x",
+        "x ^ 0",
+        "1 ^ x",
+        "if_then_else(1, 2, x)",
+    ] {
+        assert_eq!(simplified(input, exact), input, "{}", input);
+    }
+    assert_eq!(simplified("nan * 2 * x", exact), "nan * x");
+
+    // Taken to be bound to finite values
+    let finite = Simplifier::new().with_assume_finite(true);
+    assert_eq!(simplified("x - nan", finite), "nan");
+    assert_eq!(simplified("pow(x, 0)", finite), "1");
+    assert_eq!(simplified("x - x", finite), "0");
+    assert_eq!(simplified("x * 0", finite), "0");
+
+    // Under Strict a function may still raise a domain error
+    let strict = finite.with_eval_policy(EvalPolicy::Strict);
+    assert_eq!(simplified("x * 0", strict), "0");
+    assert_eq!(simplified("log(x) * 0", strict), "log(x) * 0");
+    assert_eq!(simplified("if_then_else(1, 1 / x, x)", strict), "1 / x");
+    assert_eq!(
+        simplified("if_then_else(0, 1 / x, x)", strict),
+        "if_then_else(0, 1 / x, x)"
+    );
+}
+
+#[test]
+fn inexact_rewrites_need_assume_finite() {
+    let exact = Simplifier::new();
+    let finite = Simplifier::new().with_assume_finite(true);
+
+    // powf(-inf, 0.5) is inf, sqrt(-inf) is NaN
+    assert_eq!(simplified("root(x, 2)", exact), "root(x, 2)");
+    assert_eq!(simplified("root(x, 2)", finite), "sqrt(x)");
+    let root: Expression = "root(x, 2)".parse().unwrap();
+    let mut context = Context::new();
+    context.push_kv("x", f64::NEG_INFINITY);
+    assert_eq!(root.evaluate(&context).unwrap(), f64::INFINITY);
+
+    assert_eq!(simplified("x + (y + z)", exact), "x + (y + z)");
+    assert_eq!(simplified("x + (y + z)", finite), "x + y + z");
+    // sqrt(x) ^ 2 is NaN for x < 0
+    assert_eq!(simplified("sqrt(x) ^ 2", finite), "sqrt(x) ^ 2");
+}
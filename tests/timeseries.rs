@@ -0,0 +1,279 @@
+use alpha_encoding_ast::gene::{
+    ColumnContext, EvalPolicy, Expression, FunctionData, FunctionNode, NanPolicy, TerminalData,
+};
+use proptest::prelude::*;
+
+const WINDOW: usize = 3;
+
+fn node(function: FunctionData, operands: Vec<Expression>) -> Expression {
+    Expression::Operation(FunctionNode::new(function, operands))
+}
+
+fn x() -> Expression {
+    Expression::Terminal(TerminalData::Variable("x".to_string()))
+}
+
+fn time_series(window: usize) -> Vec<FunctionData> {
+    vec![
+        FunctionData::Delay { window },
+        FunctionData::Delta { window },
+        FunctionData::TsMean { window },
+        FunctionData::TsStd { window },
+        FunctionData::TsMin { window },
+        FunctionData::TsMax { window },
+        FunctionData::TsRank { window },
+        FunctionData::TsArgMax { window },
+        FunctionData::Decay { window },
+        FunctionData::TsCorr { window },
+    ]
+}
+
+// f(x) for unary functions, ts_corr(x, -x) for the correlation
+fn applied(function: FunctionData) -> Expression {
+    match function {
+        FunctionData::TsCorr { .. } => {
+            node(function, vec![x(), node(FunctionData::Neg, vec![x()])])
+        }
+        function => node(function, vec![x()]),
+    }
+}
+
+// Every row through evaluate_columns and through evaluate_at, which have to agree
+fn series(expr: &Expression, values: &[f64]) -> Vec<f64> {
+    let mut context = ColumnContext::new(values.len());
+    context.push_column("x", values).unwrap();
+    let columns = expr.evaluate_columns(&context).unwrap();
+    for (row, value) in columns.iter().enumerate() {
+        let at = expr.evaluate_at(&context, row).unwrap();
+        assert!(
+            at.to_bits() == value.to_bits() || (at.is_nan() && value.is_nan()),
+            "{} row {}: {} vs {}",
+            expr,
+            row,
+            at,
+            value
+        );
+    }
+    columns
+}
+
+fn assert_series(expr: &Expression, values: &[f64], expected: &[f64]) {
+    let actual = series(expr, values);
+    assert_eq!(actual.len(), expected.len());
+    for (row, (a, e)) in actual.iter().zip(expected).enumerate() {
+        assert!(
+            (a.is_nan() && e.is_nan()) || (a - e).abs() < 1e-12,
+            "{} row {}: {} vs {}",
+            expr,
+            row,
+            a,
+            e
+        );
+    }
+}
+
+#[test]
+fn window_values_follow_the_definitions() {
+    let values = [1.0, 4.0, 2.0, 8.0, 5.0, 7.0];
+    let nan = f64::NAN;
+    let cases = [
+        (
+            FunctionData::Delay { window: 3 },
+            [nan, nan, nan, 1.0, 4.0, 2.0],
+        ),
+        (
+            FunctionData::Delta { window: 3 },
+            [nan, nan, nan, 7.0, 1.0, 5.0],
+        ),
+        (
+            FunctionData::TsMean { window: 3 },
+            [nan, nan, 7.0 / 3.0, 14.0 / 3.0, 5.0, 20.0 / 3.0],
+        ),
+        (
+            FunctionData::TsStd { window: 3 },
+            [
+                nan,
+                nan,
+                (7.0f64 / 3.0).sqrt(),
+                (28.0f64 / 3.0).sqrt(),
+                3.0,
+                (7.0f64 / 3.0).sqrt(),
+            ],
+        ),
+        (
+            FunctionData::TsMin { window: 3 },
+            [nan, nan, 1.0, 2.0, 2.0, 5.0],
+        ),
+        (
+            FunctionData::TsMax { window: 3 },
+            [nan, nan, 4.0, 8.0, 8.0, 8.0],
+        ),
+        (
+            FunctionData::TsRank { window: 3 },
+            [nan, nan, 0.5, 1.0, 0.5, 0.5],
+        ),
+        (
+            FunctionData::TsArgMax { window: 3 },
+            [nan, nan, 1.0, 0.0, 1.0, 2.0],
+        ),
+        (
+            FunctionData::Decay { window: 3 },
+            [nan, nan, 2.5, 16.0 / 3.0, 5.5, 6.5],
+        ),
+        (
+            FunctionData::TsCorr { window: 3 },
+            [nan, nan, -1.0, -1.0, -1.0, -1.0],
+        ),
+    ];
+    for (function, expected) in cases {
+        assert_series(&applied(function), &values, &expected);
+    }
+
+    // Ties share the average rank, the latest maximum wins
+    let flat = [2.0, 2.0, 2.0, 2.0];
+    assert_series(
+        &applied(FunctionData::TsRank { window: 3 }),
+        &flat,
+        &[f64::NAN, f64::NAN, 0.5, 0.5],
+    );
+    assert_series(
+        &applied(FunctionData::TsArgMax { window: 3 }),
+        &flat,
+        &[f64::NAN, f64::NAN, 0.0, 0.0],
+    );
+}
+
+#[test]
+fn warmup_rows_are_nan() {
+    let values: Vec<f64> = (0..10).map(|i| (i as f64 * 0.7).sin() + i as f64).collect();
+    for function in time_series(WINDOW) {
+        let expr = applied(function.clone());
+        let warmup = function.warmup();
+        assert_eq!(expr.warmup(), warmup);
+        let output = series(&expr, &values);
+        assert!(output[..warmup].iter().all(|v| v.is_nan()), "{}", expr);
+        assert!(output[warmup..].iter().all(|v| v.is_finite()), "{}", expr);
+    }
+
+    // Nested windows add up, a series shorter than the warm-up is all NaN
+    let nested = node(
+        FunctionData::TsMean { window: 4 },
+        vec![applied(FunctionData::Delta { window: 2 })],
+    );
+    assert_eq!(nested.warmup(), 5);
+    let output = series(&nested, &values);
+    assert!(output[..5].iter().all(|v| v.is_nan()));
+    assert!(output[5..].iter().all(|v| v.is_finite()));
+    assert!(series(&nested, &values[..4]).iter().all(|v| v.is_nan()));
+}
+
+#[test]
+fn nan_inside_the_window_gives_nan() {
+    let mut values: Vec<f64> = (0..9)
+        .map(|i| (i as f64 * 1.3).cos() * 3.0 + i as f64)
+        .collect();
+    values[4] = f64::NAN;
+
+    for function in time_series(WINDOW) {
+        let expr = applied(function.clone());
+        // Rows that read row 4, Delay and Delta only read the rows they compare
+        let reads_the_nan = |row: usize| match function {
+            FunctionData::Delay { window } => row == 4 + window,
+            FunctionData::Delta { window } => row == 4 || row == 4 + window,
+            _ => (4..4 + WINDOW).contains(&row),
+        };
+        for (row, value) in series(&expr, &values).iter().enumerate() {
+            if row < function.warmup() {
+                continue;
+            }
+            assert_eq!(value.is_nan(), reads_the_nan(row), "{} row {}", expr, row);
+        }
+    }
+}
+
+fn value() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        6 => -5.0..5.0f64,
+        1 => Just(0.0),
+        1 => Just(f64::NAN),
+        1 => Just(f64::INFINITY),
+    ]
+}
+
+fn expression() -> impl Strategy<Value = Expression> {
+    let terminal = prop_oneof![
+        value().prop_map(TerminalData::Constant),
+        prop::sample::select(vec!["x", "y"]).prop_map(|name| TerminalData::Variable(name.into())),
+    ]
+    .prop_map(Expression::Terminal);
+
+    terminal.prop_recursive(4, 24, 2, |inner| {
+        prop_oneof![
+            (
+                prop::sample::select(vec![
+                    FunctionData::Add,
+                    FunctionData::Subtract,
+                    FunctionData::Divide,
+                    FunctionData::Max,
+                ]),
+                prop::collection::vec(inner.clone(), 2)
+            )
+                .prop_map(|(function, ops)| node(function, ops)),
+            (
+                prop::sample::select(vec![FunctionData::Sqrt, FunctionData::Log]),
+                inner.clone()
+            )
+                .prop_map(|(function, op)| node(function, vec![op])),
+            (1..5usize, inner.clone(), inner).prop_flat_map(|(window, a, b)| {
+                prop::sample::select(time_series(window)).prop_map(move |function| {
+                    match function {
+                        FunctionData::TsCorr { .. } => node(function, vec![a.clone(), b.clone()]),
+                        // Sample deviation needs two values
+                        FunctionData::TsStd { window } => {
+                            node(FunctionData::TsStd { window: window + 1 }, vec![a.clone()])
+                        }
+                        function => node(function, vec![a.clone()]),
+                    }
+                })
+            }),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn evaluate_at_matches_the_columns(
+        expr in expression(),
+        rows in (1..12usize).prop_flat_map(|len| prop::collection::vec((value(), value()), len)),
+        nan_policy in prop_oneof![Just(NanPolicy::Propagate), Just(NanPolicy::Fill(0.5))],
+    ) {
+        let (xs, ys): (Vec<f64>, Vec<f64>) = rows.into_iter().unzip();
+        for eval_policy in [EvalPolicy::Ieee, EvalPolicy::Protected, EvalPolicy::Strict] {
+            let mut context = ColumnContext::new(xs.len())
+                .with_nan_policy(nan_policy)
+                .with_eval_policy(eval_policy);
+            context.push_column("x", &xs).unwrap();
+            context.push_column("y", &ys).unwrap();
+
+            let rows: Vec<_> = (0..xs.len()).map(|row| expr.evaluate_at(&context, row)).collect();
+            match expr.evaluate_columns(&context) {
+                Ok(columns) => {
+                    for (row, (value, at)) in columns.iter().zip(&rows).enumerate() {
+                        prop_assert!(
+                            matches!(at, Ok(at) if at.to_bits() == value.to_bits()
+                                || (at.is_nan() && value.is_nan())),
+                            "{} under {:?} row {}: {} vs {:?}",
+                            expr, eval_policy, row, value, at
+                        );
+                    }
+                }
+                // Rows only fail on values the columns compute as well
+                Err(error) => prop_assert!(
+                    rows.iter().any(Result::is_err),
+                    "{} under {:?}: {:?} but every row evaluates",
+                    expr, eval_policy, error
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use alpha_encoding_ast::{
+    features::Operation,
+    gene::{
+        generator::{GenerationMethod, TerminalSpec, TreeGenerator},
+        operators::GeneticOperators,
+        Expression, FunctionData, Gene, NodeType, TerminalData, TypeEnv, TypeErrorKind,
+    },
+    traits::GeneticTree,
+};
+use rand::rngs::StdRng;
+
+const ROOTS: [NodeType; 5] = [
+    NodeType::Float,
+    NodeType::Bool,
+    NodeType::Price,
+    NodeType::Volume,
+    NodeType::Ratio,
+];
+
+fn parse(input: &str) -> Expression {
+    input.parse().unwrap()
+}
+
+fn generator(root: NodeType, max_variadic_arity: usize) -> TreeGenerator {
+    TreeGenerator::new(
+        vec![
+            TerminalSpec::Variable("close".to_string()),
+            TerminalSpec::Variable("open".to_string()),
+            TerminalSpec::Variable("volume".to_string()),
+            TerminalSpec::EphemeralConstant {
+                min: -2.0,
+                max: 2.0,
+            },
+        ],
+        vec![
+            FunctionData::Add,
+            FunctionData::Multiply,
+            FunctionData::Subtract,
+            FunctionData::Divide,
+            FunctionData::Log,
+            FunctionData::Tanh,
+            FunctionData::GreaterThan,
+            FunctionData::IfThenElse,
+            FunctionData::TsMean { window: 3 },
+        ],
+    )
+    .unwrap()
+    .with_depth(1, 5)
+    .unwrap()
+    .with_max_variadic_arity(max_variadic_arity)
+    .unwrap()
+    .with_types(TypeEnv::for_bars(), root)
+    .unwrap()
+}
+
+// Every node of the gene has a recorded type its operands produce, the root has `root`
+fn assert_recorded_types(gene: &Gene, root: NodeType) {
+    let env = TypeEnv::for_bars();
+    let text = gene.to_expression().to_string();
+    assert!(env.typecheck_as(gene, root).is_ok(), "{} as {}", text, root);
+    assert_eq!(gene.node_type(), Some(root), "{}", text);
+    for (path, node) in gene.subtrees() {
+        let node_type = node
+            .node_type()
+            .unwrap_or_else(|| panic!("{} has no type at {:?}", text, path));
+        match (node.function_data(), node.terminal_data()) {
+            (Some(function), _) => {
+                let operands: Vec<NodeType> = node
+                    .children()
+                    .into_iter()
+                    .map(|child| child.node_type().unwrap())
+                    .collect();
+                let output = function.output_type(&operands);
+                assert_eq!(output, Some(node_type), "{} at {:?}", text, path);
+            }
+            (_, Some(TerminalData::Variable(name))) => {
+                assert_eq!(env.variable_type(name), Some(node_type), "{}", text)
+            }
+            _ => assert!(node_type.is_numeric(), "{} at {:?}", text, path),
+        }
+    }
+}
+
+#[test]
+fn typecheck_reports_mismatches_at_their_node() {
+    let env = TypeEnv::for_bars();
+    assert_eq!(env.typecheck(&parse("close - 1")), Ok(NodeType::Price));
+    assert_eq!(env.typecheck(&parse("close / open")), Ok(NodeType::Ratio));
+    assert_eq!(
+        env.typecheck(&parse("volume * 2 + 1")),
+        Ok(NodeType::Volume)
+    );
+    assert_eq!(
+        env.typecheck(&parse("tanh(close / open)")),
+        Ok(NodeType::Float)
+    );
+    assert_eq!(
+        env.typecheck(&parse("if_then_else(gt(close, open), close, 1)")),
+        Ok(NodeType::Price)
+    );
+
+    let errors = env.typecheck(&parse("close * volume")).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].kind,
+        TypeErrorKind::Mismatch {
+            found: vec![NodeType::Price, NodeType::Volume]
+        }
+    );
+    assert_eq!(errors[0].path, Vec::<usize>::new());
+    assert_eq!(errors[0].operator, Some(FunctionData::Multiply));
+
+    // The condition has to be a bool
+    let errors = env
+        .typecheck(&parse("log(close) + if_then_else(close, 1, 2)"))
+        .unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, vec![1]);
+
+    // Every mismatch is reported
+    let errors = env
+        .typecheck(&parse("(close + volume) * (open - volume) + foo"))
+        .unwrap_err();
+    let paths: Vec<Vec<usize>> = errors.iter().map(|error| error.path.clone()).collect();
+    assert_eq!(paths, vec![vec![0, 0], vec![0, 1], vec![1]]);
+    assert_eq!(
+        errors[2].kind,
+        TypeErrorKind::UnknownVariable("foo".to_string())
+    );
+
+    let errors = env
+        .typecheck_as(&parse("close / open"), NodeType::Price)
+        .unwrap_err();
+    assert_eq!(
+        errors[0].kind,
+        TypeErrorKind::UnexpectedType {
+            expected: NodeType::Price,
+            found: NodeType::Ratio
+        }
+    );
+    assert_eq!(
+        errors[0].to_string(),
+        "expected price, found ratio in `div` at the root"
+    );
+}
+
+#[test]
+fn constants_take_the_type_of_their_position() {
+    let env = TypeEnv::for_bars();
+    let cases = [
+        ("close - 1", NodeType::Price, vec!["price"; 3]),
+        (
+            "close / open + 1",
+            NodeType::Ratio,
+            vec!["ratio", "ratio", "price", "price", "ratio"],
+        ),
+        ("1 + close + 2", NodeType::Price, vec!["price"; 4]),
+        ("2 * 3", NodeType::Volume, vec!["volume", "float", "volume"]),
+        (
+            "if_then_else(gt(volume, 5), 1, close)",
+            NodeType::Price,
+            vec!["price", "bool", "volume", "volume", "price", "price"],
+        ),
+    ];
+    for (input, root, expected) in cases {
+        let types: Vec<&str> = env
+            .resolve(&parse(input), root)
+            .unwrap()
+            .iter()
+            .map(NodeType::name)
+            .collect();
+        assert_eq!(types, expected, "{}", input);
+    }
+
+    // A gene records them
+    let mut gene = Gene::from_expression(Operation::Custom, &parse("close - 1"));
+    assert_eq!(gene.node_type(), None);
+    env.assign(&mut gene, NodeType::Price).unwrap();
+    assert_recorded_types(&gene, NodeType::Price);
+    assert!(env.assign(&mut gene, NodeType::Volume).is_err());
+}
+
+#[test]
+fn generated_trees_have_the_root_type() {
+    let mut rng = TreeGenerator::seeded_rng(21);
+    for root in ROOTS {
+        let generator = generator(root, 4);
+        let genes: Vec<Gene> = generator
+            .ramped_half_and_half(120, &Operation::Custom, &mut rng)
+            .unwrap();
+        for gene in &genes {
+            assert_recorded_types(gene, root);
+        }
+        let gene: Gene = generator.full(&Operation::Custom, &mut rng).unwrap();
+        assert_recorded_types(&gene, root);
+
+        // Expressions carry no types but check the same way
+        let expression = generator
+            .expression(GenerationMethod::Grow, &mut rng)
+            .unwrap();
+        assert!(generator.typechecks(&expression), "{}", expression);
+    }
+}
+
+#[test]
+fn long_variadic_chains_are_typed_per_operand() {
+    // 5^12 operand assignments per node if they were enumerated
+    let generator = generator(NodeType::Price, 12);
+    let mut rng = TreeGenerator::seeded_rng(22);
+    let mut widest = 0;
+    for _ in 0..100 {
+        let gene: Gene = generator.grow(&Operation::Custom, &mut rng).unwrap();
+        assert_recorded_types(&gene, NodeType::Price);
+        for (_, node) in gene.subtrees() {
+            widest = widest.max(node.children().len());
+        }
+    }
+    assert!(widest > 4);
+}
+
+#[test]
+fn roots_that_cannot_be_built_are_rejected() {
+    let generator = || {
+        TreeGenerator::new(
+            vec![TerminalSpec::Variable("close".to_string())],
+            vec![FunctionData::Add, FunctionData::Divide],
+        )
+        .unwrap()
+        .with_depth(0, 3)
+        .unwrap()
+    };
+    assert!(generator()
+        .with_types(TypeEnv::for_bars(), NodeType::Volume)
+        .is_err());
+    // Variables need a type
+    assert!(generator()
+        .with_types(TypeEnv::new(), NodeType::Price)
+        .is_err());
+
+    let generator = generator()
+        .with_types(TypeEnv::for_bars(), NodeType::Price)
+        .unwrap();
+    let mut rng = TreeGenerator::seeded_rng(23);
+    let none: Option<Expression> =
+        generator.generate_typed(GenerationMethod::Grow, 3, NodeType::Volume, &(), &mut rng);
+    assert!(none.is_none());
+    let ratio: Option<Expression> =
+        generator.generate_typed(GenerationMethod::Full, 0, NodeType::Ratio, &(), &mut rng);
+    assert_eq!(
+        TypeEnv::for_bars().typecheck(&ratio.unwrap()),
+        Ok(NodeType::Ratio)
+    );
+}
+
+fn typed_parents(operators: &GeneticOperators, count: usize, rng: &mut StdRng) -> Vec<Gene> {
+    let mut parents = Vec::new();
+    while parents.len() < count {
+        let gene: Gene = operators.generator().grow(&Operation::Custom, rng).unwrap();
+        if operators.admits(&gene) {
+            parents.push(gene);
+        }
+    }
+    parents
+}
+
+#[test]
+fn operators_keep_the_root_type() {
+    let mut rng = TreeGenerator::seeded_rng(24);
+    for root in [NodeType::Price, NodeType::Ratio, NodeType::Bool] {
+        let operators = GeneticOperators::new(generator(root, 3)).with_limits(7, 48);
+        let parents = typed_parents(&operators, 40, &mut rng);
+        let mut changed = 0;
+        let mut check = |gene: &Gene, parent: &Gene| {
+            assert_recorded_types(gene, root);
+            assert!(operators.within_limits(gene));
+            if gene.to_expression() != parent.to_expression() {
+                changed += 1;
+            }
+        };
+
+        for pair in parents.chunks(2) {
+            for _ in 0..5 {
+                let (first, second) = operators.subtree_crossover(&pair[0], &pair[1], &mut rng);
+                check(&first, &pair[0]);
+                check(&second, &pair[1]);
+            }
+        }
+        for parent in &parents {
+            for _ in 0..5 {
+                check(&operators.point_mutation(parent, &mut rng), parent);
+                check(&operators.subtree_mutation(parent, &mut rng), parent);
+                check(&operators.hoist_mutation(parent, &mut rng), parent);
+                check(&operators.shrink_mutation(parent, &mut rng), parent);
+                check(&operators.constant_perturbation(parent, &mut rng), parent);
+            }
+        }
+        assert!(changed > 100, "{} changed under {}", changed, root);
+    }
+}
+
+#[test]
+fn operators_return_parents_that_do_not_typecheck() {
+    let operators = GeneticOperators::new(generator(NodeType::Price, 3));
+    let mut rng = TreeGenerator::seeded_rng(25);
+    let wrong = Gene::from_expression(Operation::Custom, &parse("close * volume + open"));
+    let typed = typed_parents(&operators, 1, &mut rng).remove(0);
+
+    let (first, second) = operators.subtree_crossover(&wrong, &typed, &mut rng);
+    assert_eq!(first.to_expression(), wrong.to_expression());
+    assert_eq!(second.to_expression(), typed.to_expression());
+    for mutated in [
+        operators.subtree_mutation(&wrong, &mut rng),
+        operators.hoist_mutation(&wrong, &mut rng),
+        operators.shrink_mutation(&wrong, &mut rng),
+    ] {
+        assert_eq!(mutated.to_expression(), wrong.to_expression());
+    }
+}